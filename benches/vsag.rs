@@ -0,0 +1,63 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use vsag::advisor::HnswParams;
+use vsag::bench::{bench_build, bench_search, Dataset};
+use vsag::metric::Metric;
+
+const HNSW_PARAMS: &str = r#"{
+    "dtype": "float32",
+    "metric_type": "l2",
+    "dim": 128,
+    "hnsw": {
+        "max_degree": 16,
+        "ef_construction": 100
+    }
+}"#;
+
+fn hnsw(c: &mut Criterion) {
+    let dataset = Dataset::random(10_000, 100, 128, 42);
+    let search_params: Vec<String> = [10, 20, 50, 100, 200]
+        .iter()
+        .map(|ef_search| format!(r#"{{"hnsw": {{"ef_search": {ef_search}}}}}"#))
+        .collect();
+
+    bench_build(c, "hnsw", "hnsw", HNSW_PARAMS, &dataset);
+    bench_search(c, "hnsw", "hnsw", HNSW_PARAMS, &dataset, 10, &search_params);
+}
+
+/// Reproduces the recall of the defaults of [`HnswParams::recommended`].
+fn recommended(c: &mut Criterion) {
+    for dim in [128, 768] {
+        let dataset = Dataset::random(10_000, 100, dim, 42);
+        for metric in [Metric::L2, Metric::Cosine] {
+            let params = HnswParams::recommended(dim, metric);
+            let index_params = params.index_params(dim, metric);
+            let name = format!("recommended/{metric}/{dim}");
+            bench_search(
+                c,
+                &name,
+                "hnsw",
+                &index_params,
+                &dataset,
+                10,
+                &[params.search_params().to_json()],
+            );
+        }
+    }
+}
+
+criterion_group!(benches, hnsw, recommended);
+criterion_main!(benches);
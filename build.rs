@@ -15,11 +15,20 @@
 fn main() {
     println!("cargo:rerun-if-env-changed=VSAG_LIB_PATH");
     println!("cargo:rerun-if-changed=build.rs");
-    println!("cargo:rustc-link-lib=dylib=vsag");
 
-    if let Some(lib_path) = vsag_lib_path() {
-        println!("cargo:rustc-link-search=native={lib_path}",);
+    // `pure-rust` only uses the pure-Rust engines and `mock-ffi` replaces the C API, both
+    // without libvsag.
+    #[cfg(not(any(feature = "pure-rust", feature = "mock-ffi")))]
+    {
+        println!("cargo:rustc-link-lib=dylib=vsag");
+
+        if let Some(lib_path) = vsag_lib_path() {
+            println!("cargo:rustc-link-search=native={lib_path}",);
+        }
     }
+
+    #[cfg(feature = "grpc")]
+    tonic_prost_build::compile_protos("proto/vsag.proto").expect("failed to compile protos");
 }
 
 /// `some-feature` becomes `SOME_FEATURE` options in cmake.
@@ -39,6 +48,7 @@ macro_rules! define_config_based_on_features {
     };
 }
 
+#[cfg(not(any(feature = "pure-rust", feature = "mock-ffi")))]
 fn vsag_lib_path() -> Option<String> {
     #[cfg(feature = "vendored")]
     {
@@ -0,0 +1,116 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// C API of vsag-rs, built with the `capi` feature, see `src/capi.rs`.
+//
+// Fallible functions return 0 on success, or an error code (see VsagRsErrorType) with the
+// error message stored in `*out_error` if `out_error` isn't NULL, to be freed with
+// vsagrs_free_string. Strings are NUL-terminated UTF-8. Returned buffers are owned by the
+// caller and freed with the matching vsagrs_free_* function. Handles must not be used by
+// several threads at once. Panics are reported as VSAGRS_INTERNAL_ERROR.
+
+#ifndef VSAG_RS_H
+#define VSAG_RS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+    VSAGRS_OK = 0,
+    VSAGRS_UNKNOWN_ERROR = 1,
+    VSAGRS_INTERNAL_ERROR,
+    VSAGRS_INVALID_ARGUMENT,
+    VSAGRS_BUILD_TWICE,
+    VSAGRS_INDEX_NOT_EMPTY,
+    VSAGRS_UNSUPPORTED_INDEX,
+    VSAGRS_UNSUPPORTED_INDEX_OPERATION,
+    VSAGRS_DIMENSION_NOT_EQUAL,
+    VSAGRS_INDEX_EMPTY,
+    VSAGRS_NO_ENOUGH_MEMORY,
+    VSAGRS_READ_ERROR,
+    VSAGRS_MISSING_FILE,
+    VSAGRS_INVALID_BINARY,
+    VSAGRS_QUOTA_EXCEEDED,
+    VSAGRS_INDEX_POISONED,
+    VSAGRS_TIMEOUT,
+} VsagRsErrorType;
+
+typedef struct VsagRsIndex VsagRsIndex;
+typedef struct VsagRsMappedIndex VsagRsMappedIndex;
+typedef struct VsagRsCollection VsagRsCollection;
+
+typedef struct {
+    int64_t id;
+    float distance;
+    uint8_t *payload;
+    size_t payload_len;
+} VsagRsHit;
+
+// Index with int64 IDs.
+int vsagrs_index_new(const char *index_type, const char *params, VsagRsIndex **out_index,
+                     char **out_error);
+int vsagrs_index_load(const char *path, const char *index_type, const char *params,
+                      VsagRsIndex **out_index, char **out_error);
+int vsagrs_index_build(VsagRsIndex *index, size_t num_vectors, size_t dim, const int64_t *ids,
+                       const float *vectors, int64_t **out_failed_ids, size_t *out_num_failed,
+                       char **out_error);
+int vsagrs_index_search(VsagRsIndex *index, const float *query_vector, size_t dim, size_t k,
+                        const char *search_params, int64_t **out_ids, float **out_distances,
+                        size_t *out_num_results, char **out_error);
+int vsagrs_index_dump(VsagRsIndex *index, const char *path, char **out_error);
+void vsagrs_index_free(VsagRsIndex *index);
+
+// Index with string keys.
+int vsagrs_mapped_new(const char *index_type, const char *params, VsagRsMappedIndex **out_index,
+                      char **out_error);
+int vsagrs_mapped_build(VsagRsMappedIndex *index, size_t num_vectors, size_t dim,
+                        const char *const *keys, const float *vectors, size_t *out_num_failed,
+                        char **out_error);
+int vsagrs_mapped_search(VsagRsMappedIndex *index, const float *query_vector, size_t dim,
+                         size_t k, const char *search_params, char ***out_keys,
+                         float **out_distances, size_t *out_num_results, char **out_error);
+void vsagrs_mapped_free(VsagRsMappedIndex *index);
+
+// Collection of points with payloads.
+int vsagrs_collection_new(const char *index_type, const char *params, size_t dim, bool normalize,
+                          VsagRsCollection **out_collection, char **out_error);
+int vsagrs_collection_open(const char *dir, VsagRsCollection **out_collection, char **out_error);
+int vsagrs_collection_upsert(VsagRsCollection *collection, int64_t id, const float *vector,
+                             size_t dim, const uint8_t *payload, size_t payload_len,
+                             char **out_error);
+bool vsagrs_collection_delete(VsagRsCollection *collection, int64_t id);
+size_t vsagrs_collection_len(VsagRsCollection *collection);
+int vsagrs_collection_commit(VsagRsCollection *collection, char **out_error);
+int vsagrs_collection_search(VsagRsCollection *collection, const float *query_vector, size_t dim,
+                             size_t k, const char *search_params, VsagRsHit **out_hits,
+                             size_t *out_num_hits, char **out_error);
+int vsagrs_collection_save(VsagRsCollection *collection, const char *dir, char **out_error);
+void vsagrs_collection_free(VsagRsCollection *collection);
+
+void vsagrs_free_ids(int64_t *ids, size_t len);
+void vsagrs_free_distances(float *distances, size_t len);
+void vsagrs_free_keys(char **keys, size_t len);
+void vsagrs_free_hits(VsagRsHit *hits, size_t len);
+void vsagrs_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif  // VSAG_RS_H
@@ -0,0 +1,393 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recommending index parameters from statistics of a sample of the dataset, or from a table
+//! of defaults when no sample is at hand, and estimating the resources an index needs before
+//! building it.
+//!
+//! The recommendations are heuristics meant as a sane starting point; `ef_search` in
+//! particular should then be tuned on the real index with [`crate::eval::tune_ef_search`].
+
+use std::time::Duration;
+
+use crate::error::{Error, ErrorType, Result};
+use crate::exact::exact_knn;
+use crate::metric::{json_u64_field, Metric};
+use crate::params::{HnswSearchParams, SearchParams};
+
+/// Maximum number of sample vectors used to estimate the intrinsic dimensionality.
+const MAX_SAMPLES: usize = 1000;
+/// Number of neighbors used by the intrinsic dimensionality estimator.
+const NUM_NEIGHBORS: usize = 10;
+/// Size of the disk sectors DiskANN lays its nodes out in.
+const SECTOR_SIZE: usize = 4096;
+/// Time of one distance computation per dimension, single-threaded, for build time hints.
+const NANOS_PER_DIM: f64 = 0.5;
+
+/// HNSW defaults by metric and maximum dimension, the first matching row applies.
+///
+/// Aimed at a recall@10 around 0.95, angular metrics (`ip` and `cosine`) needing denser graphs
+/// than `l2`. The `recommended` benchmark of `benches/vsag.rs` reports the recall and QPS they
+/// reach on reproducible datasets, rerun it after changing the table.
+const RECOMMENDED: [(bool, usize, HnswParams); 8] = [
+    (false, 128, HnswParams::new(16, 200, 64)),
+    (false, 512, HnswParams::new(24, 300, 96)),
+    (false, 1024, HnswParams::new(32, 400, 128)),
+    (false, usize::MAX, HnswParams::new(48, 500, 160)),
+    (true, 128, HnswParams::new(24, 200, 96)),
+    (true, 512, HnswParams::new(32, 300, 128)),
+    (true, 1024, HnswParams::new(48, 400, 160)),
+    (true, usize::MAX, HnswParams::new(64, 500, 200)),
+];
+
+/// Build and search parameters of an HNSW index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HnswParams {
+    pub max_degree: usize,
+    pub ef_construction: usize,
+    pub ef_search: usize,
+}
+
+impl HnswParams {
+    const fn new(max_degree: usize, ef_construction: usize, ef_search: usize) -> Self {
+        HnswParams {
+            max_degree,
+            ef_construction,
+            ef_search,
+        }
+    }
+
+    /// Returns the default parameters for vectors of `dim` and `metric`, from a table built
+    /// from benchmarks.
+    ///
+    /// Prefer [`advise_params`] when a sample of the dataset is available, since intrinsic
+    /// dimensionality matters more than `dim`.
+    pub fn recommended(dim: usize, metric: Metric) -> Self {
+        let angular = metric != Metric::L2;
+        RECOMMENDED
+            .iter()
+            .find(|&&(row_angular, max_dim, _)| row_angular == angular && dim <= max_dim)
+            .map(|&(_, _, params)| params)
+            .expect("the table covers all dimensions")
+    }
+
+    /// Renders the index parameters in JSON format for vectors of `dim` and `metric`, see
+    /// [`VsagIndex::new`](crate::VsagIndex::new).
+    pub fn index_params(&self, dim: usize, metric: Metric) -> String {
+        format!(
+            r#"{{"dtype":"float32","metric_type":"{metric}","dim":{dim},"hnsw":{{"max_degree":{},"ef_construction":{}}}}}"#,
+            self.max_degree, self.ef_construction
+        )
+    }
+
+    /// Returns the search parameters.
+    pub fn search_params(&self) -> SearchParams {
+        SearchParams::Hnsw(HnswSearchParams::new(self.ef_search))
+    }
+}
+
+/// Index parameters recommended by [`advise_params`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamAdvice {
+    /// `hnsw`, or `diskann` if an HNSW index wouldn't fit in the memory budget.
+    pub index_type: String,
+    pub max_degree: usize,
+    pub ef_construction: usize,
+    pub ef_search: usize,
+    /// Number of PQ subspaces of DiskANN, `None` for HNSW.
+    pub pq_dims: Option<usize>,
+    /// Estimated intrinsic dimensionality of the dataset.
+    pub intrinsic_dim: f64,
+    /// Estimated memory usage of the index in bytes.
+    pub estimated_memory: usize,
+}
+
+impl ParamAdvice {
+    /// Renders the index parameters in JSON format for vectors of `dim` and `metric`, see
+    /// [`VsagIndex::new`](crate::VsagIndex::new).
+    pub fn index_params(&self, dim: usize, metric: Metric) -> String {
+        match self.pq_dims {
+            Some(pq_dims) => format!(
+                r#"{{"dtype":"float32","metric_type":"{metric}","dim":{dim},"diskann":{{"max_degree":{},"ef_construction":{},"pq_dims":{pq_dims},"pq_sample_rate":0.5}}}}"#,
+                self.max_degree, self.ef_construction
+            ),
+            None => format!(
+                r#"{{"dtype":"float32","metric_type":"{metric}","dim":{dim},"hnsw":{{"max_degree":{},"ef_construction":{}}}}}"#,
+                self.max_degree, self.ef_construction
+            ),
+        }
+    }
+}
+
+/// Recommends index parameters for `num_vectors` vectors of dimension `dim`, given a sample
+/// of them in `sample_vectors`, to reach `target_recall` within `memory_budget` bytes.
+///
+/// The graph degree grows with the intrinsic dimensionality of the sample, estimated by
+/// maximum likelihood from the distances to the nearest neighbors of each sample vector, and
+/// `ef_search` grows with both the intrinsic dimensionality and the target recall.
+pub fn advise_params(
+    sample_vectors: &[f32],
+    dim: usize,
+    num_vectors: usize,
+    target_recall: f64,
+    memory_budget: usize,
+) -> Result<ParamAdvice> {
+    if dim == 0 || !sample_vectors.chunks_exact(dim).remainder().is_empty() {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            format!("length of sample vectors is not a multiple of dim {dim}"),
+        ));
+    }
+
+    let intrinsic_dim = intrinsic_dim(sample_vectors, dim)?;
+    let max_degree = match intrinsic_dim {
+        d if d < 8.0 => 16,
+        d if d < 16.0 => 24,
+        d if d < 32.0 => 32,
+        _ => 48,
+    };
+    let ef_construction = (max_degree * 8).clamp(100, 400);
+    // each "nine" of recall roughly doubles the candidates needed.
+    let nines = -(1.0 - target_recall.clamp(0.0, 0.999)).log10();
+    let ef_search = ((intrinsic_dim.max(1.0) * 4.0) * 2f64.powf(nines)).ceil() as usize;
+    let ef_search = ef_search.clamp(16, 1024);
+
+    let hnsw_memory = hnsw_memory(num_vectors, dim, max_degree);
+    if hnsw_memory <= memory_budget {
+        return Ok(ParamAdvice {
+            index_type: "hnsw".to_string(),
+            max_degree,
+            ef_construction,
+            ef_search,
+            pq_dims: None,
+            intrinsic_dim,
+            estimated_memory: hnsw_memory,
+        });
+    }
+
+    // DiskANN keeps one byte per PQ subspace in memory, and the graph and raw vectors on disk.
+    let pq_dims = (memory_budget / num_vectors.max(1)).clamp(1, dim);
+    Ok(ParamAdvice {
+        index_type: "diskann".to_string(),
+        max_degree,
+        ef_construction,
+        ef_search,
+        pq_dims: Some(pq_dims),
+        intrinsic_dim,
+        estimated_memory: num_vectors * pq_dims,
+    })
+}
+
+/// Resources needed by an index, estimated by [`estimate_resources`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceEstimate {
+    /// Peak memory in bytes, whether while building or serving, excluding the input vectors.
+    pub ram_bytes: usize,
+    /// Size of the dumped index in bytes.
+    pub disk_bytes: usize,
+    /// Rough single-threaded build time, only meant to compare builds by orders of magnitude.
+    pub build_time_hint: Duration,
+}
+
+/// Estimates the resources an index of `index_type` with `params`, see
+/// [`VsagIndex::new`](crate::VsagIndex::new), needs for `num_vectors` vectors.
+///
+/// The estimates follow how vsag lays indexes out: HNSW keeps the vectors and a graph of up
+/// to `2 * max_degree` neighbors per vector in memory, DiskANN keeps PQ codes in memory and
+/// the vectors and graph in 4 KiB sectors on disk, after building the graph in memory.
+pub fn estimate_resources(
+    index_type: &str,
+    params: &str,
+    num_vectors: usize,
+) -> Result<ResourceEstimate> {
+    let field = |key: &str, default: Option<u64>| {
+        json_u64_field(params, key)
+            .or(default)
+            .map(|v| v as usize)
+            .ok_or_else(|| Error::new(ErrorType::InvalidArgument, format!("missing {key}")))
+    };
+    let dim = field("dim", None)?;
+    let max_degree = field("max_degree", None)?;
+    let ef_construction = field("ef_construction", None)?;
+    // each insertion visits about ef_construction candidates and their neighbors.
+    let build_nanos = num_vectors as f64
+        * ef_construction as f64
+        * max_degree as f64
+        * dim as f64
+        * NANOS_PER_DIM;
+
+    match index_type {
+        "hnsw" => {
+            let memory = hnsw_memory(num_vectors, dim, max_degree);
+            Ok(ResourceEstimate {
+                ram_bytes: memory,
+                disk_bytes: memory * 10 / 11,
+                build_time_hint: Duration::from_nanos(build_nanos as u64),
+            })
+        }
+        "diskann" => {
+            let pq_dims = field("pq_dims", Some(dim as u64))?;
+            let pq_memory = num_vectors * (pq_dims + 8) + 256 * dim * 4;
+            let build_memory = num_vectors * (dim * 4 + max_degree * 4 + 8) * 11 / 10;
+
+            let node_size = dim * 4 + 4 + max_degree * 8;
+            let sectors = if node_size <= SECTOR_SIZE {
+                num_vectors.div_ceil(SECTOR_SIZE / node_size)
+            } else {
+                num_vectors * node_size.div_ceil(SECTOR_SIZE)
+            };
+            Ok(ResourceEstimate {
+                ram_bytes: build_memory.max(pq_memory),
+                // plus a metadata sector.
+                disk_bytes: (sectors + 1) * SECTOR_SIZE + pq_memory,
+                // Vamana makes two passes over the vectors.
+                build_time_hint: Duration::from_nanos((build_nanos * 2.0) as u64),
+            })
+        }
+        _ => Err(Error::new(
+            ErrorType::UnsupportedIndex,
+            format!("can't estimate resources of {index_type}"),
+        )),
+    }
+}
+
+/// Estimates the memory of an HNSW index in bytes: level 0 keeps `2 * max_degree` neighbors,
+/// upper levels are negligible, plus 10% of overhead.
+fn hnsw_memory(num_vectors: usize, dim: usize, max_degree: usize) -> usize {
+    num_vectors * (dim * 4 + max_degree * 2 * 4 + 8) * 11 / 10
+}
+
+/// Estimates the intrinsic dimensionality of `vectors` with the MLE of Levina and Bickel,
+/// averaging the inverse of the local estimates as suggested by MacKay and Ghahramani.
+fn intrinsic_dim(vectors: &[f32], dim: usize) -> Result<f64> {
+    let num_vectors = vectors.len() / dim;
+    if num_vectors <= NUM_NEIGHBORS {
+        return Ok(dim as f64);
+    }
+
+    let num_samples = num_vectors.min(MAX_SAMPLES);
+    let stride = num_vectors / num_samples;
+    let ids: Vec<i64> = (0..num_vectors as i64).collect();
+    let queries: Vec<f32> = vectors
+        .chunks_exact(dim)
+        .step_by(stride)
+        .take(num_samples)
+        .flatten()
+        .copied()
+        .collect();
+    // the closest neighbor of each query is itself.
+    let outputs = exact_knn(vectors, &ids, &queries, dim, NUM_NEIGHBORS + 1, Metric::L2)?;
+
+    let mut sum_inverse = 0.0;
+    let mut count = 0;
+    for output in outputs {
+        let distances: Vec<f64> = output.distances[1..]
+            .iter()
+            .map(|&d| (d as f64).sqrt())
+            .collect();
+        let farthest = distances[distances.len() - 1];
+        if distances[0] <= 0.0 {
+            continue;
+        }
+        let sum: f64 = distances[..distances.len() - 1]
+            .iter()
+            .map(|&d| (farthest / d).ln())
+            .sum();
+        sum_inverse += sum / (distances.len() - 1) as f64;
+        count += 1;
+    }
+    if count == 0 || sum_inverse <= 0.0 {
+        return Ok(dim as f64);
+    }
+    Ok((count as f64 / sum_inverse).min(dim as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recommended() {
+        let params = HnswParams::recommended(128, Metric::L2);
+        assert_eq!(params, HnswParams::new(16, 200, 64));
+        assert_eq!(HnswParams::recommended(768, Metric::Cosine).max_degree, 48);
+        assert_eq!(HnswParams::recommended(4096, Metric::Ip).ef_search, 200);
+        assert_eq!(
+            params.index_params(128, Metric::L2),
+            r#"{"dtype":"float32","metric_type":"l2","dim":128,"hnsw":{"max_degree":16,"ef_construction":200}}"#
+        );
+        assert_eq!(params.search_params().ef_search(), 64);
+    }
+
+    #[test]
+    fn test_advise_params() {
+        // points on a line embedded in 8 dimensions.
+        let dim = 8;
+        let samples: Vec<f32> = (0..500)
+            .flat_map(|i| {
+                let t = i as f32 * 0.37 % 100.0;
+                [t, 2.0 * t, 0.0, 0.0, 0.0, 0.0, 0.0, -t]
+            })
+            .collect();
+
+        let advice = advise_params(&samples, dim, 1_000_000, 0.95, usize::MAX).unwrap();
+        assert!(advice.intrinsic_dim < 2.0, "{}", advice.intrinsic_dim);
+        assert_eq!(advice.index_type, "hnsw");
+        assert_eq!(advice.max_degree, 16);
+        assert!(advice
+            .index_params(dim, Metric::L2)
+            .contains(r#""hnsw":{"max_degree":16"#));
+
+        let advice = advise_params(&samples, dim, 1_000_000, 0.95, 4_000_000).unwrap();
+        assert_eq!(advice.index_type, "diskann");
+        assert_eq!(advice.pq_dims, Some(4));
+        assert!(advice.estimated_memory <= 4_000_000);
+
+        assert!(advise_params(&samples[1..], dim, 1, 0.9, 0).is_err());
+    }
+
+    #[test]
+    fn test_estimate_resources() {
+        let advice = ParamAdvice {
+            index_type: "hnsw".to_string(),
+            max_degree: 16,
+            ef_construction: 200,
+            ef_search: 100,
+            pq_dims: None,
+            intrinsic_dim: 10.0,
+            estimated_memory: 0,
+        };
+        let params = advice.index_params(128, Metric::L2);
+        let estimate = estimate_resources("hnsw", &params, 1_000_000).unwrap();
+        assert_eq!(estimate.ram_bytes, 1_000_000 * (512 + 128 + 8) * 11 / 10);
+        assert!(estimate.disk_bytes < estimate.ram_bytes);
+
+        let params = ParamAdvice {
+            index_type: "diskann".to_string(),
+            pq_dims: Some(32),
+            ..advice
+        }
+        .index_params(128, Metric::L2);
+        let diskann = estimate_resources("diskann", &params, 1_000_000).unwrap();
+        // 648 bytes per node, 6 nodes per sector.
+        assert_eq!(
+            diskann.disk_bytes,
+            (166_667 + 1) * 4096 + 1_000_000 * 40 + 256 * 512
+        );
+        assert!(diskann.build_time_hint > estimate.build_time_hint);
+
+        assert!(estimate_resources("ivf", &params, 1).is_err());
+        assert!(estimate_resources("hnsw", r#"{"dim": 128}"#, 1).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Vectors aligned to cache lines, so SIMD kernels load them without penalties.
+
+use std::alloc::{self, Layout};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+/// Alignment of [`AlignedVec`] buffers in bytes, a cache line and an AVX-512 register.
+pub const ALIGNMENT: usize = 64;
+
+/// A growable buffer of `Copy` values whose start is aligned to 64 bytes.
+///
+/// It dereferences to a slice, so it can be passed wherever vectors are accepted, e.g. to
+/// [`VsagIndex::build`](crate::VsagIndex::build) or
+/// [`VsagIndex::knn_search`](crate::VsagIndex::knn_search).
+///
+/// Only the start of the buffer is aligned. When it holds several vectors back to back, as
+/// `build` expects them, each vector is aligned only if its size in bytes is a multiple of
+/// [`ALIGNMENT`], e.g. a dimension that is a multiple of 16 for `f32`. Vectors aren't padded to
+/// keep the others aligned, since vsag reads them without gaps.
+pub struct AlignedVec<T: Copy> {
+    ptr: NonNull<T>,
+    len: usize,
+    cap: usize,
+}
+
+/// The buffer is a plain heap allocation owned by this value.
+unsafe impl<T: Copy + Send> Send for AlignedVec<T> {}
+unsafe impl<T: Copy + Sync> Sync for AlignedVec<T> {}
+
+impl<T: Copy> AlignedVec<T> {
+    /// Creates an empty vector, without allocating.
+    pub fn new() -> Self {
+        AlignedVec {
+            ptr: NonNull::dangling(),
+            len: 0,
+            cap: 0,
+        }
+    }
+
+    /// Creates an empty vector with room for `capacity` values.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut vec = Self::new();
+        vec.reserve(capacity);
+        vec
+    }
+
+    /// Creates a vector of `len` copies of `value`.
+    pub fn from_elem(value: T, len: usize) -> Self {
+        let mut vec = Self::with_capacity(len);
+        for i in 0..len {
+            unsafe { vec.ptr.as_ptr().add(i).write(value) };
+        }
+        vec.len = len;
+        vec
+    }
+
+    /// Returns the number of values the vector can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Reserves room for at least `additional` more values.
+    pub fn reserve(&mut self, additional: usize) {
+        let required = self.len.checked_add(additional).expect("capacity overflow");
+        if required <= self.cap {
+            return;
+        }
+        let new_cap = required
+            .max(self.cap * 2)
+            .max(ALIGNMENT / size_of::<T>().max(1));
+        self.grow(new_cap);
+    }
+
+    /// Appends `value`.
+    pub fn push(&mut self, value: T) {
+        self.reserve(1);
+        unsafe { self.ptr.as_ptr().add(self.len).write(value) };
+        self.len += 1;
+    }
+
+    /// Appends all `values`.
+    pub fn extend_from_slice(&mut self, values: &[T]) {
+        self.reserve(values.len());
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                values.as_ptr(),
+                self.ptr.as_ptr().add(self.len),
+                values.len(),
+            )
+        };
+        self.len += values.len();
+    }
+
+    /// Removes all values, keeping the capacity.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn layout(cap: usize) -> Layout {
+        Layout::array::<T>(cap)
+            .and_then(|layout| layout.align_to(ALIGNMENT))
+            .expect("capacity overflow")
+    }
+
+    fn grow(&mut self, new_cap: usize) {
+        if size_of::<T>() == 0 {
+            self.cap = usize::MAX;
+            return;
+        }
+        let new_layout = Self::layout(new_cap);
+        let ptr = unsafe {
+            if self.cap == 0 {
+                alloc::alloc(new_layout)
+            } else {
+                alloc::realloc(
+                    self.ptr.as_ptr() as *mut u8,
+                    Self::layout(self.cap),
+                    new_layout.size(),
+                )
+            }
+        };
+        self.ptr =
+            NonNull::new(ptr as *mut T).unwrap_or_else(|| alloc::handle_alloc_error(new_layout));
+        self.cap = new_cap;
+    }
+}
+
+impl<T: Copy> Default for AlignedVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy> Drop for AlignedVec<T> {
+    fn drop(&mut self) {
+        if self.cap > 0 && size_of::<T>() > 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr() as *mut u8, Self::layout(self.cap)) };
+        }
+    }
+}
+
+impl<T: Copy> Deref for AlignedVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: Copy> DerefMut for AlignedVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: Copy> Clone for AlignedVec<T> {
+    fn clone(&self) -> Self {
+        Self::from(&self[..])
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for AlignedVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Copy + PartialEq> PartialEq for AlignedVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: Copy> From<&[T]> for AlignedVec<T> {
+    fn from(values: &[T]) -> Self {
+        let mut vec = Self::with_capacity(values.len());
+        vec.extend_from_slice(values);
+        vec
+    }
+}
+
+impl<T: Copy> FromIterator<T> for AlignedVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut vec = Self::with_capacity(iter.size_hint().0);
+        for value in iter {
+            vec.push(value);
+        }
+        vec
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+    use crate::VsagIndex;
+
+    #[test]
+    fn test_aligned_vec() {
+        let mut vectors = AlignedVec::new();
+        for i in 0..100 {
+            vectors.push(i as f32);
+            assert_eq!(vectors.as_ptr() as usize % ALIGNMENT, 0);
+        }
+        vectors.extend_from_slice(&[100.0, 101.0]);
+        assert_eq!(vectors.len(), 102);
+        assert_eq!(vectors[101], 101.0);
+        assert_eq!(vectors.clone(), vectors);
+        assert_eq!(AlignedVec::from_elem(1u8, 3)[..], [1, 1, 1]);
+
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        let ids: AlignedVec<i64> = (0..102).collect();
+        index.build(102, 1, &ids, &vectors).unwrap();
+        let query = AlignedVec::from(&[50.2f32][..]);
+        let output = index
+            .knn_search(&query, 1, r#"{"hnsw": {"ef_search": 100}}"#)
+            .unwrap();
+        assert_eq!(output.ids, vec![50]);
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reusable [criterion] benchmark drivers, enabled by the `bench` feature.
+//!
+//! ```ignore
+//! use criterion::{criterion_group, criterion_main, Criterion};
+//! use vsag::bench::{bench_build, bench_search, Dataset};
+//!
+//! fn benches(c: &mut Criterion) {
+//!     let dataset = Dataset::random(10_000, 100, 128, 42);
+//!     bench_build(c, "hnsw", "hnsw", PARAMS, &dataset);
+//!     bench_search(c, "hnsw", "hnsw", PARAMS, &dataset, 10, &SEARCH_PARAMS);
+//! }
+//!
+//! criterion_group!(group, benches);
+//! criterion_main!(group);
+//! ```
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+
+use crate::error::Result;
+use crate::eval::evaluate;
+use crate::exact::exact_knn;
+use crate::metric::Metric;
+use crate::VsagIndex;
+
+/// Vectors and queries of a benchmark.
+pub struct Dataset {
+    /// Dimension of the vectors.
+    pub dim: usize,
+    /// IDs of the vectors.
+    pub ids: Vec<i64>,
+    /// Vectors to index, in a single slice.
+    pub vectors: Vec<f32>,
+    /// Query vectors, in a single slice.
+    pub queries: Vec<f32>,
+}
+
+impl Dataset {
+    /// Creates a dataset of vectors uniformly distributed in [0, 1), generated from `seed`.
+    pub fn random(num_vectors: usize, num_queries: usize, dim: usize, seed: u64) -> Self {
+        // xorshift64*, good enough for benchmark data and keeps datasets reproducible.
+        let mut state = seed.max(1);
+        let mut next = move || {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            (state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40) as f32 / (1u64 << 24) as f32
+        };
+
+        Dataset {
+            dim,
+            ids: (0..num_vectors as i64).collect(),
+            vectors: (0..num_vectors * dim).map(|_| next()).collect(),
+            queries: (0..num_queries * dim).map(|_| next()).collect(),
+        }
+    }
+
+    /// Returns the number of vectors.
+    pub fn num_vectors(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns the number of queries.
+    pub fn num_queries(&self) -> usize {
+        self.queries.len() / self.dim.max(1)
+    }
+
+    /// Computes the exact `k` nearest neighbors of each query.
+    pub fn ground_truth(&self, k: usize, metric: Metric) -> Result<Vec<Vec<i64>>> {
+        Ok(
+            exact_knn(&self.vectors, &self.ids, &self.queries, self.dim, k, metric)?
+                .into_iter()
+                .map(|output| output.ids)
+                .collect(),
+        )
+    }
+
+    /// Creates an index and builds it with all vectors.
+    pub fn build_index(&self, index_type: &str, params: &str) -> Result<VsagIndex> {
+        let index = VsagIndex::new(index_type, params)?;
+        index.build(self.num_vectors(), self.dim, &self.ids, &self.vectors)?;
+        Ok(index)
+    }
+}
+
+/// Benchmarks building an index of `index_type` with `params` over the whole dataset.
+///
+/// Throughput is reported in vectors per second.
+pub fn bench_build(
+    c: &mut Criterion,
+    name: &str,
+    index_type: &str,
+    params: &str,
+    dataset: &Dataset,
+) {
+    let mut group = c.benchmark_group(format!("{name}/build"));
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(dataset.num_vectors() as u64));
+    group.bench_function(BenchmarkId::from_parameter(dataset.num_vectors()), |b| {
+        b.iter(|| {
+            dataset
+                .build_index(index_type, params)
+                .expect("failed to build index")
+        })
+    });
+    group.finish();
+}
+
+/// Benchmarks searching all queries of the dataset with each of `search_params`.
+///
+/// The recall@k of each search params is measured first and made part of the benchmark ID, so
+/// the report reads as a QPS vs recall curve. Throughput is reported in queries per second.
+pub fn bench_search<S: AsRef<str>>(
+    c: &mut Criterion,
+    name: &str,
+    index_type: &str,
+    params: &str,
+    dataset: &Dataset,
+    k: usize,
+    search_params: &[S],
+) {
+    let index = dataset
+        .build_index(index_type, params)
+        .expect("failed to build index");
+    let metric = index.metric().unwrap_or(Metric::L2);
+    let ground_truth = dataset
+        .ground_truth(k, metric)
+        .expect("failed to compute ground truth");
+    let report = evaluate(
+        &index,
+        &dataset.queries,
+        dataset.dim,
+        &ground_truth,
+        k,
+        search_params,
+    )
+    .expect("failed to evaluate index");
+
+    let mut group = c.benchmark_group(format!("{name}/search"));
+    group.throughput(Throughput::Elements(dataset.num_queries() as u64));
+    for result in &report.results {
+        let id = format!("{} recall={:.4}", result.search_params, result.recall);
+        group.bench_function(BenchmarkId::from_parameter(id), |b| {
+            b.iter(|| {
+                for query in dataset.queries.chunks_exact(dataset.dim) {
+                    index
+                        .knn_search_ref(query, k, &result.search_params)
+                        .expect("failed to search");
+                }
+            })
+        });
+    }
+    group.finish();
+}
@@ -0,0 +1,752 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A C API over the safe layer, declared in `include/vsag_rs.h`, for consuming [`VsagIndex`],
+//! [`MappedIndex`] and [`Collection`] from other languages.
+//!
+//! Build it as a shared library with:
+//!
+//! ```sh
+//! cargo rustc --release --features capi --crate-type cdylib
+//! ```
+//!
+//! Conventions of all functions:
+//! - Fallible functions return 0 on success, or the [`ErrorType`] code of the error, in which
+//!   case the error message is stored in `*out_error` if `out_error` isn't null, to be freed
+//!   with [`vsagrs_free_string`].
+//! - Strings are NUL-terminated UTF-8.
+//! - Buffers returned through `out_*` pointers are owned by the caller and freed with the
+//!   matching `vsagrs_free_*` function, handles with the matching `vsagrs_*_free` function.
+//! - Handles aren't thread-safe: a handle must not be used by several threads at once.
+//! - Panics don't unwind into the caller: fallible functions report them as
+//!   [`ErrorType::InternalError`], others return their default value.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::collection::{Collection, CollectionConfig};
+use crate::error::{Error, ErrorType, Result};
+use crate::mapped::MappedIndex;
+use crate::{IndexOptions, VsagIndex};
+
+/// A search result of [`vsagrs_collection_search`].
+#[repr(C)]
+pub struct VsagRsHit {
+    pub id: i64,
+    pub distance: f32,
+    pub payload: *mut u8,
+    pub payload_len: usize,
+}
+
+/// Returns 0 if `result` is ok, otherwise the code of the error, storing its message in
+/// `out_error`.
+unsafe fn status(result: Result<()>, out_error: *mut *mut c_char) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            if !out_error.is_null() {
+                *out_error = into_c_string(err.message);
+            }
+            err.error_type as c_int
+        }
+    }
+}
+
+/// Runs `f`, turning a panic into an error, since unwinding into C is undefined behavior.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        Err(Error::new(
+            ErrorType::InternalError,
+            format!("panicked: {message}"),
+        ))
+    })
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    let s = CString::new(s.replace('\0', "")).expect("0 byte in string");
+    s.into_raw()
+}
+
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str> {
+    if s.is_null() {
+        return Err(null_arg(name));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| Error::new(ErrorType::InvalidArgument, format!("{name} is not UTF-8")))
+}
+
+unsafe fn slice_arg<'a, T>(data: *const T, len: usize, name: &str) -> Result<&'a [T]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if data.is_null() {
+        return Err(null_arg(name));
+    }
+    if len > isize::MAX as usize / size_of::<T>().max(1) {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            format!("{name} has too many elements"),
+        ));
+    }
+    Ok(std::slice::from_raw_parts(data, len))
+}
+
+unsafe fn handle_arg<'a, T>(handle: *mut T) -> Result<&'a mut T> {
+    handle.as_mut().ok_or_else(|| null_arg("handle"))
+}
+
+/// Returns the length of a buffer of `num_vectors` vectors of `dim`.
+fn buffer_len(num_vectors: usize, dim: usize) -> Result<usize> {
+    num_vectors.checked_mul(dim).ok_or_else(|| {
+        Error::new(
+            ErrorType::InvalidArgument,
+            format!("{num_vectors} vectors of dim {dim} overflow"),
+        )
+    })
+}
+
+fn null_arg(name: &str) -> Error {
+    Error::new(ErrorType::InvalidArgument, format!("{name} is null"))
+}
+
+fn into_c_buffer<T>(vec: Vec<T>) -> *mut T {
+    Box::into_raw(vec.into_boxed_slice()) as *mut T
+}
+
+/// Frees a handle created by `Box::into_raw`, null is ignored.
+unsafe fn free_handle<T>(handle: *mut T) {
+    if !handle.is_null() {
+        // dropping frees vsag resources, which must not unwind into C either.
+        let _ = catch_panic(|| {
+            drop(Box::from_raw(handle));
+            Ok(())
+        });
+    }
+}
+
+unsafe fn free_c_buffer<T>(data: *mut T, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Creates an index, see [`VsagIndex::new`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_index_new(
+    index_type: *const c_char,
+    params: *const c_char,
+    out_index: *mut *mut VsagIndex,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let index = VsagIndex::new(
+            str_arg(index_type, "index_type")?,
+            str_arg(params, "params")?,
+        )?;
+        *out_index = Box::into_raw(Box::new(index));
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Loads an index, see [`VsagIndex::load`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_index_load(
+    path: *const c_char,
+    index_type: *const c_char,
+    params: *const c_char,
+    out_index: *mut *mut VsagIndex,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let index = VsagIndex::load(
+            str_arg(path, "path")?,
+            str_arg(index_type, "index_type")?,
+            str_arg(params, "params")?,
+        )?;
+        *out_index = Box::into_raw(Box::new(index));
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Builds an index, see [`VsagIndex::build`]. The IDs of the vectors that failed to be added
+/// are freed with [`vsagrs_free_ids`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_index_build(
+    index: *mut VsagIndex,
+    num_vectors: usize,
+    dim: usize,
+    ids: *const i64,
+    vectors: *const f32,
+    out_failed_ids: *mut *mut i64,
+    out_num_failed: *mut usize,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let index = handle_arg(index)?;
+        let ids = slice_arg(ids, num_vectors, "ids")?;
+        let vectors = slice_arg(vectors, buffer_len(num_vectors, dim)?, "vectors")?;
+        let failed_ids = index.build(num_vectors, dim, ids, vectors)?;
+        *out_num_failed = failed_ids.len();
+        *out_failed_ids = into_c_buffer(failed_ids);
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Searches an index, see [`VsagIndex::knn_search`]. The IDs are freed with
+/// [`vsagrs_free_ids`] and the distances with [`vsagrs_free_distances`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_index_search(
+    index: *mut VsagIndex,
+    query_vector: *const f32,
+    dim: usize,
+    k: usize,
+    search_params: *const c_char,
+    out_ids: *mut *mut i64,
+    out_distances: *mut *mut f32,
+    out_num_results: *mut usize,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let index = handle_arg(index)?;
+        let query_vector = slice_arg(query_vector, dim, "query_vector")?;
+        let output = index.knn_search(query_vector, k, str_arg(search_params, "search_params")?)?;
+        *out_num_results = output.ids.len();
+        *out_ids = into_c_buffer(output.ids);
+        *out_distances = into_c_buffer(output.distances);
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Dumps an index, see [`VsagIndex::dump`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_index_dump(
+    index: *mut VsagIndex,
+    path: *const c_char,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| handle_arg(index)?.dump(str_arg(path, "path")?));
+    status(result, out_error)
+}
+
+/// Frees an index, null is ignored.
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_index_free(index: *mut VsagIndex) {
+    free_handle(index);
+}
+
+/// Creates an index mapping string keys to vectors, see [`MappedIndex::new`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_mapped_new(
+    index_type: *const c_char,
+    params: *const c_char,
+    out_index: *mut *mut MappedIndex<String>,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let index = MappedIndex::new(
+            str_arg(index_type, "index_type")?,
+            str_arg(params, "params")?,
+        )?;
+        *out_index = Box::into_raw(Box::new(index));
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Builds a mapped index from `num_vectors` keys and vectors, see [`MappedIndex::build`].
+/// Returns the number of vectors that failed to be added in `out_num_failed`.
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_mapped_build(
+    index: *mut MappedIndex<String>,
+    num_vectors: usize,
+    dim: usize,
+    keys: *const *const c_char,
+    vectors: *const f32,
+    out_num_failed: *mut usize,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let index = handle_arg(index)?;
+        let keys = slice_arg(keys, num_vectors, "keys")?
+            .iter()
+            .map(|&key| str_arg(key, "key").map(str::to_string))
+            .collect::<Result<Vec<_>>>()?;
+        let vectors = slice_arg(vectors, buffer_len(num_vectors, dim)?, "vectors")?;
+        *out_num_failed = index.build(dim, &keys, vectors)?.len();
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Searches a mapped index, see [`MappedIndex::knn_search`]. The keys are freed with
+/// [`vsagrs_free_keys`] and the distances with [`vsagrs_free_distances`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_mapped_search(
+    index: *mut MappedIndex<String>,
+    query_vector: *const f32,
+    dim: usize,
+    k: usize,
+    search_params: *const c_char,
+    out_keys: *mut *mut *mut c_char,
+    out_distances: *mut *mut f32,
+    out_num_results: *mut usize,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let index = handle_arg(index)?;
+        let query_vector = slice_arg(query_vector, dim, "query_vector")?;
+        let output = index.knn_search(query_vector, k, str_arg(search_params, "search_params")?)?;
+        *out_num_results = output.keys.len();
+        *out_keys = into_c_buffer(output.keys.into_iter().map(into_c_string).collect());
+        *out_distances = into_c_buffer(output.distances);
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Frees a mapped index, null is ignored.
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_mapped_free(index: *mut MappedIndex<String>) {
+    free_handle(index);
+}
+
+/// Creates an empty collection of vectors of `dim`, see [`Collection::new`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_collection_new(
+    index_type: *const c_char,
+    params: *const c_char,
+    dim: usize,
+    normalize: bool,
+    out_collection: *mut *mut Collection,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let config = CollectionConfig {
+            index_type: str_arg(index_type, "index_type")?.to_string(),
+            params: str_arg(params, "params")?.to_string(),
+            dim,
+            options: IndexOptions {
+                normalize,
+                ..Default::default()
+            },
+        };
+        *out_collection = Box::into_raw(Box::new(Collection::new(config)));
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Opens a collection, see [`Collection::open`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_collection_open(
+    dir: *const c_char,
+    out_collection: *mut *mut Collection,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let collection = Collection::open(str_arg(dir, "dir")?)?;
+        *out_collection = Box::into_raw(Box::new(collection));
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Inserts or replaces a point, see [`Collection::upsert`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_collection_upsert(
+    collection: *mut Collection,
+    id: i64,
+    vector: *const f32,
+    dim: usize,
+    payload: *const u8,
+    payload_len: usize,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let collection = handle_arg(collection)?;
+        let vector = slice_arg(vector, dim, "vector")?.to_vec();
+        let payload = slice_arg(payload, payload_len, "payload")?.to_vec();
+        collection.upsert(id, vector, payload)
+    });
+    status(result, out_error)
+}
+
+/// Deletes a point, see [`Collection::delete`]. Returns whether it existed.
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_collection_delete(collection: *mut Collection, id: i64) -> bool {
+    catch_panic(|| Ok(handle_arg(collection)?.delete(id).is_some())).unwrap_or(false)
+}
+
+/// Returns the number of points of a collection, see [`Collection::len`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_collection_len(collection: *mut Collection) -> usize {
+    catch_panic(|| Ok(handle_arg(collection)?.len())).unwrap_or(0)
+}
+
+/// Makes the changes of a collection searchable, see [`Collection::commit`]. Points that fail
+/// to be indexed are deleted.
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_collection_commit(
+    collection: *mut Collection,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| handle_arg(collection)?.commit().map(drop));
+    status(result, out_error)
+}
+
+/// Searches a collection, see [`Collection::search`]. The hits are freed with
+/// [`vsagrs_free_hits`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_collection_search(
+    collection: *mut Collection,
+    query_vector: *const f32,
+    dim: usize,
+    k: usize,
+    search_params: *const c_char,
+    out_hits: *mut *mut VsagRsHit,
+    out_num_hits: *mut usize,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| {
+        let collection = handle_arg(collection)?;
+        let query_vector = slice_arg(query_vector, dim, "query_vector")?;
+        let hits = collection.search(query_vector, k, str_arg(search_params, "search_params")?)?;
+        let hits: Vec<VsagRsHit> = hits
+            .into_iter()
+            .map(|hit| VsagRsHit {
+                id: hit.id,
+                distance: hit.distance,
+                payload_len: hit.payload.len(),
+                payload: into_c_buffer(hit.payload),
+            })
+            .collect();
+        *out_num_hits = hits.len();
+        *out_hits = into_c_buffer(hits);
+        Ok(())
+    });
+    status(result, out_error)
+}
+
+/// Saves a collection, see [`Collection::save`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_collection_save(
+    collection: *mut Collection,
+    dir: *const c_char,
+    out_error: *mut *mut c_char,
+) -> c_int {
+    let result = catch_panic(|| handle_arg(collection)?.save(str_arg(dir, "dir")?));
+    status(result, out_error)
+}
+
+/// Frees a collection, null is ignored.
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_collection_free(collection: *mut Collection) {
+    free_handle(collection);
+}
+
+/// Frees IDs returned by this API.
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_free_ids(ids: *mut i64, len: usize) {
+    free_c_buffer(ids, len);
+}
+
+/// Frees distances returned by this API.
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_free_distances(distances: *mut f32, len: usize) {
+    free_c_buffer(distances, len);
+}
+
+/// Frees keys returned by [`vsagrs_mapped_search`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_free_keys(keys: *mut *mut c_char, len: usize) {
+    if keys.is_null() {
+        return;
+    }
+    for &key in std::slice::from_raw_parts(keys, len) {
+        vsagrs_free_string(key);
+    }
+    free_c_buffer(keys, len);
+}
+
+/// Frees hits returned by [`vsagrs_collection_search`].
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_free_hits(hits: *mut VsagRsHit, len: usize) {
+    if hits.is_null() {
+        return;
+    }
+    for hit in std::slice::from_raw_parts(hits, len) {
+        free_c_buffer(hit.payload, hit.payload_len);
+    }
+    free_c_buffer(hits, len);
+}
+
+/// Frees a string returned by this API, null is ignored.
+///
+/// # Safety
+///
+/// See the [module](self) conventions.
+#[no_mangle]
+pub unsafe extern "C" fn vsagrs_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_collection_api() {
+        let con_params = c"{
+            \"dtype\": \"float32\",
+            \"metric_type\": \"l2\",
+            \"dim\": 2,
+            \"hnsw\": {
+                \"max_degree\": 16,
+                \"ef_construction\": 100
+            }
+        }";
+        let search_params = c"{\"hnsw\": {\"ef_search\": 10}}";
+
+        unsafe {
+            let mut collection = ptr::null_mut();
+            let mut error = ptr::null_mut();
+            assert_eq!(
+                vsagrs_collection_new(
+                    c"hnsw".as_ptr(),
+                    con_params.as_ptr(),
+                    2,
+                    false,
+                    &mut collection,
+                    &mut error,
+                ),
+                0
+            );
+            for id in 0..10 {
+                let vector = [id as f32, 0.0];
+                let payload = [id as u8];
+                let status = vsagrs_collection_upsert(
+                    collection,
+                    id,
+                    vector.as_ptr(),
+                    2,
+                    payload.as_ptr(),
+                    1,
+                    &mut error,
+                );
+                assert_eq!(status, 0);
+            }
+            assert_eq!(vsagrs_collection_commit(collection, &mut error), 0);
+            assert!(vsagrs_collection_delete(collection, 3));
+            assert_eq!(vsagrs_collection_len(collection), 9);
+
+            let mut hits = ptr::null_mut();
+            let mut num_hits = 0;
+            let query = [3.2f32, 0.0];
+            let status = vsagrs_collection_search(
+                collection,
+                query.as_ptr(),
+                2,
+                2,
+                search_params.as_ptr(),
+                &mut hits,
+                &mut num_hits,
+                &mut error,
+            );
+            assert_eq!(status, 0);
+            // 3 has been deleted since the commit.
+            assert_eq!(num_hits, 1);
+            let hit = &*hits;
+            assert_eq!(hit.id, 4);
+            assert_eq!(
+                std::slice::from_raw_parts(hit.payload, hit.payload_len),
+                &[4]
+            );
+            vsagrs_free_hits(hits, num_hits);
+
+            let status = vsagrs_collection_search(
+                collection,
+                ptr::null(),
+                2,
+                2,
+                search_params.as_ptr(),
+                &mut hits,
+                &mut num_hits,
+                &mut error,
+            );
+            assert_eq!(status, ErrorType::InvalidArgument as c_int);
+            assert_eq!(CStr::from_ptr(error).to_str(), Ok("query_vector is null"));
+            vsagrs_free_string(error);
+
+            vsagrs_collection_free(collection);
+        }
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_index_build_overflow() {
+        unsafe {
+            let mut index = ptr::null_mut();
+            let mut error = ptr::null_mut();
+            let params = c"{
+                \"dtype\": \"float32\",
+                \"metric_type\": \"l2\",
+                \"dim\": 2,
+                \"hnsw\": {\"max_degree\": 16, \"ef_construction\": 100}
+            }";
+            assert_eq!(
+                vsagrs_index_new(c"hnsw".as_ptr(), params.as_ptr(), &mut index, &mut error),
+                0
+            );
+            let ids = [0i64];
+            let vectors = [0.0f32; 2];
+            let (mut failed_ids, mut num_failed) = (ptr::null_mut(), 0);
+            let status = vsagrs_index_build(
+                index,
+                usize::MAX,
+                2,
+                ids.as_ptr(),
+                vectors.as_ptr(),
+                &mut failed_ids,
+                &mut num_failed,
+                &mut error,
+            );
+            assert_eq!(status, ErrorType::InvalidArgument as c_int);
+            assert_eq!(
+                CStr::from_ptr(error).to_str(),
+                Ok("ids has too many elements")
+            );
+            vsagrs_free_string(error);
+            vsagrs_index_free(index);
+        }
+        assert!(buffer_len(usize::MAX / 2 + 1, 2).is_err());
+        assert_eq!(buffer_len(3, 2).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        let err = catch_panic::<()>(|| panic!("boom")).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InternalError));
+        assert_eq!(err.message, "panicked: boom");
+        let err = catch_panic::<()>(|| panic!("{}", 42)).unwrap_err();
+        assert_eq!(err.message, "panicked: 42");
+        assert_eq!(catch_panic(|| Ok(1)).unwrap(), 1);
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! K-means clustering of vectors, e.g. to route vectors to partitions or to analyze a dataset.
+
+use crate::error::{Error, ErrorType, Result};
+use crate::kernels;
+
+/// Seed of the k-means++ initialization, fixed so clustering is deterministic.
+const SEED: u64 = 0x5eed;
+
+/// Result of [`cluster`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clustering {
+    /// Dimension of the vectors.
+    pub dim: usize,
+    /// `k` centroids of `dim` values each, concatenated.
+    pub centroids: Vec<f32>,
+    /// Centroid of each input vector.
+    pub assignments: Vec<usize>,
+}
+
+impl Clustering {
+    /// Returns the number of centroids.
+    pub fn k(&self) -> usize {
+        self.centroids.len() / self.dim
+    }
+
+    /// Returns centroid `i`.
+    pub fn centroid(&self, i: usize) -> &[f32] {
+        &self.centroids[i * self.dim..(i + 1) * self.dim]
+    }
+
+    /// Returns the closest centroid of `vector` by L2 distance.
+    pub fn assign(&self, vector: &[f32]) -> usize {
+        nearest(&self.centroids, self.dim, vector).0
+    }
+
+    /// Returns the `n` closest centroids of `vector` by L2 distance, closest first.
+    pub fn nearest_centroids(&self, vector: &[f32], n: usize) -> Vec<usize> {
+        let mut distances: Vec<(usize, f32)> = self
+            .centroids
+            .chunks_exact(self.dim)
+            .map(|centroid| kernels::l2_sq(vector, centroid))
+            .enumerate()
+            .collect();
+        distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+        distances.into_iter().take(n).map(|(i, _)| i).collect()
+    }
+}
+
+/// Clusters `vectors` of dimension `dim` into `k` clusters by L2 distance, with `iters`
+/// iterations of Lloyd's algorithm after a k-means++ initialization.
+///
+/// Stops early once assignments are stable. Clusters left empty are reseeded with the vector
+/// farthest from its centroid.
+pub fn cluster(vectors: &[f32], dim: usize, k: usize, iters: usize) -> Result<Clustering> {
+    if dim == 0 || !vectors.chunks_exact(dim).remainder().is_empty() {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            format!("length of vectors is not a multiple of dim {dim}"),
+        ));
+    }
+    let num_vectors = vectors.len() / dim;
+    if k == 0 || k > num_vectors {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            format!("k must be in [1, {num_vectors}], got {k}"),
+        ));
+    }
+
+    let mut centroids = init_centroids(vectors, dim, k);
+    let mut assignments = vec![usize::MAX; num_vectors];
+    let mut distances = vec![0.0; num_vectors];
+    for _ in 0..iters {
+        let mut changed = false;
+        for (i, vector) in vectors.chunks_exact(dim).enumerate() {
+            let (centroid, distance) = nearest(&centroids, dim, vector);
+            changed |= assignments[i] != centroid;
+            assignments[i] = centroid;
+            distances[i] = distance;
+        }
+        if !changed {
+            break;
+        }
+
+        let mut counts = vec![0usize; k];
+        centroids.iter_mut().for_each(|v| *v = 0.0);
+        for (vector, &centroid) in vectors.chunks_exact(dim).zip(&assignments) {
+            counts[centroid] += 1;
+            let sum = &mut centroids[centroid * dim..(centroid + 1) * dim];
+            sum.iter_mut().zip(vector).for_each(|(s, v)| *s += v);
+        }
+        for (centroid, &count) in centroids.chunks_exact_mut(dim).zip(&counts) {
+            if count > 0 {
+                centroid.iter_mut().for_each(|v| *v /= count as f32);
+            }
+        }
+        for empty in (0..k).filter(|&c| counts[c] == 0) {
+            let farthest = (0..num_vectors)
+                .max_by(|&a, &b| distances[a].total_cmp(&distances[b]))
+                .expect("k <= num_vectors");
+            distances[farthest] = 0.0;
+            centroids[empty * dim..(empty + 1) * dim]
+                .copy_from_slice(&vectors[farthest * dim..(farthest + 1) * dim]);
+        }
+    }
+
+    // final assignment against the final centroids.
+    for (i, vector) in vectors.chunks_exact(dim).enumerate() {
+        assignments[i] = nearest(&centroids, dim, vector).0;
+    }
+    Ok(Clustering {
+        dim,
+        centroids,
+        assignments,
+    })
+}
+
+/// Picks `k` initial centroids among `vectors` with k-means++: each one with a probability
+/// proportional to its squared distance to the closest centroid picked so far.
+fn init_centroids(vectors: &[f32], dim: usize, k: usize) -> Vec<f32> {
+    let mut rng = SEED;
+    let mut centroids = Vec::with_capacity(k * dim);
+    let num_vectors = vectors.len() / dim;
+    let first = (next_random(&mut rng) * num_vectors as f64) as usize;
+    centroids.extend_from_slice(&vectors[first * dim..(first + 1) * dim]);
+
+    let mut distances: Vec<f32> = vectors
+        .chunks_exact(dim)
+        .map(|vector| kernels::l2_sq(vector, &centroids))
+        .collect();
+    for _ in 1..k {
+        let total: f64 = distances.iter().map(|&d| d as f64).sum();
+        let picked = if total > 0.0 {
+            let mut target = next_random(&mut rng) * total;
+            distances
+                .iter()
+                .position(|&d| {
+                    target -= d as f64;
+                    target < 0.0
+                })
+                .unwrap_or(num_vectors - 1)
+        } else {
+            // all vectors coincide with centroids.
+            (next_random(&mut rng) * num_vectors as f64) as usize
+        };
+        let centroid = &vectors[picked * dim..(picked + 1) * dim];
+        centroids.extend_from_slice(centroid);
+        for (distance, vector) in distances.iter_mut().zip(vectors.chunks_exact(dim)) {
+            *distance = distance.min(kernels::l2_sq(vector, centroid));
+        }
+    }
+    centroids
+}
+
+/// Returns the closest of `centroids` to `vector` and its squared L2 distance.
+fn nearest(centroids: &[f32], dim: usize, vector: &[f32]) -> (usize, f32) {
+    centroids
+        .chunks_exact(dim)
+        .map(|centroid| kernels::l2_sq(vector, centroid))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("at least one centroid")
+}
+
+/// Returns a pseudo-random number in [0, 1) with splitmix64.
+fn next_random(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster() {
+        // 3 blobs around (0, 0), (10, 0) and (0, 10).
+        let vectors: Vec<f32> = (0..30)
+            .flat_map(|i| {
+                let (x, y) = [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)][i % 3];
+                let jitter = (i / 3) as f32 * 0.1;
+                [x + jitter, y - jitter]
+            })
+            .collect();
+
+        let clustering = cluster(&vectors, 2, 3, 20).unwrap();
+        assert_eq!(clustering.k(), 3);
+        for i in 0..30 {
+            assert_eq!(clustering.assignments[i], clustering.assignments[i % 3]);
+        }
+        let blob = clustering.assign(&[9.0, 1.0]);
+        assert_eq!(blob, clustering.assignments[1]);
+        assert!((clustering.centroid(blob)[0] - 10.45).abs() < 1e-4);
+
+        assert_eq!(cluster(&vectors, 2, 30, 5).unwrap().k(), 30);
+        assert!(cluster(&vectors, 2, 31, 5).is_err());
+        assert!(cluster(&vectors, 7, 2, 5).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Little-endian encoding of the files written next to a dumped index, and stable hashing.
+
+use std::hash::Hasher;
+use std::io::{Read, Result, Write};
+
+/// FNV-1a hasher, unlike std hashers its output is stable across releases and machines.
+///
+/// Only [`Hasher::write`] is stable, the other `write_*` methods hash native-endian bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    /// Hashes `bytes` followed by a separator, so consecutive fields can't be confused.
+    pub fn write_field(&mut self, bytes: &[u8]) {
+        self.write(bytes);
+        self.write(&[0xff]);
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub fn write_u64(writer: &mut impl Write, value: u64) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Writes `bytes` prefixed with its length.
+pub fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    write_u64(writer, bytes.len() as u64)?;
+    writer.write_all(bytes)
+}
+
+/// Reads bytes written by [`write_bytes`].
+pub fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let len = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+/// Writes `values` prefixed with their count.
+pub fn write_f32s(writer: &mut impl Write, values: &[f32]) -> Result<()> {
+    write_u64(writer, values.len() as u64)?;
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads values written by [`write_f32s`].
+pub fn read_f32s(reader: &mut impl Read) -> Result<Vec<f32>> {
+    let bytes = {
+        let len = read_u64(reader)?;
+        let mut bytes = Vec::new();
+        reader.take(len.saturating_mul(4)).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != len.saturating_mul(4) {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        bytes
+    };
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
@@ -0,0 +1,1010 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A document-store-like collection of points (ID, vector and payload) over an index,
+//! [`VsagIndex`] by default.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cluster::{cluster, Clustering};
+use crate::codec::{read_bytes, read_f32s, read_u64, write_bytes, write_f32s, write_u64};
+use crate::error::{Error, ErrorType, Result};
+use crate::eval::{verify_recall, RecallCheck};
+use crate::flat::FlatIndex;
+use crate::index::VectorIndex;
+use crate::{kernels, IndexOptions, VsagIndex};
+
+/// File name of the collection config inside a saved [`Collection`] directory.
+const CONFIG_FILE: &str = "config";
+/// File name of the points inside a saved [`Collection`] directory.
+const POINTS_FILE: &str = "points";
+/// File name of the expiration times of points inside a saved [`Collection`] directory.
+const EXPIRY_FILE: &str = "expiry";
+/// File name of the index inside a saved [`Collection`] directory.
+const INDEX_FILE: &str = "index";
+/// Number of points sampled by [`Collection::estimate_count`].
+const ESTIMATE_SAMPLE_SIZE: usize = 1000;
+
+/// Configuration of a [`Collection`].
+#[derive(Debug, Clone)]
+pub struct CollectionConfig {
+    /// Type of the underlying index, see [`VsagIndex::new`].
+    pub index_type: String,
+    /// Parameters of the underlying index in JSON format, see [`VsagIndex::new`].
+    pub params: String,
+    /// Dimension of the vectors, must match `dim` in `params`.
+    pub dim: usize,
+    /// Options of the underlying index, `dedup_policy` is irrelevant since IDs are unique.
+    pub options: IndexOptions,
+}
+
+/// A point stored in a [`Collection`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    /// The vector of the point.
+    pub vector: Vec<f32>,
+    /// Arbitrary bytes attached to the point.
+    pub payload: Vec<u8>,
+    /// When the point expires, `None` if it never does, see [`Collection::upsert_with_ttl`].
+    pub expires_at: Option<SystemTime>,
+}
+
+impl Point {
+    /// Returns `true` if the point has expired at `now`.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// A search result of a [`Collection`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    /// ID of the point.
+    pub id: i64,
+    /// Distance between the point and the query vector.
+    pub distance: f32,
+    /// Payload of the point.
+    pub payload: Vec<u8>,
+}
+
+/// A search result of a [`Collection`] borrowing the payload of the point, see
+/// [`Collection::search_into`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchHitRef<'a> {
+    /// ID of the point.
+    pub id: i64,
+    /// Distance between the point and the query vector.
+    pub distance: f32,
+    /// Payload of the point.
+    pub payload: &'a [u8],
+}
+
+impl SearchHitRef<'_> {
+    /// Copies the hit into a [`SearchHit`] owning its payload.
+    pub fn to_hit(&self) -> SearchHit {
+        SearchHit {
+            id: self.id,
+            distance: self.distance,
+            payload: self.payload.to_vec(),
+        }
+    }
+}
+
+/// A change applied to a [`Collection`], see [`Collection::subscribe`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// A point was inserted, or updated if `id` already existed.
+    Upsert { id: i64, point: Point },
+    /// A point was deleted, explicitly or because it expired.
+    Delete { id: i64 },
+    /// The changes so far were committed.
+    Commit,
+}
+
+impl Change {
+    /// Applies the change to `collection`, e.g. to replicate a collection to a follower.
+    pub fn apply(self, collection: &mut Collection) -> Result<()> {
+        match self {
+            Change::Upsert { id, point } => collection.upsert_point(id, point),
+            Change::Delete { id } => {
+                collection.delete(id);
+                Ok(())
+            }
+            Change::Commit => collection.commit().map(drop),
+        }
+    }
+}
+
+/// Index implementations a [`Collection`] can keep over its points.
+pub trait CollectionIndex: VectorIndex + Sized {
+    /// Creates an empty index for a collection with `config`.
+    fn create(config: &CollectionConfig) -> Result<Self>;
+
+    /// Loads an index saved by a collection with `config` from the file at `path`.
+    fn open(path: &str, config: &CollectionConfig) -> Result<Self>;
+
+    /// Searches like [`VectorIndex::knn_search`], passing the IDs and distances of the results
+    /// to `f`, without copying them if the implementation allows.
+    fn search_with(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+        f: &mut dyn FnMut(&[i64], &[f32]),
+    ) -> Result<()> {
+        let output = self.knn_search(query_vector, k, search_params)?;
+        f(&output.ids, &output.distances);
+        Ok(())
+    }
+}
+
+impl CollectionIndex for VsagIndex {
+    fn create(config: &CollectionConfig) -> Result<Self> {
+        VsagIndex::with_options(&config.index_type, &config.params, config.options.clone())
+    }
+
+    fn open(path: &str, config: &CollectionConfig) -> Result<Self> {
+        VsagIndex::load_with_options(
+            path,
+            &config.index_type,
+            &config.params,
+            config.options.clone(),
+        )
+    }
+
+    fn search_with(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+        f: &mut dyn FnMut(&[i64], &[f32]),
+    ) -> Result<()> {
+        let output = self.knn_search_ref(query_vector, k, search_params)?;
+        f(output.ids(), output.distances());
+        Ok(())
+    }
+}
+
+/// Fails with [`ErrorType::InvalidArgument`] if `normalize` or `validate_vectors` is set,
+/// since [`FlatIndex`] doesn't apply them.
+impl CollectionIndex for FlatIndex {
+    fn create(config: &CollectionConfig) -> Result<Self> {
+        if config.options.normalize || config.options.validate_vectors {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "flat index doesn't support the normalize and validate_vectors options",
+            ));
+        }
+        FlatIndex::new(&config.index_type, &config.params)
+    }
+
+    fn open(path: &str, config: &CollectionConfig) -> Result<Self> {
+        Self::create(config)?;
+        FlatIndex::load(path, &config.index_type, &config.params)
+    }
+}
+
+/// `Collection` stores points and keeps an index over them for searching, a [`VsagIndex`]
+/// unless another [`CollectionIndex`] is given.
+///
+/// vsag indexes are built once, so changes are staged in the collection and only become
+/// searchable after [`Collection::commit`] rebuilds the index. Until then, searches run against
+/// the previous commit, skipping points that have been deleted since.
+pub struct Collection<I = VsagIndex> {
+    config: CollectionConfig,
+    points: BTreeMap<i64, Point>,
+    index: Option<I>,
+    dirty: bool,
+    subscribers: Vec<Sender<Change>>,
+}
+
+impl Collection {
+    /// Creates an empty collection over a [`VsagIndex`].
+    pub fn new(config: CollectionConfig) -> Self {
+        Self::with_config(config)
+    }
+
+    /// Opens a collection over a [`VsagIndex`] saved by [`Collection::save`] from the directory
+    /// at `dir`.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        Self::open_dir(dir)
+    }
+}
+
+impl<I: CollectionIndex> Collection<I> {
+    /// Same as [`Collection::new`], over any [`CollectionIndex`].
+    pub fn with_config(config: CollectionConfig) -> Self {
+        Collection {
+            config,
+            points: BTreeMap::new(),
+            index: None,
+            dirty: false,
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Returns a receiver of the changes applied to the collection from now on, in order.
+    ///
+    /// The receiver is unbounded, so it must be drained to avoid piling up changes; dropping it
+    /// unsubscribes.
+    pub fn subscribe(&mut self) -> Receiver<Change> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn publish(&mut self, change: impl FnOnce() -> Change) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let change = change();
+        self.subscribers
+            .retain(|subscriber| subscriber.send(change.clone()).is_ok());
+    }
+
+    /// Returns the config of the collection.
+    pub fn config(&self) -> &CollectionConfig {
+        &self.config
+    }
+
+    /// Inserts a new point, fails if `id` already exists.
+    pub fn insert(&mut self, id: i64, vector: Vec<f32>, payload: Vec<u8>) -> Result<()> {
+        if self.points.contains_key(&id) {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                format!("point {id} already exists"),
+            ));
+        }
+        self.upsert(id, vector, payload)
+    }
+
+    /// Updates an existing point, fails if `id` doesn't exist.
+    pub fn update(&mut self, id: i64, vector: Vec<f32>, payload: Vec<u8>) -> Result<()> {
+        if !self.points.contains_key(&id) {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                format!("point {id} doesn't exist"),
+            ));
+        }
+        self.upsert(id, vector, payload)
+    }
+
+    /// Inserts or updates a point.
+    pub fn upsert(&mut self, id: i64, vector: Vec<f32>, payload: Vec<u8>) -> Result<()> {
+        self.upsert_point(
+            id,
+            Point {
+                vector,
+                payload,
+                expires_at: None,
+            },
+        )
+    }
+
+    /// Inserts or updates a point that expires after `ttl`.
+    ///
+    /// Expired points are skipped by searches right away, and removed by
+    /// [`Collection::sweep_expired`], e.g. periodically with [`crate::ttl::Sweeper`].
+    pub fn upsert_with_ttl(
+        &mut self,
+        id: i64,
+        vector: Vec<f32>,
+        payload: Vec<u8>,
+        ttl: Duration,
+    ) -> Result<()> {
+        self.upsert_point(
+            id,
+            Point {
+                vector,
+                payload,
+                expires_at: Some(SystemTime::now() + ttl),
+            },
+        )
+    }
+
+    /// Fails if the point of `id` and `vector` would be rejected by [`Collection::upsert`].
+    pub(crate) fn check_point(&self, id: i64, vector: &[f32]) -> Result<()> {
+        if self.config.options.validate_ids && id < 0 {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                format!("negative id {id}"),
+            ));
+        }
+        if vector.len() != self.config.dim {
+            return Err(Error::new(
+                ErrorType::DimensionNotEqual,
+                format!(
+                    "expect vector of dimension {}, got {}",
+                    self.config.dim,
+                    vector.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn upsert_point(&mut self, id: i64, point: Point) -> Result<()> {
+        self.check_point(id, &point.vector)?;
+        self.publish(|| Change::Upsert {
+            id,
+            point: point.clone(),
+        });
+        self.points.insert(id, point);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Deletes the points that have expired, returns their IDs.
+    pub fn sweep_expired(&mut self) -> Vec<i64> {
+        let now = SystemTime::now();
+        let expired: Vec<i64> = self
+            .points
+            .iter()
+            .filter(|(_, point)| point.is_expired(now))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &expired {
+            self.delete(*id);
+        }
+        expired
+    }
+
+    /// Deletes a point, returns it if it existed.
+    pub fn delete(&mut self, id: i64) -> Option<Point> {
+        let point = self.points.remove(&id);
+        if point.is_some() {
+            self.dirty = true;
+            self.publish(|| Change::Delete { id });
+        }
+        point
+    }
+
+    /// Returns the point of `id`.
+    pub fn get(&self, id: i64) -> Option<&Point> {
+        self.points.get(&id)
+    }
+
+    /// Returns the number of points.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Returns `true` if the collection holds no points.
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Estimates how many points satisfy `filter`, without running a search.
+    ///
+    /// Up to 1000 points, evenly spread over the ID space, are checked and the matching ratio is
+    /// extrapolated, so the result is exact for small collections. This helps to decide between a
+    /// filtered search and a brute-force scan of the matching points.
+    pub fn estimate_count(&self, filter: impl Fn(i64) -> bool) -> usize {
+        let step = self.points.len().div_ceil(ESTIMATE_SAMPLE_SIZE).max(1);
+        let (sampled, matched) = self
+            .points
+            .keys()
+            .step_by(step)
+            .fold((0, 0), |(sampled, matched), &id| {
+                (sampled + 1, matched + filter(id) as usize)
+            });
+        if sampled == 0 {
+            return 0;
+        }
+        (matched as f64 / sampled as f64 * self.points.len() as f64).round() as usize
+    }
+
+    /// Clusters the vectors of all points into `k` clusters, see [`cluster`].
+    ///
+    /// Returns the IDs of the points, in the order of [`Clustering::assignments`].
+    pub fn cluster(&self, k: usize, iters: usize) -> Result<(Vec<i64>, Clustering)> {
+        let ids: Vec<i64> = self.points.keys().copied().collect();
+        let vectors: Vec<f32> = self
+            .points
+            .values()
+            .flat_map(|point| point.vector.iter().copied())
+            .collect();
+        Ok((ids, cluster(&vectors, self.config.dim, k, iters)?))
+    }
+
+    /// Returns `true` if there are changes not committed yet.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns the index as of the last commit, `None` if there are no committed points.
+    #[cfg(feature = "sled")]
+    pub(crate) fn committed_index(&self) -> Option<&I> {
+        self.index.as_ref()
+    }
+
+    /// Sets `index`, built over the current points, as the committed index.
+    #[cfg(feature = "sled")]
+    pub(crate) fn set_committed_index(&mut self, index: I) {
+        self.index = Some(index);
+        self.dirty = false;
+    }
+
+    /// Rebuilds the index over all points, making staged changes searchable.
+    ///
+    /// Points that vsag fails to insert into the index are deleted, so the points and the index
+    /// stay in sync, and their IDs are returned.
+    pub fn commit(&mut self) -> Result<Vec<i64>> {
+        if !self.dirty {
+            return Ok(Vec::new());
+        }
+        if self.points.is_empty() {
+            self.index = None;
+            self.dirty = false;
+            self.publish(|| Change::Commit);
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<i64> = self.points.keys().copied().collect();
+        let vectors: Vec<f32> = self
+            .points
+            .values()
+            .flat_map(|point| point.vector.iter().copied())
+            .collect();
+        let index = I::create(&self.config)?;
+        let failed_ids = index.build(ids.len(), self.config.dim, &ids, &vectors)?;
+        for &id in &failed_ids {
+            self.delete(id);
+        }
+
+        self.index = Some(index);
+        self.dirty = false;
+        self.publish(|| Change::Commit);
+        Ok(failed_ids)
+    }
+
+    /// Searches for the `k` nearest points of the `query_vector` as of the last commit, see
+    /// [`VsagIndex::knn_search`].
+    ///
+    /// Points deleted or expired since the last commit are skipped, so fewer than `k` points may
+    /// be returned.
+    pub fn search(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+    ) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        self.search_into(query_vector, k, search_params, &mut hits)?;
+        Ok(hits.iter().map(SearchHitRef::to_hit).collect())
+    }
+
+    /// Same as [`Collection::search`], but writes the hits into `hits`, borrowing the payloads
+    /// from the collection instead of copying them.
+    ///
+    /// `hits` is cleared first, reusing it across queries avoids allocating on the Rust side.
+    pub fn search_into<'a>(
+        &'a self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+        hits: &mut Vec<SearchHitRef<'a>>,
+    ) -> Result<()> {
+        hits.clear();
+        let Some(index) = &self.index else {
+            return Ok(());
+        };
+
+        let now = SystemTime::now();
+        index.search_with(query_vector, k, search_params, &mut |ids, distances| {
+            hits.extend(ids.iter().zip(distances).filter_map(|(&id, &distance)| {
+                let point = self.points.get(&id).filter(|point| !point.is_expired(now));
+                point.map(|point| SearchHitRef {
+                    id,
+                    distance,
+                    payload: &point.payload,
+                })
+            }));
+        })
+    }
+
+    /// Checks the committed index against an exact search over the points for
+    /// `sample_queries`, see [`verify_recall`], e.g. right after [`Collection::open`].
+    ///
+    /// Fails if there are uncommitted changes, since the index doesn't reflect the points then.
+    pub fn verify_recall(
+        &self,
+        sample_queries: &[f32],
+        k: usize,
+        search_params: &str,
+        min_recall: f64,
+    ) -> Result<RecallCheck> {
+        let Some(index) = &self.index else {
+            return Err(Error::new(ErrorType::IndexEmpty, "nothing is committed"));
+        };
+        if self.dirty {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "the collection has uncommitted changes",
+            ));
+        }
+
+        let ids: Vec<i64> = self.points.keys().copied().collect();
+        let mut vectors: Vec<f32> = self
+            .points
+            .values()
+            .flat_map(|point| point.vector.iter().copied())
+            .collect();
+        let mut sample_queries = Cow::Borrowed(sample_queries);
+        if self.config.options.normalize {
+            vectors = kernels::normalized(&vectors, self.config.dim);
+            sample_queries = Cow::Owned(kernels::normalized(&sample_queries, self.config.dim));
+        }
+        verify_recall(
+            index,
+            &vectors,
+            &ids,
+            &sample_queries,
+            k,
+            search_params,
+            min_recall,
+        )
+    }
+
+    /// Same as [`Collection::search`], but searches `k * rerank_factor` candidates and
+    /// recomputes their exact distances from the stored vectors before returning the top `k`.
+    ///
+    /// This recovers recall lost by approximate distances, e.g. of quantized indexes, at the
+    /// cost of a bigger search and one distance computation per candidate. Distances are
+    /// computed against the current vector of each point, even if it changed since the last
+    /// commit. Fails if the metric type is missing from the index parameters.
+    pub fn search_with_rerank(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        rerank_factor: usize,
+        search_params: &str,
+    ) -> Result<Vec<SearchHit>> {
+        let Some(index) = &self.index else {
+            return Ok(Vec::new());
+        };
+        let metric = index.metric().ok_or_else(|| {
+            Error::new(
+                ErrorType::InvalidArgument,
+                "metric_type is required to rerank results",
+            )
+        })?;
+
+        let normalize = self.config.options.normalize;
+        let query_vector = if normalize {
+            Cow::Owned(kernels::normalized(query_vector, query_vector.len()))
+        } else {
+            Cow::Borrowed(query_vector)
+        };
+        let num_candidates = k.saturating_mul(rerank_factor.max(1));
+        let mut hits = self.search(&query_vector, num_candidates, search_params)?;
+        for hit in &mut hits {
+            let vector = &self.points[&hit.id].vector;
+            hit.distance = if normalize {
+                metric.distance_between(&query_vector, &kernels::normalized(vector, vector.len()))
+            } else {
+                metric.distance_between(&query_vector, vector)
+            };
+        }
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance).then(a.id.cmp(&b.id)));
+        hits.truncate(k);
+        Ok(hits)
+    }
+
+    /// Saves the whole collection into the directory at `dir`.
+    ///
+    /// The directory is created if it doesn't exist. The index is only saved when there are no
+    /// uncommitted changes, otherwise it's rebuilt by [`Collection::open`].
+    pub fn save(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut writer = BufWriter::new(File::create(dir.join(CONFIG_FILE))?);
+        write_bytes(&mut writer, self.config.index_type.as_bytes())?;
+        write_bytes(&mut writer, self.config.params.as_bytes())?;
+        write_u64(&mut writer, self.config.dim as u64)?;
+        write_bytes(
+            &mut writer,
+            &[
+                self.config.options.normalize as u8,
+                self.config.options.validate_vectors as u8,
+                self.config.options.validate_ids as u8,
+            ],
+        )?;
+        writer.flush()?;
+
+        let mut writer = BufWriter::new(File::create(dir.join(POINTS_FILE))?);
+        write_u64(&mut writer, self.points.len() as u64)?;
+        for (id, point) in &self.points {
+            write_u64(&mut writer, *id as u64)?;
+            write_f32s(&mut writer, &point.vector)?;
+            write_bytes(&mut writer, &point.payload)?;
+        }
+        writer.flush()?;
+
+        let expiring: Vec<(i64, SystemTime)> = self
+            .points
+            .iter()
+            .filter_map(|(&id, point)| point.expires_at.map(|expires_at| (id, expires_at)))
+            .collect();
+        let expiry_path = dir.join(EXPIRY_FILE);
+        if expiring.is_empty() {
+            match std::fs::remove_file(&expiry_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        } else {
+            let mut writer = BufWriter::new(File::create(expiry_path)?);
+            write_u64(&mut writer, expiring.len() as u64)?;
+            for (id, expires_at) in expiring {
+                let millis = expires_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                write_u64(&mut writer, id as u64)?;
+                write_u64(&mut writer, millis as u64)?;
+            }
+            writer.flush()?;
+        }
+
+        let index_path = dir.join(INDEX_FILE);
+        match &self.index {
+            Some(index) if !self.dirty => index.dump(&index_path.display().to_string()),
+            _ => match std::fs::remove_file(&index_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    /// Same as [`Collection::open`], over any [`CollectionIndex`].
+    pub fn open_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+
+        let mut reader = BufReader::new(File::open(dir.join(CONFIG_FILE))?);
+        let index_type = read_string(&mut reader)?;
+        let params = read_string(&mut reader)?;
+        let dim = read_u64(&mut reader)? as usize;
+        let flags = read_bytes(&mut reader)?;
+        let config = CollectionConfig {
+            index_type,
+            params,
+            dim,
+            options: IndexOptions {
+                normalize: flags.first() == Some(&1),
+                validate_vectors: flags.get(1) == Some(&1),
+                validate_ids: flags.get(2) == Some(&1),
+                ..Default::default()
+            },
+        };
+
+        let mut reader = BufReader::new(File::open(dir.join(POINTS_FILE))?);
+        let mut points = BTreeMap::new();
+        for _ in 0..read_u64(&mut reader)? {
+            let id = read_u64(&mut reader)? as i64;
+            let vector = read_f32s(&mut reader)?;
+            let payload = read_bytes(&mut reader)?;
+            points.insert(
+                id,
+                Point {
+                    vector,
+                    payload,
+                    expires_at: None,
+                },
+            );
+        }
+
+        // saved by older versions without expiration times.
+        match File::open(dir.join(EXPIRY_FILE)) {
+            Ok(file) => {
+                let mut reader = BufReader::new(file);
+                for _ in 0..read_u64(&mut reader)? {
+                    let id = read_u64(&mut reader)? as i64;
+                    let millis = read_u64(&mut reader)?;
+                    if let Some(point) = points.get_mut(&id) {
+                        point.expires_at = Some(UNIX_EPOCH + Duration::from_millis(millis));
+                    }
+                }
+            }
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            Err(_) => {}
+        }
+
+        let index_path = dir.join(INDEX_FILE);
+        let index = if index_path.exists() {
+            Some(I::open(&index_path.display().to_string(), &config)?)
+        } else {
+            None
+        };
+
+        let mut collection = Collection {
+            dirty: index.is_none() && !points.is_empty(),
+            config,
+            points,
+            index,
+            subscribers: Vec::new(),
+        };
+        collection.commit()?;
+        Ok(collection)
+    }
+}
+
+fn read_string(reader: &mut impl std::io::Read) -> Result<String> {
+    String::from_utf8(read_bytes(reader)?)
+        .map_err(|_| Error::new(ErrorType::InvalidBinary, "invalid utf-8 string"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::KnnSearchOutput;
+
+    fn new_collection() -> Collection {
+        Collection::new(CollectionConfig {
+            index_type: "hnsw".to_string(),
+            params: r#"{
+                "dtype": "float32",
+                "metric_type": "l2",
+                "dim": 2,
+                "hnsw": {
+                    "max_degree": 16,
+                    "ef_construction": 100
+                }
+            }"#
+            .to_string(),
+            dim: 2,
+            options: IndexOptions::default(),
+        })
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_collection_crud_and_search() {
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let mut collection = new_collection();
+        collection.insert(1, vec![0.0, 0.0], b"a".to_vec()).unwrap();
+        collection.insert(2, vec![1.0, 0.0], b"b".to_vec()).unwrap();
+        collection.insert(3, vec![5.0, 5.0], b"c".to_vec()).unwrap();
+        assert!(collection.insert(1, vec![0.0, 0.0], vec![]).is_err());
+        assert!(collection.update(4, vec![0.0, 0.0], vec![]).is_err());
+        assert!(collection.insert(4, vec![0.0], vec![]).is_err());
+
+        // not searchable before commit
+        assert!(collection
+            .search(&[0.0, 0.0], 2, search_params)
+            .unwrap()
+            .is_empty());
+        collection.commit().unwrap();
+        let hits = collection.search(&[0.0, 0.0], 2, search_params).unwrap();
+        assert_eq!(
+            hits.iter().map(|hit| hit.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(hits[0].payload, b"a");
+        let mut hit_refs = Vec::new();
+        collection
+            .search_into(&[0.0, 0.0], 2, search_params, &mut hit_refs)
+            .unwrap();
+        assert_eq!(
+            hit_refs.iter().map(|hit| hit.to_hit()).collect::<Vec<_>>(),
+            hits
+        );
+
+        // deleted points are skipped before commit
+        collection.delete(1).unwrap();
+        let hits = collection.search(&[0.0, 0.0], 2, search_params).unwrap();
+        assert_eq!(hits.iter().map(|hit| hit.id).collect::<Vec<_>>(), vec![2]);
+
+        collection.update(3, vec![0.0, 0.1], b"d".to_vec()).unwrap();
+        collection.commit().unwrap();
+        let hits = collection.search(&[0.0, 0.0], 2, search_params).unwrap();
+        assert_eq!(
+            hits.iter().map(|hit| hit.id).collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+        assert_eq!(collection.get(3).unwrap().payload, b"d");
+
+        let hits = collection
+            .search_with_rerank(&[0.0, 0.0], 1, 2, search_params)
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, 3);
+        assert!((hits[0].distance - 0.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_flat_collection() {
+        let mut config = new_collection().config().clone();
+        config.index_type = "flat".to_string();
+        let mut collection = Collection::<FlatIndex>::with_config(config.clone());
+        collection.upsert(1, vec![0.0, 0.0], b"a".to_vec()).unwrap();
+        collection.upsert(2, vec![3.0, 0.0], b"b".to_vec()).unwrap();
+        collection.commit().unwrap();
+        let hits = collection.search(&[2.9, 0.0], 1, "").unwrap();
+        assert_eq!(hits[0].id, 2);
+        assert_eq!(hits[0].payload, b"b");
+
+        let dir = tempdir::TempDir::new("test_flat_collection").unwrap();
+        collection.save(dir.path()).unwrap();
+        let reopened = Collection::<FlatIndex>::open_dir(dir.path()).unwrap();
+        assert!(!reopened.is_dirty());
+        assert_eq!(reopened.search(&[0.1, 0.0], 1, "").unwrap()[0].id, 1);
+
+        config.options.normalize = true;
+        let mut collection = Collection::<FlatIndex>::with_config(config);
+        collection.upsert(1, vec![0.0, 1.0], vec![]).unwrap();
+        let err = collection.commit().unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+    }
+
+    /// A flat index failing to insert negative IDs, like vsag may fail to insert some vectors.
+    struct PickyIndex(FlatIndex);
+
+    impl VectorIndex for PickyIndex {
+        fn build(
+            &self,
+            _num_vectors: usize,
+            dim: usize,
+            ids: &[i64],
+            vectors: &[f32],
+        ) -> Result<Vec<i64>> {
+            let (mut kept_ids, mut kept_vectors, mut failed_ids) = (vec![], vec![], vec![]);
+            for (&id, vector) in ids.iter().zip(vectors.chunks_exact(dim)) {
+                if id < 0 {
+                    failed_ids.push(id);
+                } else {
+                    kept_ids.push(id);
+                    kept_vectors.extend_from_slice(vector);
+                }
+            }
+            self.0
+                .build(kept_ids.len(), dim, &kept_ids, &kept_vectors)?;
+            Ok(failed_ids)
+        }
+
+        fn knn_search(&self, query: &[f32], k: usize, params: &str) -> Result<KnnSearchOutput> {
+            self.0.knn_search(query, k, params)
+        }
+
+        fn dump(&self, path: &str) -> Result<()> {
+            self.0.dump(path)
+        }
+
+        fn load(path: &str, index_type: &str, params: &str) -> Result<Self> {
+            FlatIndex::load(path, index_type, params).map(PickyIndex)
+        }
+
+        fn index_type(&self) -> &str {
+            self.0.index_type()
+        }
+
+        fn metric(&self) -> Option<Metric> {
+            self.0.metric()
+        }
+    }
+
+    impl CollectionIndex for PickyIndex {
+        fn create(config: &CollectionConfig) -> Result<Self> {
+            FlatIndex::create(config).map(PickyIndex)
+        }
+
+        fn open(path: &str, config: &CollectionConfig) -> Result<Self> {
+            <FlatIndex as CollectionIndex>::open(path, config).map(PickyIndex)
+        }
+    }
+
+    #[test]
+    fn test_commit_failed_ids() {
+        let mut config = new_collection().config().clone();
+        config.index_type = "flat".to_string();
+        let mut collection = Collection::<PickyIndex>::with_config(config);
+        let changes = collection.subscribe();
+        collection.upsert(1, vec![0.0, 0.0], vec![]).unwrap();
+        collection.upsert(-1, vec![1.0, 0.0], vec![]).unwrap();
+        collection.upsert(2, vec![2.0, 0.0], vec![]).unwrap();
+
+        // failed points are deleted, and the deletion replicated.
+        assert_eq!(collection.commit().unwrap(), vec![-1]);
+        assert!(collection.get(-1).is_none());
+        assert_eq!(collection.len(), 2);
+        assert!(!collection.is_dirty());
+        assert!(changes
+            .try_iter()
+            .any(|change| matches!(change, Change::Delete { id: -1 })));
+        assert!(collection.commit().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_estimate_count() {
+        let mut collection = new_collection();
+        assert_eq!(collection.estimate_count(|_| true), 0);
+        for id in 0..10_000 {
+            collection.insert(id, vec![0.0, 0.0], vec![]).unwrap();
+        }
+        let estimated = collection.estimate_count(|id| id % 3 == 0);
+        assert!(estimated.abs_diff(3334) < 100, "estimated {estimated}");
+        assert_eq!(collection.estimate_count(|id| id < 100), 100);
+        assert_eq!(collection.estimate_count(|_| false), 0);
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_collection_save_open() {
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let dir = tempdir::TempDir::new("test_collection_save_open").unwrap();
+
+        let mut collection = new_collection();
+        collection.insert(1, vec![0.0, 0.0], b"a".to_vec()).unwrap();
+        collection.insert(2, vec![1.0, 0.0], b"b".to_vec()).unwrap();
+        collection.commit().unwrap();
+        collection.save(dir.path()).unwrap();
+
+        let reopened = Collection::open(dir.path()).unwrap();
+        assert_eq!(reopened.len(), 2);
+        let check = reopened
+            .verify_recall(&[0.1, 0.0, 0.9, 0.0], 1, search_params, 1.0)
+            .unwrap();
+        assert!(check.passed());
+        assert_eq!(reopened.get(2), collection.get(2));
+        assert_eq!(
+            reopened.search(&[1.0, 0.0], 1, search_params).unwrap(),
+            collection.search(&[1.0, 0.0], 1, search_params).unwrap()
+        );
+
+        // uncommitted changes are persisted, and the index is rebuilt on open
+        collection.insert(3, vec![2.0, 0.0], b"c".to_vec()).unwrap();
+        collection.save(dir.path()).unwrap();
+        let reopened = Collection::open(dir.path()).unwrap();
+        assert!(!reopened.is_dirty());
+        let hits = reopened.search(&[2.0, 0.0], 1, search_params).unwrap();
+        assert_eq!(hits[0].id, 3);
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_change_stream() {
+        let mut leader = new_collection();
+        let mut follower = new_collection();
+        let receiver = leader.subscribe();
+        leader.upsert(1, vec![0.0, 0.0], b"a".to_vec()).unwrap();
+        leader.upsert(2, vec![1.0, 0.0], b"b".to_vec()).unwrap();
+        leader.commit().unwrap();
+        leader.upsert(1, vec![3.0, 0.0], b"c".to_vec()).unwrap();
+        leader.delete(2);
+        leader.delete(2);
+        leader.commit().unwrap();
+
+        let changes: Vec<Change> = receiver.try_iter().collect();
+        drop(receiver);
+        assert_eq!(changes.len(), 6);
+        assert_eq!(changes[4], Change::Delete { id: 2 });
+        for change in changes {
+            change.apply(&mut follower).unwrap();
+        }
+        assert!(!follower.is_dirty());
+        assert_eq!(follower.len(), 1);
+        assert_eq!(follower.get(1), leader.get(1));
+
+        // dropped receivers are unsubscribed.
+        leader.upsert(3, vec![0.0, 0.0], vec![]).unwrap();
+        assert!(leader.subscribers.is_empty());
+    }
+}
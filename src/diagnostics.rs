@@ -0,0 +1,135 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Which BLAS backend and SIMD instructions this build uses, to tell why a node is slower than
+//! another.
+
+use std::fmt;
+use std::hint::black_box;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::kernels;
+
+/// Dimension of the vectors of the distance micro-benchmark.
+const BENCH_DIM: usize = 128;
+/// Number of distances computed by the micro-benchmark.
+const BENCH_ITERS: u32 = 100_000;
+
+/// What [`build_info`] reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildInfo {
+    /// Version of this crate.
+    pub version: &'static str,
+    /// BLAS backend libvsag was built with, as selected by the crate features: `openblas` or
+    /// `intel-mkl` when vendored, `external` for a libvsag from `VSAG_LIB_PATH` and `none`
+    /// without libvsag.
+    pub blas: &'static str,
+    /// SIMD instruction sets supported by the CPU.
+    pub cpu_features: Vec<&'static str>,
+    /// Implementation of the Rust-side distance kernels, `avx2+fma` or `portable`.
+    pub kernels: &'static str,
+    /// Nanoseconds per L2 distance between 128-dimensional vectors with the Rust-side kernels.
+    pub l2_nanos: f64,
+}
+
+/// Returns the build and CPU information, probed on the first call, which takes a few
+/// milliseconds to run the distance micro-benchmark.
+pub fn build_info() -> &'static BuildInfo {
+    static INFO: OnceLock<BuildInfo> = OnceLock::new();
+    INFO.get_or_init(|| BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        blas: blas(),
+        cpu_features: cpu_features(),
+        kernels: kernels::implementation(),
+        l2_nanos: bench_l2(),
+    })
+}
+
+/// A single line, to be logged at startup.
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "vsag-rs {}, blas: {}, cpu: [{}], kernels: {}, l2 dim {BENCH_DIM}: {:.1}ns",
+            self.version,
+            self.blas,
+            self.cpu_features.join(" "),
+            self.kernels,
+            self.l2_nanos
+        )
+    }
+}
+
+fn blas() -> &'static str {
+    if cfg!(any(feature = "pure-rust", feature = "mock-ffi")) {
+        "none"
+    } else if !cfg!(feature = "vendored") {
+        "external"
+    } else if cfg!(feature = "enable-intel-mkl") {
+        "intel-mkl"
+    } else {
+        "openblas"
+    }
+}
+
+fn cpu_features() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut features = Vec::new();
+    #[cfg(target_arch = "x86_64")]
+    {
+        let detected = [
+            ("sse4.2", is_x86_feature_detected!("sse4.2")),
+            ("avx", is_x86_feature_detected!("avx")),
+            ("avx2", is_x86_feature_detected!("avx2")),
+            ("fma", is_x86_feature_detected!("fma")),
+            ("avx512f", is_x86_feature_detected!("avx512f")),
+        ];
+        features.extend(detected.iter().filter(|(_, on)| *on).map(|(name, _)| *name));
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            features.push("neon");
+        }
+        if std::arch::is_aarch64_feature_detected!("sve") {
+            features.push("sve");
+        }
+    }
+    features
+}
+
+fn bench_l2() -> f64 {
+    let a: Vec<f32> = (0..BENCH_DIM).map(|i| i as f32).collect();
+    let b: Vec<f32> = (0..BENCH_DIM).map(|i| (BENCH_DIM - i) as f32).collect();
+    let start = Instant::now();
+    for _ in 0..BENCH_ITERS {
+        black_box(kernels::l2_sq(black_box(&a), black_box(&b)));
+    }
+    start.elapsed().as_nanos() as f64 / BENCH_ITERS as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(info.l2_nanos > 0.0);
+        assert!(std::ptr::eq(info, build_info()));
+        assert!(info.to_string().starts_with("vsag-rs "));
+    }
+}
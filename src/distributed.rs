@@ -0,0 +1,276 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Distributed build of a [`ShardedIndex`]: workers build and dump one shard each from the
+//! same input, and a coordinator loads them into the final index.
+//!
+//! vsag graphs can't be merged, so the final index keeps one graph per shard and fans searches
+//! out to them.
+
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::codec::{read_bytes, read_u64, write_bytes, write_u64, Fnv1a};
+use crate::error::{Error, ErrorType, Result};
+use crate::sharded::{ShardedIndex, Sharding};
+use crate::{IndexOptions, VsagIndex};
+
+/// File name of the manifest inside a shard directory.
+const MANIFEST_FILE: &str = "manifest";
+/// File name of the vsag index inside a shard directory.
+const INDEX_FILE: &str = "index";
+
+/// The plan of a distributed build, shared by the workers and the coordinator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildPlan {
+    /// Type of the index of each shard, see [`VsagIndex::new`].
+    pub index_type: String,
+    /// Parameters of the index of each shard in JSON format, see [`VsagIndex::new`].
+    pub params: String,
+    /// Dimension of the vectors.
+    pub dim: usize,
+    /// How vectors are assigned to shards, one shard per worker.
+    pub sharding: Sharding,
+}
+
+/// What a worker built, stored next to the dumped shard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardManifest {
+    /// [`BuildPlan::fingerprint`] of the plan the shard was built with.
+    pub fingerprint: u64,
+    /// Number of the shard.
+    pub shard: usize,
+    /// Number of vectors in the shard.
+    pub num_vectors: usize,
+}
+
+impl BuildPlan {
+    /// Returns a hash of the plan, identical on all machines for identical plans.
+    pub fn fingerprint(&self) -> u64 {
+        let sharding = match &self.sharding {
+            Sharding::Hash { num_shards } => format!("hash:{num_shards}"),
+            Sharding::Range { bounds } => format!("range:{bounds:?}"),
+        };
+        let mut hasher = Fnv1a::default();
+        hasher.write_field(self.index_type.as_bytes());
+        hasher.write_field(self.params.as_bytes());
+        hasher.write_field(&(self.dim as u64).to_le_bytes());
+        hasher.write_field(sharding.as_bytes());
+        hasher.finish()
+    }
+
+    /// Returns the IDs and vectors of `shard` among all `ids` and `vectors`.
+    ///
+    /// Vectors are assigned by ID only, so workers may read the input in any order.
+    pub fn partition(
+        &self,
+        shard: usize,
+        ids: &[i64],
+        vectors: &[f32],
+    ) -> Result<(Vec<i64>, Vec<f32>)> {
+        if self.dim == 0 || vectors.len() != ids.len() * self.dim {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "ids and vectors have mismatched lengths",
+            ));
+        }
+        let mut shard_ids = Vec::new();
+        let mut shard_vectors = Vec::new();
+        for (&id, vector) in ids.iter().zip(vectors.chunks_exact(self.dim)) {
+            if self.sharding.shard_of(id) == shard {
+                shard_ids.push(id);
+                shard_vectors.extend_from_slice(vector);
+            }
+        }
+        Ok((shard_ids, shard_vectors))
+    }
+
+    /// Builds `shard` from its vectors among `ids` and `vectors` and dumps it with its manifest
+    /// into `dir`, to be run by the worker of the shard.
+    ///
+    /// Returns IDs of vectors that failed to be added.
+    pub fn build_shard(
+        &self,
+        shard: usize,
+        ids: &[i64],
+        vectors: &[f32],
+        options: IndexOptions,
+        dir: impl AsRef<Path>,
+    ) -> Result<Vec<i64>> {
+        let num_shards = self.sharding.num_shards();
+        if shard >= num_shards {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                format!("shard {shard} is out of {num_shards} shards"),
+            ));
+        }
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let (ids, vectors) = self.partition(shard, ids, vectors)?;
+        let mut failed_ids = Vec::new();
+        // empty indexes can't be dumped, empty shards are recreated on merge instead.
+        if !ids.is_empty() {
+            let index = VsagIndex::with_options(&self.index_type, &self.params, options)?;
+            failed_ids = index.build(ids.len(), self.dim, &ids, &vectors)?;
+            index.dump(&dir.join(INDEX_FILE).display().to_string())?;
+        }
+
+        let manifest = ShardManifest {
+            fingerprint: self.fingerprint(),
+            shard,
+            num_vectors: ids.len() - failed_ids.len(),
+        };
+        let mut writer = BufWriter::new(File::create(dir.join(MANIFEST_FILE))?);
+        write_u64(&mut writer, manifest.fingerprint)?;
+        write_u64(&mut writer, manifest.shard as u64)?;
+        write_u64(&mut writer, manifest.num_vectors as u64)?;
+        write_bytes(&mut writer, self.index_type.as_bytes())?;
+        writer.flush()?;
+        Ok(failed_ids)
+    }
+
+    /// Loads the shards dumped by [`BuildPlan::build_shard`] into `dirs`, in any order, into
+    /// the final index, to be run by the coordinator.
+    ///
+    /// Fails if a shard was built with another plan, or if shards are missing or duplicated.
+    pub fn merge(
+        &self,
+        dirs: &[impl AsRef<Path>],
+        options: IndexOptions,
+    ) -> Result<ShardedIndex<VsagIndex>> {
+        let num_shards = self.sharding.num_shards();
+        let mut shards: Vec<Option<(VsagIndex, usize)>> = (0..num_shards).map(|_| None).collect();
+        for dir in dirs {
+            let dir = dir.as_ref();
+            let manifest = read_manifest(dir)?;
+            if manifest.fingerprint != self.fingerprint() {
+                return Err(Error::new(
+                    ErrorType::InvalidArgument,
+                    format!("shard in {} was built with another plan", dir.display()),
+                ));
+            }
+            let Some(slot) = shards.get_mut(manifest.shard) else {
+                return Err(Error::new(
+                    ErrorType::InvalidArgument,
+                    format!("shard {} is out of {num_shards} shards", manifest.shard),
+                ));
+            };
+            if slot.is_some() {
+                return Err(Error::new(
+                    ErrorType::InvalidArgument,
+                    format!("shard {} is duplicated", manifest.shard),
+                ));
+            }
+
+            let index = if manifest.num_vectors > 0 {
+                VsagIndex::load_with_options(
+                    &dir.join(INDEX_FILE).display().to_string(),
+                    &self.index_type,
+                    &self.params,
+                    options.clone(),
+                )?
+            } else {
+                VsagIndex::with_options(&self.index_type, &self.params, options.clone())?
+            };
+            *slot = Some((index, manifest.num_vectors));
+        }
+
+        let mut indexes = Vec::with_capacity(num_shards);
+        let mut sizes = Vec::with_capacity(num_shards);
+        for (shard, slot) in shards.into_iter().enumerate() {
+            let (index, size) = slot.ok_or_else(|| {
+                Error::new(ErrorType::MissingFile, format!("shard {shard} is missing"))
+            })?;
+            indexes.push(index);
+            sizes.push(size);
+        }
+        ShardedIndex::from_built_shards(self.sharding.clone(), indexes, sizes)
+    }
+}
+
+/// Reads the manifest of the shard dumped into `dir`.
+pub fn read_manifest(dir: impl AsRef<Path>) -> Result<ShardManifest> {
+    let mut reader = BufReader::new(File::open(dir.as_ref().join(MANIFEST_FILE))?);
+    let fingerprint = read_u64(&mut reader)?;
+    let shard = read_u64(&mut reader)? as usize;
+    let num_vectors = read_u64(&mut reader)? as usize;
+    // the index type is only informative.
+    read_bytes(&mut reader)?;
+    Ok(ShardManifest {
+        fingerprint,
+        shard,
+        num_vectors,
+    })
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distributed_build() {
+        let plan = BuildPlan {
+            index_type: "hnsw".to_string(),
+            params: r#"{
+                "dtype": "float32",
+                "metric_type": "l2",
+                "dim": 1,
+                "hnsw": {
+                    "max_degree": 16,
+                    "ef_construction": 100
+                }
+            }"#
+            .to_string(),
+            dim: 1,
+            sharding: Sharding::Range {
+                bounds: vec![50, 100],
+            },
+        };
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = (0..100).map(|i| i as f32).collect();
+
+        let dir = tempdir::TempDir::new("test_distributed_build").unwrap();
+        let dirs: Vec<_> = (0..3)
+            .map(|shard| dir.path().join(shard.to_string()))
+            .collect();
+        for (shard, dir) in dirs.iter().enumerate() {
+            let failed_ids = plan
+                .build_shard(shard, &ids, &vectors, IndexOptions::default(), dir)
+                .unwrap();
+            assert!(failed_ids.is_empty());
+        }
+        assert_eq!(read_manifest(&dirs[1]).unwrap().num_vectors, 50);
+
+        let mut index = plan.merge(&dirs, IndexOptions::default()).unwrap();
+        assert_eq!(index.shard_sizes(), &[50, 50, 0]);
+        let output = index
+            .knn_search(&[49.8], 2, r#"{"hnsw": {"ef_search": 100}}"#)
+            .unwrap();
+        assert_eq!(output.ids, vec![50, 49]);
+
+        assert!(plan.merge(&dirs[..2], IndexOptions::default()).is_err());
+        assert!(plan
+            .merge(&[&dirs[0], &dirs[0], &dirs[1]], IndexOptions::default())
+            .is_err());
+        let other = BuildPlan {
+            dim: 2,
+            ..plan.clone()
+        };
+        assert_ne!(other.fingerprint(), plan.fingerprint());
+        assert!(other.merge(&dirs, IndexOptions::default()).is_err());
+    }
+}
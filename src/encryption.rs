@@ -0,0 +1,300 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encryption at rest of dumped indexes, with a caller-provided [`Cipher`], or AES-256-GCM
+//! with the `aes-gcm` feature.
+//!
+//! An encrypted dump starts with a magic number and the ID of the key it's encrypted with, so
+//! keys can be rotated while older dumps stay loadable. That header is authenticated along
+//! with the ciphertext.
+//!
+//! vsag only dumps to and loads from paths, so the plaintext goes through an anonymous
+//! in-memory file created with `memfd_create`, never through persistent storage, except for
+//! swap. Encrypted dumps are therefore only supported on Linux.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+
+use crate::codec::{read_bytes, write_bytes};
+use crate::error::{Error, ErrorType, Result};
+use crate::{IndexOptions, VsagIndex};
+
+/// Magic number at the start of an encrypted dump.
+const MAGIC: &[u8; 8] = b"VSAGENC1";
+
+/// `Cipher` encrypts and decrypts dumped indexes.
+pub trait Cipher: Send + Sync {
+    /// Returns the ID of the key used by [`Cipher::encrypt`].
+    fn key_id(&self) -> &str;
+
+    /// Encrypts `plaintext` with the key of [`Cipher::key_id`], authenticating `aad` too,
+    /// which is the header of the dump and stays in clear.
+    fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decrypts `ciphertext`, encrypted with the key of `key_id` and the same `aad`.
+    fn decrypt(&self, key_id: &str, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>>;
+}
+
+impl VsagIndex {
+    /// Dumps the index to the file at `path`, encrypted with `cipher`.
+    ///
+    /// The plaintext is held in memory, see the [module](self) docs, fails with
+    /// [`ErrorType::UnsupportedIndexOperation`] outside of Linux.
+    pub fn dump_encrypted(&self, path: &str, cipher: &dyn Cipher) -> Result<()> {
+        let mut plain = MemFile::new()?;
+        self.dump(&plain.path())?;
+        let mut plaintext = Vec::new();
+        plain.0.read_to_end(&mut plaintext)?;
+        drop(plain);
+
+        let header = header(cipher.key_id())?;
+        let ciphertext = cipher.encrypt(&plaintext, &header)?;
+        let mut file = File::create(path)?;
+        file.write_all(&header)?;
+        file.write_all(&ciphertext)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Loads an index dumped by [`VsagIndex::dump_encrypted`] with `options`, see
+    /// [`VsagIndex::load_with_options`].
+    ///
+    /// As for dumping, the plaintext is only held in memory while vsag loads it.
+    pub fn load_encrypted(
+        path: &str,
+        index_type: &str,
+        params: &str,
+        options: IndexOptions,
+        cipher: &dyn Cipher,
+    ) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(
+                ErrorType::InvalidBinary,
+                format!("{path} isn't an encrypted index"),
+            ));
+        }
+        let key_id = String::from_utf8(read_bytes(&mut reader)?)
+            .map_err(|_| Error::new(ErrorType::InvalidBinary, "invalid key id"))?;
+        let mut ciphertext = Vec::new();
+        reader.read_to_end(&mut ciphertext)?;
+        let plaintext = cipher.decrypt(&key_id, &ciphertext, &header(&key_id)?)?;
+
+        let mut plain = MemFile::new()?;
+        plain.0.write_all(&plaintext)?;
+        plain.0.seek(SeekFrom::Start(0))?;
+        drop(plaintext);
+        VsagIndex::load_with_options(&plain.path(), index_type, params, options)
+    }
+}
+
+/// Returns the header of a dump encrypted with the key of `key_id`.
+fn header(key_id: &str) -> Result<Vec<u8>> {
+    let mut header = MAGIC.to_vec();
+    write_bytes(&mut header, key_id.as_bytes())?;
+    Ok(header)
+}
+
+/// An anonymous file in memory, freed once closed, that vsag opens by path through
+/// `/proc/self/fd`.
+struct MemFile(File);
+
+impl MemFile {
+    #[cfg(target_os = "linux")]
+    fn new() -> Result<Self> {
+        use std::os::fd::FromRawFd;
+        use std::os::raw::{c_char, c_int, c_uint};
+
+        extern "C" {
+            fn memfd_create(name: *const c_char, flags: c_uint) -> c_int;
+        }
+        const MFD_CLOEXEC: c_uint = 1;
+
+        let fd = unsafe { memfd_create(c"vsag-plain".as_ptr(), MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        // SAFETY: `fd` was just created and is owned by nothing else.
+        Ok(MemFile(unsafe { File::from_raw_fd(fd) }))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn new() -> Result<Self> {
+        Err(Error::new(
+            ErrorType::UnsupportedIndexOperation,
+            "encrypted dumps need memfd_create, only available on Linux",
+        ))
+    }
+
+    fn path(&self) -> String {
+        format!("/proc/self/fd/{}", self.0.as_raw_fd())
+    }
+}
+
+/// AES-256-GCM [`Cipher`] over a set of keys, encrypting with the current one.
+///
+/// Each encryption uses a random 96-bit nonce, stored before the ciphertext.
+#[cfg(feature = "aes-gcm")]
+pub struct AesGcmCipher {
+    current: String,
+    keys: std::collections::HashMap<String, aes_gcm::Aes256Gcm>,
+}
+
+#[cfg(feature = "aes-gcm")]
+impl AesGcmCipher {
+    /// Creates a cipher encrypting with the 256-bit `key` of `key_id`.
+    pub fn new(key_id: impl Into<String>, key: &[u8; 32]) -> Self {
+        let mut cipher = AesGcmCipher {
+            current: String::new(),
+            keys: std::collections::HashMap::new(),
+        };
+        cipher.rotate(key_id, key);
+        cipher
+    }
+
+    /// Encrypts with the 256-bit `key` of `key_id` from now on, older keys are kept for
+    /// decryption.
+    pub fn rotate(&mut self, key_id: impl Into<String>, key: &[u8; 32]) {
+        use aes_gcm::KeyInit;
+
+        self.current = key_id.into();
+        self.keys
+            .insert(self.current.clone(), aes_gcm::Aes256Gcm::new(key.into()));
+    }
+
+    /// Adds the 256-bit `key` of `key_id` for decryption only.
+    pub fn add_key(&mut self, key_id: impl Into<String>, key: &[u8; 32]) {
+        use aes_gcm::KeyInit;
+
+        self.keys
+            .insert(key_id.into(), aes_gcm::Aes256Gcm::new(key.into()));
+    }
+}
+
+#[cfg(feature = "aes-gcm")]
+impl Cipher for AesGcmCipher {
+    fn key_id(&self) -> &str {
+        &self.current
+    }
+
+    fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        use aes_gcm::aead::{Aead, AeadCore, OsRng, Payload};
+
+        let nonce = aes_gcm::Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        let ciphertext = self.keys[&self.current]
+            .encrypt(&nonce, payload)
+            .map_err(|_| Error::new(ErrorType::InternalError, "failed to encrypt"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn decrypt(&self, key_id: &str, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        use aes_gcm::aead::{Aead, Payload};
+
+        let key = self.keys.get(key_id).ok_or_else(|| {
+            Error::new(ErrorType::InvalidArgument, format!("unknown key {key_id}"))
+        })?;
+        if ciphertext.len() < 12 {
+            return Err(Error::new(ErrorType::InvalidBinary, "ciphertext too short"));
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(12);
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        key.decrypt(nonce.into(), payload)
+            .map_err(|_| Error::new(ErrorType::InvalidBinary, "failed to decrypt"))
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+
+    /// XORs with the key ID, for testing only.
+    struct XorCipher(String);
+
+    impl Cipher for XorCipher {
+        fn key_id(&self) -> &str {
+            &self.0
+        }
+
+        fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+            self.decrypt(&self.0, plaintext, aad)
+        }
+
+        fn decrypt(&self, key_id: &str, ciphertext: &[u8], _aad: &[u8]) -> Result<Vec<u8>> {
+            let key = key_id.as_bytes();
+            Ok(ciphertext
+                .iter()
+                .enumerate()
+                .map(|(i, b)| b ^ key[i % key.len()])
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_dump_load_encrypted() {
+        let params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = VsagIndex::new("hnsw", params).unwrap();
+        index
+            .build(3, 2, &[1, 2, 3], &[0.0, 0.0, 1.0, 0.0, 5.0, 5.0])
+            .unwrap();
+
+        let dir = tempdir::TempDir::new("test_dump_load_encrypted").unwrap();
+        let path = dir.path().join("index").to_string_lossy().into_owned();
+        let cipher = XorCipher("key-1".to_string());
+        index.dump_encrypted(&path, &cipher).unwrap();
+        // only the encrypted dump is left.
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        assert!(std::fs::read(&path).unwrap().starts_with(MAGIC));
+
+        let loaded =
+            VsagIndex::load_encrypted(&path, "hnsw", params, IndexOptions::default(), &cipher)
+                .unwrap();
+        let output = loaded
+            .knn_search(&[5.0, 5.0], 1, r#"{"hnsw": {"ef_search": 100}}"#)
+            .unwrap();
+        assert_eq!(output.ids, vec![3]);
+
+        #[cfg(feature = "aes-gcm")]
+        {
+            let mut cipher = AesGcmCipher::new("old", &[1; 32]);
+            let ciphertext = cipher.encrypt(b"embeddings", b"header").unwrap();
+            cipher.rotate("new", &[2; 32]);
+            assert_eq!(cipher.key_id(), "new");
+            assert_eq!(
+                cipher.decrypt("old", &ciphertext, b"header").unwrap(),
+                b"embeddings"
+            );
+            assert!(cipher.decrypt("old", &ciphertext, b"tampered").is_err());
+            assert!(cipher.decrypt("new", &ciphertext, b"header").is_err());
+            assert!(cipher.decrypt("unknown", &ciphertext, b"header").is_err());
+        }
+    }
+}
@@ -54,4 +54,35 @@ pub enum ErrorType {
     MissingFile,
     /// the content of binary is invalid
     InvalidBinary,
+
+    // [rust-side errors]
+    /// a quota of the partition (tenant) is exceeded
+    QuotaExceeded,
+    /// a previous internal error may have left the index inconsistent, it can't be used anymore
+    IndexPoisoned,
+    /// an operation didn't complete within its timeout
+    Timeout,
+}
+
+impl Error {
+    /// Creates an error of `error_type` with `message`.
+    pub fn new(error_type: ErrorType, message: impl Into<String>) -> Self {
+        Error {
+            error_type,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        let error_type = match err.kind() {
+            std::io::ErrorKind::NotFound => ErrorType::MissingFile,
+            std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::InvalidData => {
+                ErrorType::InvalidBinary
+            }
+            _ => ErrorType::InternalError,
+        };
+        Error::new(error_type, err.to_string())
+    }
 }
@@ -0,0 +1,365 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evaluating recall and latency of an index over a grid of search parameters.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, ErrorType, Result};
+use crate::exact::exact_knn;
+use crate::index::VectorIndex;
+use crate::params::SearchParams;
+
+/// Largest `ef_search` tried by [`tune_ef_search`].
+pub const MAX_TUNED_EF_SEARCH: usize = 4096;
+
+/// Latency distribution of the queries of an evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyStats {
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Computes the distribution of `latencies`.
+    pub fn from_latencies(latencies: &[Duration]) -> Self {
+        if latencies.is_empty() {
+            return LatencyStats::default();
+        }
+
+        let mut sorted = latencies.to_vec();
+        sorted.sort_unstable();
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+        LatencyStats {
+            mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// Evaluation result of one set of search parameters.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EvalResult {
+    /// Search parameters in JSON format.
+    pub search_params: String,
+    /// Mean recall@k over all queries.
+    pub recall: f64,
+    /// Queries per second, searched one after another on a single thread.
+    pub qps: f64,
+    /// Latency distribution of the queries.
+    pub latency: LatencyStats,
+}
+
+/// Evaluation results of all search parameters, in the order they were given.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EvalReport {
+    /// Number of results searched for.
+    pub k: usize,
+    /// Number of queries.
+    pub num_queries: usize,
+    pub results: Vec<EvalResult>,
+}
+
+/// Outcome of [`verify_recall`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecallCheck {
+    /// Mean recall@k over all queries.
+    pub recall: f64,
+    /// Lowest recall@k of a single query.
+    pub worst_recall: f64,
+    /// Positions of the queries whose recall@k is below the minimum.
+    pub failed_queries: Vec<usize>,
+    /// Minimum mean recall@k required to pass.
+    pub min_recall: f64,
+}
+
+impl RecallCheck {
+    /// Returns `true` if the mean recall@k reaches the minimum.
+    pub fn passed(&self) -> bool {
+        self.recall >= self.min_recall
+    }
+}
+
+/// Checks the results of `index` for `sample_queries` against an exact search over `vectors`,
+/// e.g. as a smoke test right after loading an index in production.
+///
+/// `vectors` holds all the vectors of the index in a single slice, with their `ids`, and
+/// `sample_queries` query vectors of the same dimension. The exact search compares each query
+/// with every vector, so keep the sample small. Fails if the metric of the index is unknown.
+pub fn verify_recall(
+    index: &impl VectorIndex,
+    vectors: &[f32],
+    ids: &[i64],
+    sample_queries: &[f32],
+    k: usize,
+    search_params: &str,
+    min_recall: f64,
+) -> Result<RecallCheck> {
+    let metric = index.metric().ok_or_else(|| {
+        Error::new(
+            ErrorType::InvalidArgument,
+            "metric_type is required to verify recall",
+        )
+    })?;
+    let dim = vectors.len() / ids.len().max(1);
+    let ground_truth: Vec<Vec<i64>> = exact_knn(vectors, ids, sample_queries, dim, k, metric)?
+        .into_iter()
+        .map(|output| output.ids)
+        .collect();
+
+    let mut check = RecallCheck {
+        recall: 0.0,
+        worst_recall: 1.0,
+        failed_queries: Vec::new(),
+        min_recall,
+    };
+    for (pos, (query, expected)) in sample_queries
+        .chunks_exact(dim)
+        .zip(&ground_truth)
+        .enumerate()
+    {
+        let output = index.knn_search(query, k, search_params)?;
+        let recall = recall_at_k(&output.ids, expected, k);
+        check.recall += recall;
+        check.worst_recall = check.worst_recall.min(recall);
+        if recall < min_recall {
+            check.failed_queries.push(pos);
+        }
+    }
+    check.recall /= ground_truth.len().max(1) as f64;
+    Ok(check)
+}
+
+/// Returns the fraction of the first `k` entries of `ground_truth` found in `results`.
+pub fn recall_at_k(results: &[i64], ground_truth: &[i64], k: usize) -> f64 {
+    let expected = &ground_truth[..k.min(ground_truth.len())];
+    if expected.is_empty() {
+        return 1.0;
+    }
+
+    let found: HashSet<i64> = results.iter().take(k).copied().collect();
+    let hits = expected.iter().filter(|id| found.contains(id)).count();
+    hits as f64 / expected.len() as f64
+}
+
+/// Searches `queries` with each of `search_params`, and measures recall@k against
+/// `ground_truth` along with the latency of each query.
+///
+/// `queries` holds the query vectors of dimension `dim` in a single slice, and `ground_truth`
+/// holds the IDs of the exact nearest neighbors of each query, closest first.
+pub fn evaluate<S: AsRef<str>>(
+    index: &impl VectorIndex,
+    queries: &[f32],
+    dim: usize,
+    ground_truth: &[Vec<i64>],
+    k: usize,
+    search_params: &[S],
+) -> Result<EvalReport> {
+    if dim == 0 || queries.len() != ground_truth.len() * dim {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            "queries and ground truth have mismatched lengths",
+        ));
+    }
+
+    let mut results = Vec::with_capacity(search_params.len());
+    for params in search_params {
+        let params = params.as_ref();
+        let mut latencies = Vec::with_capacity(ground_truth.len());
+        let mut recall = 0.0;
+
+        let start = Instant::now();
+        for (query, expected) in queries.chunks_exact(dim).zip(ground_truth) {
+            let query_start = Instant::now();
+            let output = index.knn_search(query, k, params)?;
+            latencies.push(query_start.elapsed());
+            recall += recall_at_k(&output.ids, expected, k);
+        }
+        let elapsed = start.elapsed();
+
+        let num_queries = ground_truth.len().max(1) as f64;
+        results.push(EvalResult {
+            search_params: params.to_string(),
+            recall: recall / num_queries,
+            qps: ground_truth.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            latency: LatencyStats::from_latencies(&latencies),
+        });
+    }
+
+    Ok(EvalReport {
+        k,
+        num_queries: ground_truth.len(),
+        results,
+    })
+}
+
+/// Returns the smallest `ef_search` whose mean recall@k over `queries` reaches `target_recall`,
+/// `None` if even [`MAX_TUNED_EF_SEARCH`] doesn't.
+///
+/// `ef_search` is doubled from `k` until the target is met, then binary searched in the last
+/// interval, so recall is assumed to grow with `ef_search`. Other search parameters are the
+/// defaults of the index type, see [`SearchParams::default_for`]. `queries` and `ground_truth`
+/// are the same as in [`evaluate`].
+pub fn tune_ef_search(
+    index: &impl VectorIndex,
+    queries: &[f32],
+    dim: usize,
+    ground_truth: &[Vec<i64>],
+    k: usize,
+    target_recall: f64,
+) -> Result<Option<usize>> {
+    let mut params = SearchParams::default_for(index.index_type()).ok_or_else(|| {
+        Error::new(
+            ErrorType::UnsupportedIndex,
+            format!("no default search params for {}", index.index_type()),
+        )
+    })?;
+    let mut recall_of = |ef_search: usize| -> Result<f64> {
+        params.set_ef_search(ef_search);
+        let report = evaluate(index, queries, dim, ground_truth, k, &[params.to_json()])?;
+        Ok(report.results[0].recall)
+    };
+
+    // invariant: recall of `low` is below the target, recall of `high` reaches it.
+    let mut low = 0;
+    let mut high = k.clamp(1, MAX_TUNED_EF_SEARCH);
+    while recall_of(high)? < target_recall {
+        if high == MAX_TUNED_EF_SEARCH {
+            return Ok(None);
+        }
+        low = high;
+        high = (high * 2).min(MAX_TUNED_EF_SEARCH);
+    }
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if recall_of(mid)? < target_recall {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(Some(high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "pure-rust"))]
+    use crate::VsagIndex;
+
+    #[test]
+    fn test_recall_at_k() {
+        assert_eq!(recall_at_k(&[1, 2, 3], &[1, 2, 3], 3), 1.0);
+        assert_eq!(recall_at_k(&[1, 5, 3, 2], &[1, 2, 3, 4], 2), 0.5);
+        assert_eq!(recall_at_k(&[], &[1, 2], 2), 0.0);
+        assert_eq!(recall_at_k(&[1], &[], 2), 1.0);
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        let stats = LatencyStats::from_latencies(&latencies);
+        assert_eq!(stats.p50, Duration::from_millis(51));
+        assert_eq!(stats.p99, Duration::from_millis(99));
+        assert_eq!(stats.max, Duration::from_millis(100));
+        assert_eq!(LatencyStats::from_latencies(&[]), LatencyStats::default());
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_evaluate() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        index.build(100, 1, &ids, &vectors).unwrap();
+
+        let queries = [10.1, 50.1];
+        let ground_truth = vec![vec![10, 11], vec![50, 51]];
+        let search_params = [
+            r#"{"hnsw": {"ef_search": 10}}"#,
+            r#"{"hnsw": {"ef_search": 100}}"#,
+        ];
+        let report = evaluate(&index, &queries, 1, &ground_truth, 2, &search_params).unwrap();
+        assert_eq!(report.num_queries, 2);
+        assert_eq!(report.results.len(), 2);
+        assert_eq!(report.results[1].search_params, search_params[1]);
+        assert_eq!(report.results[1].recall, 1.0);
+        assert!(report.results[1].qps > 0.0);
+
+        let ef_search = tune_ef_search(&index, &queries, 1, &ground_truth, 2, 1.0).unwrap();
+        assert!(ef_search.is_some_and(|ef| ef <= 2));
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_verify_recall() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        index.build(100, 1, &ids, &vectors).unwrap();
+
+        let queries = [10.1, 50.1];
+        let check =
+            verify_recall(&index, &vectors, &ids, &queries, 2, search_params, 0.99).unwrap();
+        assert!(check.passed());
+        assert_eq!(check.recall, 1.0);
+        assert_eq!(check.worst_recall, 1.0);
+        assert!(check.failed_queries.is_empty());
+
+        // vectors that don't match the index.
+        let other_ids: Vec<i64> = ids.iter().rev().copied().collect();
+        let check = verify_recall(
+            &index,
+            &vectors,
+            &other_ids,
+            &queries,
+            2,
+            search_params,
+            0.99,
+        )
+        .unwrap();
+        assert!(!check.passed());
+        assert_eq!(check.worst_recall, 0.0);
+        assert_eq!(check.failed_queries, vec![0, 1]);
+    }
+}
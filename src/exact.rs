@@ -0,0 +1,127 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exact brute-force k-NN search, e.g. to generate ground truth for [`crate::eval`].
+
+use std::collections::BinaryHeap;
+use std::thread;
+
+use crate::error::{Error, ErrorType, Result};
+use crate::metric::Metric;
+use crate::topk::ScoredPoint;
+use crate::KnnSearchOutput;
+
+/// Searches the exact `k` nearest neighbors of each query by comparing it with every vector of
+/// the dataset.
+///
+/// `dataset` and `queries` hold vectors of dimension `dim` in a single slice, and `ids` holds the
+/// ID of each vector of `dataset`. Distances are computed the same way as vsag does for `metric`.
+/// Queries are spread over all available cores.
+///
+/// Returns one output per query, closest first.
+pub fn exact_knn(
+    dataset: &[f32],
+    ids: &[i64],
+    queries: &[f32],
+    dim: usize,
+    k: usize,
+    metric: Metric,
+) -> Result<Vec<KnnSearchOutput>> {
+    if dim == 0
+        || dataset.len() != ids.len() * dim
+        || !queries.chunks_exact(dim).remainder().is_empty()
+    {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            "dataset, ids and queries have mismatched lengths",
+        ));
+    }
+
+    let num_queries = queries.len() / dim;
+    let num_threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(num_queries)
+        .max(1);
+    let queries_per_thread = num_queries.div_ceil(num_threads).max(1);
+
+    Ok(thread::scope(|s| {
+        let handles: Vec<_> = queries
+            .chunks(queries_per_thread * dim)
+            .map(|queries| {
+                s.spawn(move || {
+                    queries
+                        .chunks_exact(dim)
+                        .map(|query| search_one(dataset, ids, query, k, metric))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("exact search thread panicked"))
+            .collect()
+    }))
+}
+
+/// Searches the exact `k` nearest neighbors of a single `query`, see [`exact_knn`].
+pub(crate) fn search_one(
+    dataset: &[f32],
+    ids: &[i64],
+    query: &[f32],
+    k: usize,
+    metric: Metric,
+) -> KnnSearchOutput {
+    // max-heap of the closest points found so far, the farthest on top.
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for (vector, &id) in dataset.chunks_exact(query.len()).zip(ids) {
+        let point = ScoredPoint {
+            id,
+            distance: metric.distance_between(query, vector),
+        };
+        if heap.len() < k {
+            heap.push(point);
+        } else if heap.peek().is_some_and(|farthest| point < *farthest) {
+            heap.pop();
+            heap.push(point);
+        }
+    }
+    heap.into_sorted_vec().into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_knn() {
+        let dataset: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let ids: Vec<i64> = (100..200).collect();
+        let queries = [10.2, 0.0, 99.0];
+
+        let outputs = exact_knn(&dataset, &ids, &queries, 1, 3, Metric::L2).unwrap();
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[0].ids, vec![110, 111, 109]);
+        assert_eq!(outputs[1].ids, vec![100, 101, 102]);
+        assert_eq!(outputs[1].distances, vec![0.0, 1.0, 4.0]);
+        assert_eq!(outputs[2].ids, vec![199, 198, 197]);
+
+        assert!(exact_knn(&dataset, &ids[1..], &queries, 1, 3, Metric::L2).is_err());
+        assert!(
+            exact_knn(&dataset, &ids, &queries, 1, 0, Metric::L2).unwrap()[0]
+                .ids
+                .is_empty()
+        );
+    }
+}
@@ -12,8 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::os::raw::{c_char, c_int, c_void};
+use std::os::raw::c_int;
+#[cfg(not(any(feature = "pure-rust", feature = "mock-ffi")))]
+use std::os::raw::{c_char, c_void};
 
+#[cfg(feature = "mock-ffi")]
+pub(crate) use crate::mock::{
+    build_index, create_index, dump_index, free_error, free_f32_vector, free_i64_vector,
+    free_index, knn_search_index, load_index,
+};
+
+#[cfg(all(feature = "pure-rust", not(feature = "mock-ffi")))]
+pub(crate) use self::unavailable::{
+    build_index, create_index, dump_index, free_error, free_f32_vector, free_i64_vector,
+    free_index, knn_search_index, load_index,
+};
+
+#[cfg(not(any(feature = "pure-rust", feature = "mock-ffi")))]
 extern "C" {
     pub fn create_index(
         in_index_type: *const c_char,
@@ -61,6 +76,97 @@ extern "C" {
     pub fn free_f32_vector(vector: *const f32);
 }
 
+/// The vsag C API with the `pure-rust` feature, which doesn't link libvsag: creating or loading
+/// an index fails, so no other function can be reached.
+#[cfg(all(feature = "pure-rust", not(feature = "mock-ffi")))]
+mod unavailable {
+    use std::os::raw::{c_char, c_void};
+
+    use super::CError;
+    use crate::error::ErrorType;
+
+    const NO_INDEX: &str = "no vsag index exists with the `pure-rust` feature";
+
+    fn unavailable() -> *const CError {
+        let mut message = [0; 256];
+        let text = b"vsag isn't available with the `pure-rust` feature, use flat::FlatIndex";
+        message[..text.len()].copy_from_slice(text);
+        Box::into_raw(Box::new(CError {
+            type_: ErrorType::UnsupportedIndex as _,
+            message,
+        }))
+    }
+
+    pub(crate) unsafe extern "C" fn create_index(
+        _in_index_type: *const c_char,
+        _in_parameters: *const c_char,
+
+        _out_index_ptr: *mut *const c_void,
+    ) -> *const CError {
+        unavailable()
+    }
+
+    pub(crate) unsafe extern "C" fn load_index(
+        _in_file_path: *const c_char,
+        _in_index_type: *const c_char,
+        _in_parameters: *const c_char,
+
+        _out_index_ptr: *mut *const c_void,
+    ) -> *const CError {
+        unavailable()
+    }
+
+    pub(crate) unsafe extern "C" fn build_index(
+        _in_index_ptr: *const c_void,
+        _in_num_vectors: usize,
+        _in_dim: usize,
+        _in_ids: *const i64,
+        _in_vectors: *const f32,
+
+        _out_failed_ids: *mut *const i64,
+        _out_num_failed: *mut usize,
+    ) -> *const CError {
+        unreachable!("{NO_INDEX}")
+    }
+
+    pub(crate) unsafe extern "C" fn knn_search_index(
+        _in_index_ptr: *const c_void,
+        _in_dim: usize,
+        _in_query_vector: *const f32,
+        _in_k: usize,
+        _in_search_parameters: *const c_char,
+
+        _out_ids: *mut *const i64,
+        _out_distances: *mut *const f32,
+        _out_num_results: *mut usize,
+    ) -> *const CError {
+        unreachable!("{NO_INDEX}")
+    }
+
+    pub(crate) unsafe extern "C" fn dump_index(
+        _in_index_ptr: *const c_void,
+        _in_file_path: *const c_char,
+    ) -> *const CError {
+        unreachable!("{NO_INDEX}")
+    }
+
+    pub(crate) unsafe extern "C" fn free_index(_index_ptr: *const c_void) {
+        unreachable!("{NO_INDEX}")
+    }
+
+    pub(crate) unsafe extern "C" fn free_error(error: *const CError) {
+        drop(Box::from_raw(error as *mut CError));
+    }
+
+    pub(crate) unsafe extern "C" fn free_i64_vector(_vector: *const i64) {
+        unreachable!("{NO_INDEX}")
+    }
+
+    pub(crate) unsafe extern "C" fn free_f32_vector(_vector: *const f32) {
+        unreachable!("{NO_INDEX}")
+    }
+}
+
 #[repr(C)]
 pub struct CError {
     pub type_: c_int,
@@ -86,24 +192,113 @@ pub fn from_c_error(err: *const CError) -> crate::error::Error {
     error
 }
 
-pub fn from_c_i64_vector(vector: *const i64, len: usize) -> Vec<i64> {
-    let slice = unsafe { std::slice::from_raw_parts(vector, len) };
-    let vec = slice.to_vec();
-    unsafe {
-        free_i64_vector(vector);
+/// Element type of the vectors allocated by vsag, freed by its own function.
+pub trait CElement: Sized {
+    /// Frees `vector`, allocated by vsag.
+    ///
+    /// # Safety
+    ///
+    /// `vector` must have been returned by vsag and not freed yet.
+    unsafe fn free(vector: *const Self);
+}
+
+impl CElement for i64 {
+    unsafe fn free(vector: *const Self) {
+        free_i64_vector(vector)
     }
-    vec
 }
 
-pub fn from_c_f32_vector(vector: *const f32, len: usize) -> Vec<f32> {
-    let slice = unsafe { std::slice::from_raw_parts(vector, len) };
-    let vec = slice.to_vec();
-    unsafe {
-        free_f32_vector(vector);
+impl CElement for f32 {
+    unsafe fn free(vector: *const Self) {
+        free_f32_vector(vector)
+    }
+}
+
+/// A vector allocated by vsag, borrowed as a slice without copying and freed on drop, exactly
+/// once.
+pub struct CVec<T: CElement> {
+    ptr: *const T,
+    len: usize,
+}
+
+/// The vector is a plain heap allocation owned by this value, so it's sendable.
+unsafe impl<T: CElement + Send> Send for CVec<T> {}
+
+impl<T: CElement> CVec<T> {
+    /// Takes ownership of `len` elements at `ptr`, which may be null if `len` is 0.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or have been returned by vsag with `len` elements, and must not be
+    /// freed elsewhere.
+    pub unsafe fn from_raw(ptr: *const T, len: usize) -> Self {
+        CVec { ptr, len }
+    }
+}
+
+impl<T: CElement> std::ops::Deref for CVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        if self.ptr.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T: CElement> Drop for CVec<T> {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            unsafe { T::free(self.ptr) }
+        }
     }
-    vec
 }
 
 pub fn to_c_string(s: &str) -> std::ffi::CString {
     std::ffi::CString::new(s).expect("0 byte in string")
 }
+
+thread_local! {
+    /// Buffer of [`with_c_string`], reused by the calls on the same thread.
+    static C_STRING: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Calls `f` with `s` as a C string, without allocating on the hot path: the per-thread buffer
+/// is reused, and not even rewritten if `s` is the same as in the previous call.
+///
+/// Panics if `s` contains a 0 byte, like [`to_c_string`].
+pub fn with_c_string<R>(s: &str, f: impl FnOnce(&std::ffi::CStr) -> R) -> R {
+    C_STRING.with(|buf| {
+        // nested calls, e.g. from hooks, fall back to allocating.
+        let Ok(mut buf) = buf.try_borrow_mut() else {
+            return f(&to_c_string(s));
+        };
+        if buf.len() != s.len() + 1 || &buf[..s.len()] != s.as_bytes() {
+            assert!(!s.as_bytes().contains(&0), "0 byte in string");
+            buf.clear();
+            buf.extend_from_slice(s.as_bytes());
+            buf.push(0);
+        }
+        let c_str = std::ffi::CStr::from_bytes_with_nul(&buf).expect("checked for 0 bytes");
+        f(c_str)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_c_string() {
+        let outer = with_c_string(r#"{"hnsw": {}}"#, |outer| {
+            // nested calls don't clobber the outer string.
+            let inner = with_c_string("inner", |inner| inner.to_owned());
+            assert_eq!(inner.to_str(), Ok("inner"));
+            outer.to_owned()
+        });
+        assert_eq!(outer.to_str(), Ok(r#"{"hnsw": {}}"#));
+        assert_eq!(with_c_string("", |s| s.to_bytes().len()), 0);
+        assert_eq!(with_c_string("ab", |s| s.to_owned()).to_str(), Ok("ab"));
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Indexes and collections written by released versions of the crate, under
+//! `src/fixtures/<version>`, that must stay readable so upgrades don't silently break data on
+//! disk.
+//!
+//! Fixtures of the code not released yet are written to `src/fixtures/unreleased`:
+//!
+//! ```text
+//! cargo test --lib fixtures::write_fixtures -- --ignored
+//! ```
+//!
+//! When tagging a release, rewrite them with the libvsag the release ships with and rename the
+//! directory after the version. No release has fixtures yet: the ones in `unreleased` only hold
+//! the [`FlatIndex`] and the points of the [`Collection`], without the vsag dumps, so nothing
+//! checks yet that [`VsagIndex::load`] reads older files.
+//!
+//! [`test_fixtures`] opens the fixtures of every directory with the current code. The data and
+//! parameters below must never change, since older fixtures were written with them.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::collection::{Collection, CollectionConfig};
+use crate::flat::FlatIndex;
+use crate::{IndexOptions, VsagIndex};
+
+const NUM_POINTS: i64 = 20;
+const HNSW_PARAMS: &str = r#"{
+    "dtype": "float32",
+    "metric_type": "l2",
+    "dim": 2,
+    "hnsw": {
+        "max_degree": 16,
+        "ef_construction": 100
+    }
+}"#;
+const HNSW_SEARCH_PARAMS: &str = r#"{"hnsw": {"ef_search": 100}}"#;
+const FLAT_PARAMS: &str = r#"{"dtype": "float32", "metric_type": "l2", "dim": 2}"#;
+/// File name of a dumped [`VsagIndex`] of type `hnsw`.
+const HNSW_FILE: &str = "hnsw.index";
+/// File name of a dumped [`FlatIndex`].
+const FLAT_FILE: &str = "flat.index";
+/// Directory name of a saved [`Collection`].
+const COLLECTION_DIR: &str = "collection";
+
+fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/fixtures")
+}
+
+/// Returns the ids and vectors of the fixtures, the vector of `id` being `[id, id % 3]`.
+fn points() -> (Vec<i64>, Vec<f32>) {
+    let ids: Vec<i64> = (0..NUM_POINTS).collect();
+    let vectors = ids
+        .iter()
+        .flat_map(|&id| [id as f32, (id % 3) as f32])
+        .collect();
+    (ids, vectors)
+}
+
+fn collection_config() -> CollectionConfig {
+    CollectionConfig {
+        index_type: "hnsw".to_string(),
+        params: HNSW_PARAMS.to_string(),
+        dim: 2,
+        options: IndexOptions::default(),
+    }
+}
+
+/// Writes the fixtures of the code not released yet.
+#[test]
+#[ignore]
+fn write_fixtures() {
+    let dir = fixtures_dir().join("unreleased");
+    fs::create_dir_all(&dir).unwrap();
+    let (ids, vectors) = points();
+
+    let index = VsagIndex::new("hnsw", HNSW_PARAMS).unwrap();
+    index.build(ids.len(), 2, &ids, &vectors).unwrap();
+    index
+        .dump(&dir.join(HNSW_FILE).display().to_string())
+        .unwrap();
+
+    let index = FlatIndex::new("flat", FLAT_PARAMS).unwrap();
+    index.build(ids.len(), 2, &ids, &vectors).unwrap();
+    index
+        .dump(&dir.join(FLAT_FILE).display().to_string())
+        .unwrap();
+
+    let mut collection = Collection::new(collection_config());
+    for (&id, vector) in ids.iter().zip(vectors.chunks_exact(2)) {
+        collection
+            .upsert(id, vector.to_vec(), id.to_string().into_bytes())
+            .unwrap();
+    }
+    collection.commit().unwrap();
+    collection.save(dir.join(COLLECTION_DIR)).unwrap();
+}
+
+/// Opens the fixtures of every directory found, each may lack some of them.
+#[test]
+fn test_fixtures() {
+    let query = [5.1, 2.0];
+    for entry in fs::read_dir(fixtures_dir()).unwrap() {
+        let dir = entry.unwrap().path();
+        let version = dir.display();
+
+        let path = dir.join(HNSW_FILE);
+        if path.exists() {
+            let index = VsagIndex::load(&path.display().to_string(), "hnsw", HNSW_PARAMS)
+                .unwrap_or_else(|e| panic!("failed to load {version}/{HNSW_FILE}: {e:?}"));
+            let output = index.knn_search(&query, 1, HNSW_SEARCH_PARAMS).unwrap();
+            assert_eq!(output.ids, vec![5], "{version}/{HNSW_FILE}");
+        }
+
+        let path = dir.join(FLAT_FILE);
+        if path.exists() {
+            let index = FlatIndex::load(&path.display().to_string(), "flat", FLAT_PARAMS)
+                .unwrap_or_else(|e| panic!("failed to load {version}/{FLAT_FILE}: {e:?}"));
+            let output = index.knn_search(&query, 1, "").unwrap();
+            assert_eq!(output.ids, vec![5], "{version}/{FLAT_FILE}");
+        }
+
+        let path = dir.join(COLLECTION_DIR);
+        if path.exists() {
+            let collection = Collection::open(&path)
+                .unwrap_or_else(|e| panic!("failed to open {version}/{COLLECTION_DIR}: {e:?}"));
+            assert_eq!(collection.len(), NUM_POINTS as usize);
+            assert_eq!(collection.config().params, HNSW_PARAMS);
+            let hits = collection.search(&query, 1, HNSW_SEARCH_PARAMS).unwrap();
+            assert_eq!(hits[0].id, 5, "{version}/{COLLECTION_DIR}");
+            assert_eq!(hits[0].payload, b"5");
+        }
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A pure-Rust flat index, searched exhaustively, with the same API as [`VsagIndex`].
+//!
+//! It doesn't need libvsag, so with the `pure-rust` feature, which skips building and linking
+//! libvsag, tests and targets where libvsag isn't available can still run code paths built on
+//! it. Creating or loading a [`VsagIndex`] fails with [`ErrorType::UnsupportedIndex`] then.
+//!
+//! [`ErrorType::UnsupportedIndex`]: crate::error::ErrorType::UnsupportedIndex
+//! [`VsagIndex`]: crate::VsagIndex
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::sync::OnceLock;
+
+use crate::codec::{read_f32s, read_u64, write_f32s, write_u64};
+use crate::error::{Error, ErrorType, Result};
+use crate::exact::search_one;
+use crate::metric::{json_u64_field, Metric};
+use crate::KnnSearchOutput;
+
+/// `FlatIndex` stores vectors as is and compares queries with every one of them, so results are
+/// exact and search parameters are ignored.
+pub struct FlatIndex {
+    index_type: String,
+    dim: usize,
+    metric: Metric,
+    /// IDs and vectors, set once by [`FlatIndex::build`].
+    data: OnceLock<(Vec<i64>, Vec<f32>)>,
+}
+
+impl FlatIndex {
+    /// Creates a new flat index.
+    ///
+    /// `params` are the same as the ones of [`VsagIndex::new`](crate::VsagIndex::new), of which
+    /// only `dim` and `metric_type` are used. `index_type` is only reported by
+    /// [`FlatIndex::index_type`].
+    pub fn new(index_type: &str, params: &str) -> Result<Self> {
+        let dim = json_u64_field(params, "dim")
+            .filter(|&dim| dim > 0)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorType::InvalidArgument,
+                    "missing or invalid dim in params",
+                )
+            })?;
+        let metric = Metric::from_params(params).ok_or_else(|| {
+            Error::new(
+                ErrorType::InvalidArgument,
+                "missing or invalid metric_type in params",
+            )
+        })?;
+
+        Ok(FlatIndex {
+            index_type: index_type.to_string(),
+            dim: dim as usize,
+            metric,
+            data: OnceLock::new(),
+        })
+    }
+
+    /// Builds index with all vectors, see [`VsagIndex::build`](crate::VsagIndex::build).
+    ///
+    /// Returns IDs of vectors that failed to be added to the index, which is always empty.
+    pub fn build(
+        &self,
+        num_vectors: usize,
+        dim: usize,
+        ids: &[i64],
+        vectors: &[f32],
+    ) -> Result<Vec<i64>> {
+        self.check_dim(dim)?;
+        if ids.len() != num_vectors || vectors.len() != num_vectors * dim {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "ids and vectors have mismatched lengths",
+            ));
+        }
+
+        self.data
+            .set((ids.to_vec(), vectors.to_vec()))
+            .map_err(|_| Error::new(ErrorType::BuildTwice, "index has been built"))?;
+        Ok(Vec::new())
+    }
+
+    /// Searches for the `k` nearest neighbors of the `query_vector`, see
+    /// [`VsagIndex::knn_search`](crate::VsagIndex::knn_search).
+    pub fn knn_search(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        _search_params: &str,
+    ) -> Result<KnnSearchOutput> {
+        self.check_dim(query_vector.len())?;
+        if k == 0 {
+            return Err(Error::new(ErrorType::InvalidArgument, "k must be positive"));
+        }
+        let Some((ids, vectors)) = self.data.get() else {
+            return Ok(KnnSearchOutput {
+                ids: Vec::new(),
+                distances: Vec::new(),
+            });
+        };
+        Ok(search_one(vectors, ids, query_vector, k, self.metric))
+    }
+
+    /// Returns the type of the index given to [`FlatIndex::new`].
+    pub fn index_type(&self) -> &str {
+        &self.index_type
+    }
+
+    /// Returns the metric type of the index.
+    pub fn metric(&self) -> Option<Metric> {
+        Some(self.metric)
+    }
+
+    /// Dumps the index to the file at `path`.
+    pub fn dump(&self, path: &str) -> Result<()> {
+        let Some((ids, vectors)) = self.data.get() else {
+            return Err(Error::new(ErrorType::IndexEmpty, "index is empty"));
+        };
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_u64(&mut writer, ids.len() as u64)?;
+        for &id in ids {
+            write_u64(&mut writer, id as u64)?;
+        }
+        write_f32s(&mut writer, vectors)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads an index dumped by [`FlatIndex::dump`] from the file at `path`.
+    ///
+    /// `index_type` and `params` should be the same as the ones used to create the index.
+    pub fn load(path: &str, index_type: &str, params: &str) -> Result<Self> {
+        let index = Self::new(index_type, params)?;
+
+        let mut reader = BufReader::new(File::open(path)?);
+        let num_vectors = read_u64(&mut reader)? as usize;
+        let ids = (0..num_vectors)
+            .map(|_| read_u64(&mut reader).map(|id| id as i64))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let vectors = read_f32s(&mut reader)?;
+        if vectors.len() != num_vectors * index.dim {
+            return Err(Error::new(
+                ErrorType::InvalidBinary,
+                "number of vectors doesn't match the number of ids",
+            ));
+        }
+
+        index.build(num_vectors, index.dim, &ids, &vectors)?;
+        Ok(index)
+    }
+
+    fn check_dim(&self, dim: usize) -> Result<()> {
+        if dim != self.dim {
+            return Err(Error::new(
+                ErrorType::DimensionNotEqual,
+                format!(
+                    "dimension {dim} doesn't match the index dimension {}",
+                    self.dim
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_index() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = FlatIndex::new("hnsw", con_params).unwrap();
+        let ids: Vec<i64> = (0..10).collect();
+        let vectors: Vec<f32> = (0..10).flat_map(|i| [i as f32, 0.0]).collect();
+        assert!(index.build(10, 2, &ids, &vectors).unwrap().is_empty());
+        assert!(index.build(10, 2, &ids, &vectors).is_err());
+        assert!(index.knn_search(&[1.0], 2, "").is_err());
+
+        let output = index.knn_search(&[3.2, 0.0], 2, "").unwrap();
+        assert_eq!(output.ids, vec![3, 4]);
+
+        let dir = tempdir::TempDir::new("test_flat_index").unwrap();
+        let path = dir.path().join("index");
+        let path = path.to_str().unwrap();
+        index.dump(path).unwrap();
+        let index = FlatIndex::load(path, "hnsw", con_params).unwrap();
+        let loaded = index.knn_search(&[3.2, 0.0], 2, "").unwrap();
+        assert_eq!(loaded.ids, output.ids);
+        assert_eq!(loaded.distances, output.distances);
+    }
+
+    #[cfg(all(feature = "pure-rust", not(feature = "mock-ffi")))]
+    #[test]
+    fn test_vsag_unavailable() {
+        let params = r#"{"dtype": "float32", "metric_type": "l2", "dim": 2}"#;
+        let err = crate::VsagIndex::new("hnsw", params).map(drop).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::UnsupportedIndex));
+        let err = crate::VsagIndex::load("index", "hnsw", params)
+            .map(drop)
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::UnsupportedIndex));
+    }
+}
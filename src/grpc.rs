@@ -0,0 +1,255 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A gRPC service over named [`Collection`](crate::collection::Collection)s, enabled by the
+//! `grpc` feature.
+//!
+//! Inserts are committed before returning, so searches never rebuild an index. Commits, saves and
+//! searches run on tokio's blocking threads.
+//!
+//! The protobuf schema is `proto/vsag.proto`, compiled at build time, which requires `protoc`.
+//! The service shares [`Collections`] with the HTTP [`server`](crate::server), so both can be
+//! served side by side over the same indexes.
+//!
+//! ```ignore
+//! tonic::transport::Server::builder()
+//!     .add_service(vsag::grpc::service(collections))
+//!     .serve(addr)
+//!     .await?;
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use tonic::{Request, Response, Status};
+
+use crate::collection::CollectionConfig;
+use crate::error::{Error, ErrorType};
+use crate::params::SearchParams;
+use crate::server::{checked_collection, Collections};
+use crate::IndexOptions;
+
+/// Messages and service stubs generated from `proto/vsag.proto`.
+pub mod proto {
+    tonic::include_proto!("vsag.v1");
+}
+
+use proto::vector_store_server::{VectorStore, VectorStoreServer};
+
+/// Implementation of the `VectorStore` service.
+pub struct VectorStoreService {
+    collections: Arc<Collections>,
+}
+
+impl VectorStoreService {
+    pub fn new(collections: Arc<Collections>) -> Self {
+        VectorStoreService { collections }
+    }
+}
+
+/// Creates the `VectorStore` service over `collections`, to be added to a tonic server.
+pub fn service(collections: Arc<Collections>) -> VectorStoreServer<VectorStoreService> {
+    VectorStoreServer::new(VectorStoreService::new(collections))
+}
+
+fn to_status(err: Error) -> Status {
+    match err.error_type {
+        ErrorType::InvalidArgument | ErrorType::DimensionNotEqual | ErrorType::UnsupportedIndex => {
+            Status::invalid_argument(err.message)
+        }
+        ErrorType::MissingFile => Status::not_found(err.message),
+        ErrorType::QuotaExceeded => Status::resource_exhausted(err.message),
+        _ => Status::internal(err.message),
+    }
+}
+
+fn not_found(name: &str) -> Status {
+    Status::not_found(format!("collection {name} not found"))
+}
+
+#[tonic::async_trait]
+impl VectorStore for VectorStoreService {
+    async fn create_index(
+        &self,
+        request: Request<proto::CreateIndexRequest>,
+    ) -> Result<Response<proto::CreateIndexResponse>, Status> {
+        let req = request.into_inner();
+        let config = CollectionConfig {
+            index_type: req.index_type,
+            params: req.params,
+            dim: req.dim as usize,
+            options: IndexOptions {
+                normalize: req.normalize,
+                ..Default::default()
+            },
+        };
+        let collection = checked_collection(config).map_err(to_status)?;
+
+        let mut collections = self.collections.lock();
+        if collections.contains_key(&req.name) {
+            return Err(Status::already_exists(format!(
+                "collection {} already exists",
+                req.name
+            )));
+        }
+        collections.insert(req.name, Arc::new(Mutex::new(collection)));
+        Ok(Response::new(proto::CreateIndexResponse {}))
+    }
+
+    async fn insert(
+        &self,
+        request: Request<proto::InsertRequest>,
+    ) -> Result<Response<proto::InsertResponse>, Status> {
+        let proto::InsertRequest { name, points } = request.into_inner();
+        // All the points or none of them, committed right away so searches see them.
+        let failed_ids = self
+            .collections
+            .with_collection(&name, move |collection| {
+                for point in &points {
+                    collection.check_point(point.id, &point.vector)?;
+                }
+                for point in points {
+                    collection.upsert(point.id, point.vector, point.payload)?;
+                }
+                collection.commit()
+            })
+            .await
+            .ok_or_else(|| not_found(&name))?
+            .map_err(to_status)?;
+        Ok(Response::new(proto::InsertResponse { failed_ids }))
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SearchResponse>, Status> {
+        let req = request.into_inner();
+        let name = req.name.clone();
+        self.collections
+            .with_collection(&name, move |collection| {
+                let params = match req.params {
+                    Some(params) => params,
+                    None => SearchParams::default_for(&collection.config().index_type)
+                        .ok_or_else(|| {
+                            Status::invalid_argument(
+                                "search params are required for this index type",
+                            )
+                        })?
+                        .to_json(),
+                };
+                let hits = collection
+                    .search(&req.vector, req.k as usize, &params)
+                    .map_err(to_status)?
+                    .into_iter()
+                    .map(|hit| proto::SearchHit {
+                        id: hit.id,
+                        distance: hit.distance,
+                        payload: hit.payload,
+                    })
+                    .collect();
+                Ok(Response::new(proto::SearchResponse { hits }))
+            })
+            .await
+            .ok_or_else(|| not_found(&name))?
+    }
+
+    async fn dump(
+        &self,
+        request: Request<proto::DumpRequest>,
+    ) -> Result<Response<proto::DumpResponse>, Status> {
+        let proto::DumpRequest { name, dir } = request.into_inner();
+        self.collections
+            .with_collection(&name, move |collection| {
+                collection.commit()?;
+                collection.save(&dir)
+            })
+            .await
+            .ok_or_else(|| not_found(&name))?
+            .map_err(to_status)?;
+        Ok(Response::new(proto::DumpResponse {}))
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grpc_service() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let service = VectorStoreService::new(Arc::new(Collections::default()));
+        let name = || "docs".to_string();
+
+        rt.block_on(async {
+            let req = proto::CreateIndexRequest {
+                name: name(),
+                index_type: "hnsw".to_string(),
+                params: r#"{
+                    "dtype": "float32",
+                    "metric_type": "l2",
+                    "dim": 2,
+                    "hnsw": {"max_degree": 16, "ef_construction": 100}
+                }"#
+                .to_string(),
+                dim: 2,
+                normalize: false,
+            };
+            service
+                .create_index(Request::new(req.clone()))
+                .await
+                .unwrap();
+            let status = service.create_index(Request::new(req)).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::AlreadyExists);
+
+            let points = (0..10)
+                .map(|i| proto::Point {
+                    id: i,
+                    vector: vec![i as f32, 0.0],
+                    payload: format!("doc {i}").into_bytes(),
+                })
+                .collect();
+            service
+                .insert(Request::new(proto::InsertRequest {
+                    name: name(),
+                    points,
+                }))
+                .await
+                .unwrap();
+
+            let resp = service
+                .search(Request::new(proto::SearchRequest {
+                    name: name(),
+                    vector: vec![3.2, 0.0],
+                    k: 2,
+                    params: None,
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            let ids: Vec<i64> = resp.hits.iter().map(|hit| hit.id).collect();
+            assert_eq!(ids, vec![3, 4]);
+            assert_eq!(resp.hits[0].payload, b"doc 3");
+
+            let status = service
+                .insert(Request::new(proto::InsertRequest {
+                    name: "missing".to_string(),
+                    points: Vec::new(),
+                }))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::NotFound);
+        });
+    }
+}
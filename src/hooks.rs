@@ -0,0 +1,182 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Callbacks on the lifecycle of a [`VsagIndex`](crate::VsagIndex), e.g. for audits and alerts.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::progress::BuildProgress;
+
+/// Callbacks invoked by a [`VsagIndex`](crate::VsagIndex) they're installed on with
+/// [`IndexOptions::hooks`](crate::IndexOptions::hooks).
+///
+/// All methods do nothing by default. They're called synchronously on the thread calling the
+/// index, so they should be cheap or hand the work off.
+pub trait Hooks: Send + Sync {
+    /// Called before building the index with `num_vectors` vectors of dimension `dim`.
+    fn on_build_start(&self, _index_type: &str, _num_vectors: usize, _dim: usize) {}
+
+    /// Called after building the index, with the IDs of the vectors that failed to be added or
+    /// the error.
+    fn on_build_complete(
+        &self,
+        _index_type: &str,
+        _result: Result<&[i64], &Error>,
+        _elapsed: Duration,
+    ) {
+    }
+
+    /// Called as a build makes progress, see [`BuildProgress`].
+    fn on_build_progress(&self, _index_type: &str, _progress: &BuildProgress) {}
+
+    /// Called after dumping the index to `path`.
+    fn on_dump(&self, _index_type: &str, _path: &str, _result: Result<(), &Error>) {}
+
+    /// Called after loading an index from `path`.
+    fn on_load(&self, _index_type: &str, _path: &str, _result: Result<(), &Error>) {}
+
+    /// Threshold above which a search is reported to [`Hooks::on_search_slow`], `None` to not
+    /// report any.
+    fn slow_search_threshold(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Called after a search for `k` neighbors that took `elapsed`, at least
+    /// [`Hooks::slow_search_threshold`].
+    fn on_search_slow(
+        &self,
+        _index_type: &str,
+        _k: usize,
+        _search_params: &str,
+        _elapsed: Duration,
+    ) {
+    }
+}
+
+impl fmt::Debug for dyn Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hooks")
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{IndexOptions, VsagIndex};
+
+    #[derive(Default)]
+    struct RecordingHooks {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl RecordingHooks {
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl Hooks for RecordingHooks {
+        fn on_build_start(&self, index_type: &str, num_vectors: usize, dim: usize) {
+            self.record(format!("build_start {index_type} {num_vectors} {dim}"));
+        }
+
+        fn on_build_complete(
+            &self,
+            _index_type: &str,
+            result: Result<&[i64], &Error>,
+            _elapsed: Duration,
+        ) {
+            self.record(format!("build_complete {}", result.unwrap().len()));
+        }
+
+        fn on_build_progress(&self, _index_type: &str, progress: &BuildProgress) {
+            self.record(format!(
+                "build_progress {} {:?}",
+                progress.vectors_done, progress.total_vectors
+            ));
+        }
+
+        fn on_dump(&self, _index_type: &str, _path: &str, result: Result<(), &Error>) {
+            self.record(format!("dump {}", result.is_ok()));
+        }
+
+        fn on_load(&self, _index_type: &str, _path: &str, result: Result<(), &Error>) {
+            self.record(format!("load {}", result.is_ok()));
+        }
+
+        fn slow_search_threshold(&self) -> Option<Duration> {
+            Some(Duration::ZERO)
+        }
+
+        fn on_search_slow(&self, _index_type: &str, k: usize, _params: &str, _elapsed: Duration) {
+            self.record(format!("search_slow {k}"));
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let hooks = Arc::new(RecordingHooks::default());
+        let options = IndexOptions {
+            hooks: Some(hooks.clone()),
+            ..Default::default()
+        };
+        let index = VsagIndex::with_options("hnsw", con_params, options.clone()).unwrap();
+        index.build(3, 1, &[1, 2, 3], &[1.0, 2.0, 3.0]).unwrap();
+        index
+            .knn_search(&[1.0], 2, r#"{"hnsw": {"ef_search": 10}}"#)
+            .unwrap();
+
+        let dir = tempdir::TempDir::new("test_hooks").unwrap();
+        let path = dir.path().join("index");
+        let path = path.to_str().unwrap();
+        index.dump(path).unwrap();
+        assert!(
+            VsagIndex::load_with_options("missing", "hnsw", con_params, options.clone()).is_err()
+        );
+
+        let index = VsagIndex::with_options("hnsw", con_params, options).unwrap();
+        let chunks = vec![(vec![4], vec![4.0]), (vec![5, 6], vec![5.0, 6.0])];
+        index.build_chunked(1, chunks).unwrap();
+
+        assert_eq!(
+            *hooks.events.lock().unwrap(),
+            vec![
+                "build_start hnsw 3 1",
+                "build_complete 0",
+                "build_progress 3 Some(3)",
+                "search_slow 2",
+                "dump true",
+                "load false",
+                "build_progress 1 Some(2)",
+                "build_progress 3 Some(3)",
+                "build_start hnsw 3 1",
+                "build_complete 0",
+                "build_progress 3 Some(3)",
+            ]
+        );
+    }
+}
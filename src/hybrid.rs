@@ -0,0 +1,190 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fusion of dense results from [`VsagIndex`](crate::VsagIndex) with sparse (e.g. BM25) scores from another
+//! source, for hybrid search.
+
+use std::collections::HashMap;
+
+use crate::error::Result;
+use crate::index::VectorIndex;
+use crate::KnnSearchOutput;
+
+/// `k` of reciprocal rank fusion commonly used, from the original paper.
+pub const DEFAULT_RRF_K: f32 = 60.0;
+
+/// How dense and sparse results are fused into a single score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    /// Reciprocal rank fusion: each list contributes `1 / (k + rank)` for the results it
+    /// contains, with ranks starting at 1. Only ranks matter, so the scales of the lists don't.
+    Rrf { k: f32 },
+    /// Weighted sum of the scores of both lists, each min-max normalized into [0, 1] first.
+    /// Results missing from a list get 0 from it.
+    WeightedSum {
+        dense_weight: f32,
+        sparse_weight: f32,
+    },
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Fusion::Rrf { k: DEFAULT_RRF_K }
+    }
+}
+
+/// A result of a hybrid search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HybridHit {
+    pub id: i64,
+    /// Fused score, bigger is better.
+    pub score: f32,
+    /// Rank in the dense results, starting at 0, `None` if missing from them.
+    pub dense_rank: Option<usize>,
+    /// Rank in the sparse results, starting at 0, `None` if missing from them.
+    pub sparse_rank: Option<usize>,
+}
+
+impl HybridHit {
+    fn new(id: i64) -> Self {
+        HybridHit {
+            id,
+            score: 0.0,
+            dense_rank: None,
+            sparse_rank: None,
+        }
+    }
+}
+
+/// Fuses `dense` results, closest first, with `sparse` results as `(id, score)` pairs where a
+/// bigger score is better, and returns the `k` best hits, best first.
+///
+/// `sparse` doesn't need to be sorted.
+pub fn fuse(
+    dense: &KnnSearchOutput,
+    sparse: &[(i64, f32)],
+    fusion: Fusion,
+    k: usize,
+) -> Vec<HybridHit> {
+    let mut sparse_sorted = sparse.to_vec();
+    sparse_sorted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    // dense distances are lower is closer, negate them so both lists are bigger is better.
+    let dense_scores: Vec<f32> = dense.distances.iter().map(|d| -d).collect();
+    let sparse_scores: Vec<f32> = sparse_sorted.iter().map(|(_, score)| *score).collect();
+    let (dense_scores, sparse_scores, dense_weight, sparse_weight) = match fusion {
+        Fusion::Rrf { k } => (
+            rrf_scores(dense_scores.len(), k),
+            rrf_scores(sparse_scores.len(), k),
+            1.0,
+            1.0,
+        ),
+        Fusion::WeightedSum {
+            dense_weight,
+            sparse_weight,
+        } => (
+            min_max_normalized(&dense_scores),
+            min_max_normalized(&sparse_scores),
+            dense_weight,
+            sparse_weight,
+        ),
+    };
+
+    let mut hits: HashMap<i64, HybridHit> = HashMap::new();
+    for (rank, (&id, score)) in dense.ids.iter().zip(dense_scores).enumerate() {
+        let hit = hits.entry(id).or_insert_with(|| HybridHit::new(id));
+        if hit.dense_rank.is_none() {
+            hit.dense_rank = Some(rank);
+            hit.score += dense_weight * score;
+        }
+    }
+    for (rank, (&(id, _), score)) in sparse_sorted.iter().zip(sparse_scores).enumerate() {
+        let hit = hits.entry(id).or_insert_with(|| HybridHit::new(id));
+        if hit.sparse_rank.is_none() {
+            hit.sparse_rank = Some(rank);
+            hit.score += sparse_weight * score;
+        }
+    }
+
+    let mut hits: Vec<HybridHit> = hits.into_values().collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.id.cmp(&b.id)));
+    hits.truncate(k);
+    hits
+}
+
+/// Searches `index` for `num_candidates` dense results, fetches sparse results with
+/// `sparse_search`, which is given `num_candidates` too, and fuses them, see [`fuse`].
+pub fn hybrid_search(
+    index: &impl VectorIndex,
+    query_vector: &[f32],
+    search_params: &str,
+    sparse_search: impl FnOnce(usize) -> Result<Vec<(i64, f32)>>,
+    fusion: Fusion,
+    num_candidates: usize,
+    k: usize,
+) -> Result<Vec<HybridHit>> {
+    let dense = index.knn_search(query_vector, num_candidates, search_params)?;
+    let sparse = sparse_search(num_candidates)?;
+    Ok(fuse(&dense, &sparse, fusion, k))
+}
+
+fn rrf_scores(len: usize, k: f32) -> Vec<f32> {
+    (1..=len).map(|rank| 1.0 / (k + rank as f32)).collect()
+}
+
+fn min_max_normalized(scores: &[f32]) -> Vec<f32> {
+    let min = scores.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    scores
+        .iter()
+        .map(|&score| {
+            if max > min {
+                (score - min) / (max - min)
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuse() {
+        let dense = KnnSearchOutput {
+            ids: vec![1, 2, 3],
+            distances: vec![0.1, 0.2, 0.9],
+        };
+        let sparse = [(4, 1.0), (3, 12.0), (2, 5.0)];
+
+        let hits = fuse(&dense, &sparse, Fusion::default(), 10);
+        let ids: Vec<i64> = hits.iter().map(|hit| hit.id).collect();
+        // 3 and 2 are in both lists, ranked 3rd + 1st and 2nd + 2nd.
+        assert_eq!(ids, vec![3, 2, 1, 4]);
+        assert_eq!(hits[0].dense_rank, Some(2));
+        assert_eq!(hits[0].sparse_rank, Some(0));
+        assert_eq!(hits[3].dense_rank, None);
+
+        let fusion = Fusion::WeightedSum {
+            dense_weight: 1.0,
+            sparse_weight: 0.0,
+        };
+        let hits = fuse(&dense, &sparse, fusion, 2);
+        let ids: Vec<i64> = hits.iter().map(|hit| hit.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(hits[0].score, 1.0);
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The [`VectorIndex`] trait over index implementations, so code built on top of them can be
+//! generic, e.g. to run against [`FlatIndex`] in tests instead of linking libvsag.
+
+use crate::error::Result;
+use crate::flat::FlatIndex;
+use crate::metric::Metric;
+use crate::{KnnSearchOutput, VsagIndex};
+
+/// Operations shared by index implementations, [`VsagIndex`] being the primary one.
+///
+/// Adding vectors after [`VectorIndex::build`] isn't part of it, since vsag indexes are built
+/// once.
+pub trait VectorIndex {
+    /// Builds index with all vectors, see [`VsagIndex::build`].
+    ///
+    /// Returns IDs of vectors that failed to be added to the index.
+    fn build(
+        &self,
+        num_vectors: usize,
+        dim: usize,
+        ids: &[i64],
+        vectors: &[f32],
+    ) -> Result<Vec<i64>>;
+
+    /// Searches for the `k` nearest neighbors of the `query_vector`, see
+    /// [`VsagIndex::knn_search`].
+    fn knn_search(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+    ) -> Result<KnnSearchOutput>;
+
+    /// Dumps the index to the file at `path`.
+    fn dump(&self, path: &str) -> Result<()>;
+
+    /// Loads an index dumped by [`VectorIndex::dump`] from the file at `path`.
+    ///
+    /// `index_type` and `params` should be the same as the ones used to create the index.
+    fn load(path: &str, index_type: &str, params: &str) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Returns the type of the index, e.g. `hnsw`.
+    fn index_type(&self) -> &str;
+
+    /// Returns the metric type of the index, `None` if it's unknown.
+    fn metric(&self) -> Option<Metric>;
+}
+
+macro_rules! impl_vector_index {
+    ($($index:ty),*) => {
+        $(
+            impl VectorIndex for $index {
+                fn build(
+                    &self,
+                    num_vectors: usize,
+                    dim: usize,
+                    ids: &[i64],
+                    vectors: &[f32],
+                ) -> Result<Vec<i64>> {
+                    <$index>::build(self, num_vectors, dim, ids, vectors)
+                }
+
+                fn knn_search(
+                    &self,
+                    query_vector: &[f32],
+                    k: usize,
+                    search_params: &str,
+                ) -> Result<KnnSearchOutput> {
+                    <$index>::knn_search(self, query_vector, k, search_params)
+                }
+
+                fn dump(&self, path: &str) -> Result<()> {
+                    <$index>::dump(self, path)
+                }
+
+                fn load(path: &str, index_type: &str, params: &str) -> Result<Self> {
+                    <$index>::load(path, index_type, params)
+                }
+
+                fn index_type(&self) -> &str {
+                    <$index>::index_type(self)
+                }
+
+                fn metric(&self) -> Option<Metric> {
+                    <$index>::metric(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_vector_index!(VsagIndex, FlatIndex);
@@ -0,0 +1,230 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Buffered ingestion into a [`Collection`], committing in batches.
+
+use std::time::{Duration, Instant};
+
+use crate::collection::Collection;
+use crate::error::Result;
+use crate::trace;
+
+/// When an [`IngestBuffer`] flushes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlushPolicy {
+    /// Flush once this many points are buffered.
+    pub max_points: usize,
+    /// Flush once the oldest buffered point has waited this long.
+    pub max_delay: Duration,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        FlushPolicy {
+            max_points: 10_000,
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// `IngestBuffer` accumulates points and upserts them into a [`Collection`] then commits it
+/// once the [`FlushPolicy`] is hit, amortizing the rebuild of the index over many points.
+///
+/// The delay is only checked on [`IngestBuffer::add`] and [`IngestBuffer::flush_if_due`], so the
+/// latter should be called periodically when points arrive slowly.
+///
+/// With the `metrics` feature, the number of buffered points is recorded in the
+/// `vsag_ingest_queue_depth` gauge.
+pub struct IngestBuffer {
+    collection: Collection,
+    policy: FlushPolicy,
+    pending: Vec<(i64, Vec<f32>, Vec<u8>)>,
+    oldest: Option<Instant>,
+}
+
+impl IngestBuffer {
+    /// Creates an empty buffer over `collection`.
+    pub fn new(collection: Collection, policy: FlushPolicy) -> Self {
+        IngestBuffer {
+            collection,
+            policy,
+            pending: Vec::new(),
+            oldest: None,
+        }
+    }
+
+    /// Buffers a point to upsert, flushing if due, returns whether it flushed.
+    ///
+    /// The point is checked right away, so only valid points are buffered.
+    pub fn add(&mut self, id: i64, vector: Vec<f32>, payload: Vec<u8>) -> Result<bool> {
+        self.collection.check_point(id, &vector)?;
+        self.pending.push((id, vector, payload));
+        self.oldest.get_or_insert_with(Instant::now);
+        trace::track_queued(1.0);
+        self.flush_if_due()
+    }
+
+    /// Flushes if the buffer is full or its oldest point has waited long enough, returns
+    /// whether it flushed.
+    pub fn flush_if_due(&mut self) -> Result<bool> {
+        let due = self.pending.len() >= self.policy.max_points
+            || self
+                .oldest
+                .is_some_and(|oldest| oldest.elapsed() >= self.policy.max_delay);
+        if due {
+            self.flush()?;
+        }
+        Ok(due)
+    }
+
+    /// Upserts the buffered points into the collection and commits it, returns the number of
+    /// points flushed.
+    ///
+    /// If the commit fails, the points stay upserted in the collection, to be committed again.
+    /// Points that fail to be indexed are deleted by the commit, see [`Collection::commit`].
+    pub fn flush(&mut self) -> Result<usize> {
+        // checked by `add` already, so upserting can't fail halfway and lose the rest.
+        for (id, vector, _) in &self.pending {
+            self.collection.check_point(*id, vector)?;
+        }
+        let count = self.pending.len();
+        trace::track_queued(-(count as f64));
+        self.oldest = None;
+        for (id, vector, payload) in self.pending.drain(..) {
+            self.collection.upsert(id, vector, payload)?;
+        }
+        if count > 0 {
+            self.collection.commit()?;
+        }
+        Ok(count)
+    }
+
+    /// Returns the number of buffered points.
+    pub fn depth(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the collection, without the buffered points.
+    pub fn collection(&self) -> &Collection {
+        &self.collection
+    }
+
+    /// Flushes and returns the collection.
+    pub fn into_collection(mut self) -> Result<Collection> {
+        self.flush()?;
+        let empty = Collection::new(self.collection.config().clone());
+        Ok(std::mem::replace(&mut self.collection, empty))
+    }
+
+    /// Flushes and drops the buffer, reporting a failed flush that dropping would ignore.
+    /// Points that failed to be flushed are dropped with the buffer either way.
+    pub fn close(mut self) -> Result<()> {
+        self.flush().map(drop)
+    }
+}
+
+impl Drop for IngestBuffer {
+    fn drop(&mut self) {
+        trace::track_queued(-(self.pending.len() as f64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::CollectionConfig;
+    use crate::error::ErrorType;
+    use crate::IndexOptions;
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_ingest_buffer() {
+        let collection = Collection::new(CollectionConfig {
+            index_type: "hnsw".to_string(),
+            params: r#"{
+                "dtype": "float32",
+                "metric_type": "l2",
+                "dim": 2,
+                "hnsw": {
+                    "max_degree": 16,
+                    "ef_construction": 100
+                }
+            }"#
+            .to_string(),
+            dim: 2,
+            options: IndexOptions::default(),
+        });
+        let policy = FlushPolicy {
+            max_points: 3,
+            max_delay: Duration::from_millis(50),
+        };
+        let mut buffer = IngestBuffer::new(collection, policy);
+        assert!(!buffer.add(1, vec![0.0, 0.0], vec![]).unwrap());
+        assert!(!buffer.add(2, vec![1.0, 0.0], vec![]).unwrap());
+        assert!(buffer.add(3, vec![0.0], vec![]).is_err());
+        assert_eq!(buffer.depth(), 2);
+        assert!(buffer.collection().is_empty());
+
+        assert!(buffer.add(3, vec![2.0, 0.0], vec![]).unwrap());
+        assert_eq!(buffer.depth(), 0);
+        assert_eq!(buffer.collection().len(), 3);
+        assert!(!buffer.collection().is_dirty());
+
+        assert!(!buffer.add(4, vec![3.0, 0.0], vec![]).unwrap());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(buffer.flush_if_due().unwrap());
+        assert_eq!(buffer.flush().unwrap(), 0);
+
+        buffer.add(5, vec![4.0, 0.0], vec![]).unwrap();
+        let collection = buffer.into_collection().unwrap();
+        let hits = collection
+            .search(&[4.0, 0.0], 1, r#"{"hnsw": {"ef_search": 100}}"#)
+            .unwrap();
+        assert_eq!(hits[0].id, 5);
+    }
+
+    #[test]
+    fn test_invalid_points_not_buffered() {
+        let mut config = CollectionConfig {
+            index_type: "hnsw".to_string(),
+            params: String::new(),
+            dim: 2,
+            options: IndexOptions::default(),
+        };
+        config.options.validate_ids = true;
+        let mut buffer = IngestBuffer::new(Collection::new(config), FlushPolicy::default());
+        buffer.add(1, vec![0.0, 0.0], vec![]).unwrap();
+        let err = buffer.add(-1, vec![1.0, 0.0], vec![]).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+        assert!(buffer.add(2, vec![1.0], vec![]).is_err());
+        assert_eq!(buffer.depth(), 1);
+    }
+
+    #[test]
+    fn test_close() {
+        let config = CollectionConfig {
+            index_type: "invalid".to_string(),
+            params: String::new(),
+            dim: 2,
+            options: IndexOptions::default(),
+        };
+        let buffer = IngestBuffer::new(Collection::new(config.clone()), FlushPolicy::default());
+        buffer.close().unwrap();
+
+        // the index can't be created, so the commit of the flush fails.
+        let mut buffer = IngestBuffer::new(Collection::new(config), FlushPolicy::default());
+        buffer.add(1, vec![0.0, 0.0], vec![]).unwrap();
+        assert!(buffer.close().is_err());
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An IVF-like index: vectors are clustered, each cluster gets its own [`VsagIndex`], and
+//! searches only visit the clusters closest to the query.
+
+use crate::cluster::{cluster, Clustering};
+use crate::error::{Error, ErrorType, Result};
+use crate::topk::merge_topk;
+use crate::{IndexOptions, KnnSearchOutput, VsagIndex};
+
+/// Maximum number of vectors the centroids are trained on, evenly sampled.
+const MAX_TRAIN_VECTORS: usize = 100_000;
+
+/// `IvfIndex` routes vectors to one [`VsagIndex`] per k-means centroid, and searches the
+/// `nprobe` partitions whose centroids are the closest to the query.
+///
+/// Centroids are by L2 distance whatever the metric of the partitions, which routes well for
+/// normalized vectors, e.g. with cosine.
+pub struct IvfIndex {
+    index_type: String,
+    params: String,
+    options: IndexOptions,
+    num_partitions: usize,
+    clustering: Option<Clustering>,
+    /// Index of each partition, `None` if it's empty.
+    partitions: Vec<Option<VsagIndex>>,
+    sizes: Vec<usize>,
+}
+
+impl IvfIndex {
+    /// Creates an index of `num_partitions` partitions, see [`VsagIndex::new`] for
+    /// `index_type` and `params`.
+    pub fn new(index_type: &str, params: &str, num_partitions: usize) -> Self {
+        Self::with_options(index_type, params, num_partitions, IndexOptions::default())
+    }
+
+    /// Creates an index whose partitions are created with `options`, see [`IvfIndex::new`].
+    pub fn with_options(
+        index_type: &str,
+        params: &str,
+        num_partitions: usize,
+        options: IndexOptions,
+    ) -> Self {
+        IvfIndex {
+            index_type: index_type.to_string(),
+            params: params.to_string(),
+            options,
+            num_partitions,
+            clustering: None,
+            partitions: Vec::new(),
+            sizes: Vec::new(),
+        }
+    }
+
+    /// Trains the centroids with `iters` k-means iterations on up to 100,000 of `vectors`, then
+    /// builds the index of each partition with its vectors.
+    ///
+    /// Returns IDs of vectors that failed to be added to the index.
+    pub fn build(
+        &mut self,
+        dim: usize,
+        ids: &[i64],
+        vectors: &[f32],
+        iters: usize,
+    ) -> Result<Vec<i64>> {
+        if self.clustering.is_some() {
+            return Err(Error::new(
+                ErrorType::BuildTwice,
+                "index has been built already",
+            ));
+        }
+        if dim == 0 || vectors.len() != ids.len() * dim {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "ids and vectors have mismatched lengths",
+            ));
+        }
+
+        let stride = ids.len().div_ceil(MAX_TRAIN_VECTORS).max(1);
+        let samples: Vec<f32> = vectors
+            .chunks_exact(dim)
+            .step_by(stride)
+            .flatten()
+            .copied()
+            .collect();
+        let clustering = cluster(&samples, dim, self.num_partitions, iters)?;
+
+        let mut routed = vec![(Vec::new(), Vec::new()); self.num_partitions];
+        for (&id, vector) in ids.iter().zip(vectors.chunks_exact(dim)) {
+            let (partition_ids, partition_vectors) = &mut routed[clustering.assign(vector)];
+            partition_ids.push(id);
+            partition_vectors.extend_from_slice(vector);
+        }
+
+        let mut failed_ids = Vec::new();
+        let mut partitions = Vec::with_capacity(self.num_partitions);
+        let mut sizes = Vec::with_capacity(self.num_partitions);
+        for (ids, vectors) in routed {
+            sizes.push(ids.len());
+            if ids.is_empty() {
+                partitions.push(None);
+                continue;
+            }
+            let index =
+                VsagIndex::with_options(&self.index_type, &self.params, self.options.clone())?;
+            failed_ids.extend(index.build(ids.len(), dim, &ids, &vectors)?);
+            partitions.push(Some(index));
+        }
+
+        self.clustering = Some(clustering);
+        self.partitions = partitions;
+        self.sizes = sizes;
+        Ok(failed_ids)
+    }
+
+    /// Searches for the `k` nearest neighbors of the `query_vector` in the `nprobe` partitions
+    /// closest to it, see [`VsagIndex::knn_search`].
+    ///
+    /// Returns no results if the index isn't built.
+    pub fn knn_search(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        nprobe: usize,
+        search_params: &str,
+    ) -> Result<KnnSearchOutput> {
+        let Some(clustering) = &self.clustering else {
+            return Ok(KnnSearchOutput {
+                ids: Vec::new(),
+                distances: Vec::new(),
+            });
+        };
+        if query_vector.len() != clustering.dim {
+            return Err(Error::new(
+                ErrorType::DimensionNotEqual,
+                format!(
+                    "expect query of dimension {}, got {}",
+                    clustering.dim,
+                    query_vector.len()
+                ),
+            ));
+        }
+
+        let outputs = clustering
+            .nearest_centroids(query_vector, nprobe)
+            .into_iter()
+            .filter_map(|partition| self.partitions[partition].as_ref())
+            .map(|index| index.knn_search(query_vector, k, search_params))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(merge_topk(&outputs, k))
+    }
+
+    /// Returns the centroids, `None` if the index isn't built.
+    pub fn clustering(&self) -> Option<&Clustering> {
+        self.clustering.as_ref()
+    }
+
+    /// Returns the number of vectors in each partition.
+    pub fn partition_sizes(&self) -> &[usize] {
+        &self.sizes
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ivf_index() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        // 4 well separated groups of 25 vectors.
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = ids
+            .iter()
+            .map(|&i| (i / 25) as f32 * 100.0 + (i % 25) as f32)
+            .collect();
+
+        let mut index = IvfIndex::new("hnsw", con_params, 4);
+        assert!(index
+            .knn_search(&[0.0], 1, 1, search_params)
+            .unwrap()
+            .ids
+            .is_empty());
+        assert!(index.build(1, &ids, &vectors, 10).unwrap().is_empty());
+        assert_eq!(index.partition_sizes(), &[25; 4]);
+        assert!(index.build(1, &ids, &vectors, 10).is_err());
+
+        // only the closest group is searched with nprobe 1.
+        let output = index.knn_search(&[160.7], 4, 1, search_params).unwrap();
+        assert_eq!(output.ids, vec![49, 48, 47, 46]);
+        let output = index.knn_search(&[160.7], 4, 2, search_params).unwrap();
+        assert_eq!(output.ids, vec![49, 48, 47, 50]);
+        assert!(index.knn_search(&[0.0, 0.0], 1, 1, search_params).is_err());
+    }
+}
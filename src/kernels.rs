@@ -0,0 +1,399 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Vector kernels for the work done on the Rust side.
+//!
+//! Element-wise kernels are plain loops over slices so that they're auto-vectorized.
+//! Reductions aren't, since floating point addition isn't associative, so they use AVX2 and
+//! FMA when the CPU supports them, and otherwise 8 independent accumulators that the compiler
+//! maps to vector registers.
+//!
+//! The dimensions of common embedding models get reductions monomorphized for their length,
+//! fully unrolled without a remainder loop: 64, 128, 384, 768, 1024 and 1536.
+
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+
+/// Number of lanes of the portable reductions, one AVX2 register of `f32`.
+const LANES: usize = 8;
+
+/// Calls `$fixed::<DIM>` if the length of `$a` is one of the specialized dimensions, all
+/// multiples of `4 * LANES`, and `$generic` otherwise. `$a` and `$b` must have the same length.
+macro_rules! specialize {
+    ($a:expr, $b:expr, $($fixed:ident)::+, $($generic:ident)::+) => {
+        match $a.len() {
+            64 => $($fixed)::+::<64>($a.try_into().unwrap(), $b.try_into().unwrap()),
+            128 => $($fixed)::+::<128>($a.try_into().unwrap(), $b.try_into().unwrap()),
+            384 => $($fixed)::+::<384>($a.try_into().unwrap(), $b.try_into().unwrap()),
+            768 => $($fixed)::+::<768>($a.try_into().unwrap(), $b.try_into().unwrap()),
+            1024 => $($fixed)::+::<1024>($a.try_into().unwrap(), $b.try_into().unwrap()),
+            1536 => $($fixed)::+::<1536>($a.try_into().unwrap(), $b.try_into().unwrap()),
+            _ => $($generic)::+($a, $b),
+        }
+    };
+}
+
+/// Converts `f64` values to `f32`.
+pub fn to_f32(values: &[f64]) -> Vec<f32> {
+    values.iter().map(|&v| v as f32).collect()
+}
+
+/// Returns a copy of `vectors` with every `dim`-sized vector L2-normalized.
+///
+/// Zero vectors are kept as is.
+pub fn normalized(vectors: &[f32], dim: usize) -> Vec<f32> {
+    let mut vectors = vectors.to_vec();
+    if dim > 0 {
+        for vector in vectors.chunks_exact_mut(dim) {
+            normalize(vector);
+        }
+    }
+    vectors
+}
+
+/// L2-normalizes `vector` in place, zero vectors are kept as is.
+pub fn normalize(vector: &mut [f32]) {
+    let norm = inner_product(vector, vector).sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+/// Returns the position of the first `dim`-sized vector in `vectors` containing a NaN or
+/// infinite value.
+pub fn first_non_finite(vectors: &[f32], dim: usize) -> Option<usize> {
+    if dim == 0 {
+        return None;
+    }
+    vectors.chunks(dim).position(|vector| !all_finite(vector))
+}
+
+/// Returns `true` if `values` contains no NaN or infinite value.
+fn all_finite(values: &[f32]) -> bool {
+    // NaN and infinities have all exponent bits set, OR-ing a lane-wise flag vectorizes.
+    let non_finite = values.iter().fold(false, |acc, v| {
+        acc | (v.to_bits() & 0x7f80_0000 == 0x7f80_0000)
+    });
+    !non_finite
+}
+
+/// Returns the squared L2 distance between `a` and `b`.
+pub fn l2_sq(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let (a, b) = (&a[..len], &b[..len]);
+    #[cfg(target_arch = "x86_64")]
+    if has_avx2_fma() {
+        // SAFETY: the CPU supports AVX2 and FMA.
+        return unsafe { specialize!(a, b, avx2::l2_sq_fixed, avx2::l2_sq) };
+    }
+    specialize!(a, b, l2_sq_fixed, l2_sq_portable)
+}
+
+fn l2_sq_portable(a: &[f32], b: &[f32]) -> f32 {
+    reduce(a, b, |x, y| (x - y) * (x - y))
+}
+
+fn l2_sq_fixed<const N: usize>(a: &[f32; N], b: &[f32; N]) -> f32 {
+    reduce_fixed(a, b, |x, y| (x - y) * (x - y))
+}
+
+/// Returns the inner product of `a` and `b`.
+pub fn inner_product(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let (a, b) = (&a[..len], &b[..len]);
+    #[cfg(target_arch = "x86_64")]
+    if has_avx2_fma() {
+        // SAFETY: the CPU supports AVX2 and FMA.
+        return unsafe { specialize!(a, b, avx2::inner_product_fixed, avx2::inner_product) };
+    }
+    specialize!(a, b, inner_product_fixed, inner_product_portable)
+}
+
+fn inner_product_portable(a: &[f32], b: &[f32]) -> f32 {
+    reduce(a, b, |x, y| x * y)
+}
+
+fn inner_product_fixed<const N: usize>(a: &[f32; N], b: &[f32; N]) -> f32 {
+    reduce_fixed(a, b, |x, y| x * y)
+}
+
+/// Returns the cosine similarity of `a` and `b`, 0 if either is a zero vector.
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let norms = (inner_product(a, a) * inner_product(b, b)).sqrt();
+    if norms > 0.0 {
+        inner_product(a, b) / norms
+    } else {
+        0.0
+    }
+}
+
+/// Sums `f` over the pairs of `a` and `b`, of the same length, in [`LANES`] accumulators.
+#[inline(always)]
+fn reduce(a: &[f32], b: &[f32], f: impl Fn(f32, f32) -> f32) -> f32 {
+    let mut acc = [0.0; LANES];
+    let (a_chunks, b_chunks) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(&x, &y)| f(x, y))
+        .sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for i in 0..LANES {
+            acc[i] += f(x[i], y[i]);
+        }
+    }
+    acc.iter().sum::<f32>() + tail
+}
+
+/// Same as [`reduce`] for a length known at compile time, a multiple of [`LANES`].
+#[inline(always)]
+fn reduce_fixed<const N: usize>(a: &[f32; N], b: &[f32; N], f: impl Fn(f32, f32) -> f32) -> f32 {
+    const { assert!(N.is_multiple_of(LANES)) };
+    let mut acc = [0.0; LANES];
+    for i in (0..N).step_by(LANES) {
+        for j in 0..LANES {
+            acc[j] += f(a[i + j], b[i + j]);
+        }
+    }
+    acc.iter().sum()
+}
+
+/// Returns which implementation the reductions use on this CPU.
+pub fn implementation() -> &'static str {
+    #[cfg(target_arch = "x86_64")]
+    if has_avx2_fma() {
+        return "avx2+fma";
+    }
+    "portable"
+}
+
+#[cfg(target_arch = "x86_64")]
+fn has_avx2_fma() -> bool {
+    static DETECTED: OnceLock<bool> = OnceLock::new();
+    *DETECTED.get_or_init(|| is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma"))
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::LANES;
+
+    /// # Safety
+    ///
+    /// The CPU must support AVX2 and FMA, and `a` and `b` must have the same length.
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn l2_sq(a: &[f32], b: &[f32]) -> f32 {
+        let mut acc = _mm256_setzero_ps();
+        let (a_chunks, b_chunks) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+        let mut tail = 0.0;
+        for (&x, &y) in a_chunks.remainder().iter().zip(b_chunks.remainder()) {
+            tail += (x - y) * (x - y);
+        }
+        for (x, y) in a_chunks.zip(b_chunks) {
+            let d = _mm256_sub_ps(_mm256_loadu_ps(x.as_ptr()), _mm256_loadu_ps(y.as_ptr()));
+            acc = _mm256_fmadd_ps(d, d, acc);
+        }
+        horizontal_sum(acc) + tail
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support AVX2 and FMA, and `a` and `b` must have the same length.
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn inner_product(a: &[f32], b: &[f32]) -> f32 {
+        let mut acc = _mm256_setzero_ps();
+        let (a_chunks, b_chunks) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+        let mut tail = 0.0;
+        for (&x, &y) in a_chunks.remainder().iter().zip(b_chunks.remainder()) {
+            tail += x * y;
+        }
+        for (x, y) in a_chunks.zip(b_chunks) {
+            acc = _mm256_fmadd_ps(
+                _mm256_loadu_ps(x.as_ptr()),
+                _mm256_loadu_ps(y.as_ptr()),
+                acc,
+            );
+        }
+        horizontal_sum(acc) + tail
+    }
+
+    /// Same as [`l2_sq`] for a length known at compile time, a multiple of `4 * LANES`, with 4
+    /// accumulators to hide the latency of FMA.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2 and FMA.
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn l2_sq_fixed<const N: usize>(a: &[f32; N], b: &[f32; N]) -> f32 {
+        const { assert!(N.is_multiple_of(4 * LANES)) };
+        let mut acc = [_mm256_setzero_ps(); 4];
+        for i in (0..N).step_by(4 * LANES) {
+            for (j, acc) in acc.iter_mut().enumerate() {
+                let offset = i + j * LANES;
+                let d = _mm256_sub_ps(
+                    _mm256_loadu_ps(a[offset..].as_ptr()),
+                    _mm256_loadu_ps(b[offset..].as_ptr()),
+                );
+                *acc = _mm256_fmadd_ps(d, d, *acc);
+            }
+        }
+        horizontal_sum(sum_4(acc))
+    }
+
+    /// Same as [`inner_product`] for a length known at compile time, see [`l2_sq_fixed`].
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2 and FMA.
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn inner_product_fixed<const N: usize>(a: &[f32; N], b: &[f32; N]) -> f32 {
+        const { assert!(N.is_multiple_of(4 * LANES)) };
+        let mut acc = [_mm256_setzero_ps(); 4];
+        for i in (0..N).step_by(4 * LANES) {
+            for (j, acc) in acc.iter_mut().enumerate() {
+                let offset = i + j * LANES;
+                *acc = _mm256_fmadd_ps(
+                    _mm256_loadu_ps(a[offset..].as_ptr()),
+                    _mm256_loadu_ps(b[offset..].as_ptr()),
+                    *acc,
+                );
+            }
+        }
+        horizontal_sum(sum_4(acc))
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn sum_4(acc: [__m256; 4]) -> __m256 {
+        _mm256_add_ps(_mm256_add_ps(acc[0], acc[1]), _mm256_add_ps(acc[2], acc[3]))
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn horizontal_sum(v: __m256) -> f32 {
+        let sum = _mm_add_ps(_mm256_castps256_ps128(v), _mm256_extractf128_ps(v, 1));
+        let sum = _mm_add_ps(sum, _mm_movehl_ps(sum, sum));
+        let sum = _mm_add_ss(sum, _mm_shuffle_ps(sum, sum, 1));
+        _mm_cvtss_f32(sum)
+    }
+}
+
+/// Hasher of vector IDs for deduplication, much faster than the default SipHash on `i64` keys.
+///
+/// IDs are mixed with a per-process random seed then with the murmur3 finalizer, so all their
+/// bits reach the bits used for bucketing, e.g. IDs differing only in their high bits don't
+/// collide. It's not a keyed hash though, unlike SipHash it doesn't stop IDs crafted against
+/// it from colliding.
+#[derive(Clone, Copy, Default)]
+pub struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+
+    fn write_i64(&mut self, id: i64) {
+        self.write_u64(id as u64);
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = fmix64(self.0.rotate_left(5) ^ value);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Finalizer of murmur3, every input bit affects every output bit.
+fn fmix64(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+/// [`BuildHasher`] of [`IdHasher`]s seeded once per process.
+#[derive(Clone, Copy)]
+pub struct IdHasherBuilder(u64);
+
+impl Default for IdHasherBuilder {
+    fn default() -> Self {
+        static SEED: OnceLock<u64> = OnceLock::new();
+        IdHasherBuilder(
+            *SEED.get_or_init(|| std::collections::hash_map::RandomState::new().hash_one(0u64)),
+        )
+    }
+}
+
+impl BuildHasher for IdHasherBuilder {
+    type Hasher = IdHasher;
+
+    fn build_hasher(&self) -> IdHasher {
+        IdHasher(self.0)
+    }
+}
+
+/// Map of vector IDs hashed with [`IdHasher`].
+pub type IdMap<V> = std::collections::HashMap<i64, V, IdHasherBuilder>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernels() {
+        for len in [0, 1, 7, 8, 9, 100, 64, 384, 1536] {
+            let a: Vec<f32> = (0..len).map(|i| i as f32 * 0.5).collect();
+            let b: Vec<f32> = (0..len).map(|i| 3.0 - i as f32).collect();
+            let l2: f32 = a.iter().zip(&b).map(|(x, y)| (x - y) * (x - y)).sum();
+            let ip: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+            assert!((l2_sq(&a, &b) - l2).abs() <= l2.abs() * 1e-5, "{len}");
+            assert!((reduce(&a, &b, |x, y| x * y) - ip).abs() <= ip.abs() * 1e-5);
+            assert!((inner_product(&a, &b) - ip).abs() <= ip.abs() * 1e-5);
+            if let Ok(a) = <&[f32; 64]>::try_from(&a[..]) {
+                let b = b[..].try_into().unwrap();
+                assert!((l2_sq_fixed(a, b) - l2).abs() <= l2.abs() * 1e-5);
+                assert!((inner_product_fixed(a, b) - ip).abs() <= ip.abs() * 1e-5);
+            }
+        }
+
+        let vectors = [1.0, 2.0, 3.0, f32::NEG_INFINITY, 0.0, f32::NAN];
+        assert_eq!(first_non_finite(&vectors, 2), Some(1));
+        assert_eq!(first_non_finite(&vectors[..3], 1), None);
+        let mut vector = [3.0, 4.0];
+        normalize(&mut vector);
+        assert_eq!(vector, [0.6, 0.8]);
+
+        let mut ids: IdMap<usize> = IdMap::default();
+        for id in -1000..1000 {
+            ids.insert(id, id as usize);
+        }
+        assert_eq!(ids.len(), 2000);
+        assert_eq!(ids[&-5], -5i64 as usize);
+    }
+
+    #[test]
+    fn test_id_hasher_high_bits() {
+        // IDs differing only in their high bits must spread over the low bits used for buckets.
+        let hasher = IdHasherBuilder::default();
+        let buckets: std::collections::HashSet<u64> = (0..4096i64)
+            .map(|j| hasher.hash_one(j << 48) & 0xffff)
+            .collect();
+        assert!(buckets.len() > 3500, "{}", buckets.len());
+    }
+}
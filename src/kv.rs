@@ -0,0 +1,374 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A [`Collection`] addressed by external keys and persisted in an embedded sled database, with
+//! the `sled` feature.
+//!
+//! Points and key ↔ ID mappings are written transactionally and flushed on every change, and the
+//! index is dumped next to the database on every commit, so the whole collection survives
+//! restarts.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::Transactional;
+
+use crate::codec::{read_bytes, read_f32s, write_bytes, write_f32s};
+use crate::collection::{Collection, CollectionConfig, Point};
+use crate::error::{Error, ErrorType, Result};
+use crate::VsagIndex;
+
+/// Directory of the sled database inside the directory of a [`KvCollection`].
+const DB_DIR: &str = "db";
+/// File name of the index inside the directory of a [`KvCollection`].
+const INDEX_FILE: &str = "index";
+/// Metadata key of the next ID to allocate.
+const NEXT_ID_KEY: &[u8] = b"next_id";
+/// Metadata key present when points changed since the index was last dumped.
+const DIRTY_KEY: &[u8] = b"dirty";
+
+/// A search result of a [`KvCollection`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct KvSearchHit {
+    /// Key of the point.
+    pub key: Vec<u8>,
+    /// Distance between the point and the query vector.
+    pub distance: f32,
+    /// Payload of the point.
+    pub payload: Vec<u8>,
+}
+
+/// `KvCollection` is a [`Collection`] whose points are addressed by arbitrary byte keys, mapped
+/// to internal IDs, and persisted in a sled database.
+///
+/// Changes are flushed to disk before they return, but like in [`Collection`] they only become
+/// searchable after [`KvCollection::commit`]. If the process stops with uncommitted changes, the
+/// index is rebuilt when reopening.
+pub struct KvCollection {
+    dir: PathBuf,
+    db: sled::Db,
+    /// ID → point.
+    points: sled::Tree,
+    /// Key → ID.
+    keys: sled::Tree,
+    /// ID → key.
+    ids: sled::Tree,
+    meta: sled::Tree,
+    collection: Collection,
+}
+
+impl KvCollection {
+    /// Opens the collection in the directory at `dir`, creating it if it doesn't exist.
+    ///
+    /// `config` should be the same every time the collection is opened.
+    pub fn open(dir: impl AsRef<Path>, config: CollectionConfig) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        let db = sled::open(dir.join(DB_DIR)).map_err(kv_error)?;
+        let points = db.open_tree("points").map_err(kv_error)?;
+        let keys = db.open_tree("keys").map_err(kv_error)?;
+        let ids = db.open_tree("ids").map_err(kv_error)?;
+        let meta = db.open_tree("meta").map_err(kv_error)?;
+
+        let mut collection = Collection::new(config);
+        for entry in points.iter() {
+            let (id, value) = entry.map_err(kv_error)?;
+            let point = decode_point(&value)?;
+            collection.upsert(decode_id(&id)?, point.vector, point.payload)?;
+        }
+
+        let index_path = dir.join(INDEX_FILE);
+        let dirty = meta.contains_key(DIRTY_KEY).map_err(kv_error)?;
+        if !dirty && !collection.is_empty() && index_path.exists() {
+            let config = collection.config();
+            let index = VsagIndex::load_with_options(
+                &index_path.display().to_string(),
+                &config.index_type,
+                &config.params,
+                config.options.clone(),
+            )?;
+            collection.set_committed_index(index);
+        }
+
+        let mut kv_collection = KvCollection {
+            dir,
+            db,
+            points,
+            keys,
+            ids,
+            meta,
+            collection,
+        };
+        if kv_collection.collection.is_dirty() {
+            kv_collection.commit()?;
+        }
+        Ok(kv_collection)
+    }
+
+    /// Inserts or updates the point of `key`, returns its internal ID.
+    pub fn upsert(&mut self, key: &[u8], vector: Vec<f32>, payload: Vec<u8>) -> Result<i64> {
+        let dim = self.collection.config().dim;
+        if vector.len() != dim {
+            return Err(Error::new(
+                ErrorType::DimensionNotEqual,
+                format!("expect vector of dimension {dim}, got {}", vector.len()),
+            ));
+        }
+
+        let point = encode_point(&vector, &payload)?;
+        let id = (&self.keys, &self.ids, &self.points, &self.meta)
+            .transaction(|(keys, ids, points, meta)| {
+                let id = match keys.get(key)? {
+                    Some(id) => decode_id(&id).map_err(ConflictableTransactionError::Abort)?,
+                    None => {
+                        let id = match meta.get(NEXT_ID_KEY)? {
+                            Some(id) => {
+                                decode_id(&id).map_err(ConflictableTransactionError::Abort)?
+                            }
+                            None => 0,
+                        };
+                        meta.insert(NEXT_ID_KEY, &(id + 1).to_be_bytes())?;
+                        keys.insert(key, &id.to_be_bytes())?;
+                        ids.insert(&id.to_be_bytes(), key)?;
+                        id
+                    }
+                };
+                points.insert(&id.to_be_bytes(), point.as_slice())?;
+                meta.insert(DIRTY_KEY, &[])?;
+                Ok(id)
+            })
+            .map_err(tx_error)?;
+        self.db.flush().map_err(kv_error)?;
+
+        self.collection.upsert(id, vector, payload)?;
+        Ok(id)
+    }
+
+    /// Deletes the point of `key`, returns whether it existed.
+    pub fn delete(&mut self, key: &[u8]) -> Result<bool> {
+        let id = (&self.keys, &self.ids, &self.points, &self.meta)
+            .transaction(|(keys, ids, points, meta)| {
+                let Some(id) = keys.remove(key)? else {
+                    return Ok(None);
+                };
+                ids.remove(&id)?;
+                points.remove(&id)?;
+                meta.insert(DIRTY_KEY, &[])?;
+                decode_id(&id)
+                    .map(Some)
+                    .map_err(ConflictableTransactionError::Abort)
+            })
+            .map_err(tx_error)?;
+        if id.is_some() {
+            self.db.flush().map_err(kv_error)?;
+        }
+
+        Ok(id.is_some_and(|id| self.collection.delete(id).is_some()))
+    }
+
+    /// Returns the internal ID of `key`.
+    pub fn id_of(&self, key: &[u8]) -> Result<Option<i64>> {
+        match self.keys.get(key).map_err(kv_error)? {
+            Some(id) => decode_id(&id).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the key of the internal `id`.
+    pub fn key_of(&self, id: i64) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .ids
+            .get(id.to_be_bytes())
+            .map_err(kv_error)?
+            .map(|key| key.to_vec()))
+    }
+
+    /// Returns the point of `key`.
+    pub fn get(&self, key: &[u8]) -> Result<Option<&Point>> {
+        Ok(self.id_of(key)?.and_then(|id| self.collection.get(id)))
+    }
+
+    /// Returns the underlying collection, addressed by internal IDs.
+    pub fn collection(&self) -> &Collection {
+        &self.collection
+    }
+
+    /// Rebuilds the index, see [`Collection::commit`], and persists it, returns the keys of the
+    /// points that failed to be indexed, which are deleted.
+    pub fn commit(&mut self) -> Result<Vec<Vec<u8>>> {
+        let mut failed_keys = Vec::new();
+        for id in self.collection.commit()? {
+            if let Some(key) = self.key_of(id)? {
+                self.delete(&key)?;
+                failed_keys.push(key);
+            }
+        }
+
+        let index_path = self.dir.join(INDEX_FILE);
+        match self.collection.committed_index() {
+            Some(index) => {
+                let tmp_path = index_path.with_extension("tmp");
+                index.dump(&tmp_path.display().to_string())?;
+                std::fs::rename(&tmp_path, &index_path)?;
+            }
+            None => match std::fs::remove_file(&index_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            },
+        }
+
+        self.meta.remove(DIRTY_KEY).map_err(kv_error)?;
+        self.db.flush().map_err(kv_error)?;
+        Ok(failed_keys)
+    }
+
+    /// Searches for the `k` nearest points of the `query_vector` as of the last commit, see
+    /// [`Collection::search`].
+    pub fn search(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+    ) -> Result<Vec<KvSearchHit>> {
+        self.collection
+            .search(query_vector, k, search_params)?
+            .into_iter()
+            .map(|hit| {
+                let key = self.key_of(hit.id)?.ok_or_else(|| {
+                    Error::new(
+                        ErrorType::InternalError,
+                        format!("missing key of id {}", hit.id),
+                    )
+                })?;
+                Ok(KvSearchHit {
+                    key,
+                    distance: hit.distance,
+                    payload: hit.payload,
+                })
+            })
+            .collect()
+    }
+}
+
+fn kv_error(err: impl fmt::Display) -> Error {
+    Error::new(ErrorType::InternalError, format!("kv store error: {err}"))
+}
+
+fn tx_error(err: TransactionError<Error>) -> Error {
+    match err {
+        TransactionError::Abort(err) => err,
+        TransactionError::Storage(err) => kv_error(err),
+    }
+}
+
+fn decode_id(bytes: &[u8]) -> Result<i64> {
+    let bytes = bytes
+        .try_into()
+        .map_err(|_| Error::new(ErrorType::InvalidBinary, "invalid id"))?;
+    Ok(i64::from_be_bytes(bytes))
+}
+
+fn encode_point(vector: &[f32], payload: &[u8]) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(16 + vector.len() * 4 + payload.len());
+    write_f32s(&mut bytes, vector)?;
+    write_bytes(&mut bytes, payload)?;
+    Ok(bytes)
+}
+
+fn decode_point(mut bytes: &[u8]) -> Result<Point> {
+    let vector = read_f32s(&mut bytes)?;
+    let payload = read_bytes(&mut bytes)?;
+    Ok(Point {
+        vector,
+        payload,
+        expires_at: None,
+    })
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// Reopens the collection at `dir`, waiting for sled's background threads to release the
+    /// lock of the database after it was dropped.
+    fn reopen(dir: &Path, config: CollectionConfig) -> KvCollection {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            match KvCollection::open(dir, config.clone()) {
+                Ok(collection) => return collection,
+                Err(e) if e.message.contains("could not acquire lock") => {
+                    assert!(Instant::now() < deadline, "{e:?}");
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => panic!("{e:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_kv_collection() {
+        let config = CollectionConfig {
+            index_type: "hnsw".to_string(),
+            params: r#"{
+                "dtype": "float32",
+                "metric_type": "l2",
+                "dim": 2,
+                "hnsw": {
+                    "max_degree": 16,
+                    "ef_construction": 100
+                }
+            }"#
+            .to_string(),
+            dim: 2,
+            options: Default::default(),
+        };
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let dir = tempdir::TempDir::new("test_kv_collection").unwrap();
+
+        let mut collection = KvCollection::open(dir.path(), config.clone()).unwrap();
+        for i in 0..10 {
+            let key = format!("doc-{i}");
+            let id = collection
+                .upsert(key.as_bytes(), vec![i as f32, 0.0], vec![i as u8])
+                .unwrap();
+            assert_eq!(id, i);
+        }
+        assert!(collection.upsert(b"doc-0", vec![0.0], Vec::new()).is_err());
+        collection.commit().unwrap();
+        assert!(collection.delete(b"doc-3").unwrap());
+        assert!(!collection.delete(b"doc-3").unwrap());
+        // uncommitted when reopening.
+        assert_eq!(
+            collection
+                .upsert(b"doc-10", vec![3.0, 0.0], vec![10])
+                .unwrap(),
+            10
+        );
+        drop(collection);
+
+        let collection = reopen(dir.path(), config);
+        assert_eq!(collection.collection().len(), 10);
+        assert_eq!(collection.id_of(b"doc-10").unwrap(), Some(10));
+        assert_eq!(collection.key_of(4).unwrap(), Some(b"doc-4".to_vec()));
+        assert_eq!(collection.get(b"doc-3").unwrap(), None);
+
+        let hits = collection.search(&[3.1, 0.0], 2, search_params).unwrap();
+        assert_eq!(hits[0].key, b"doc-10");
+        assert_eq!(hits[0].payload, vec![10]);
+        assert_eq!(hits[1].key, b"doc-4");
+    }
+}
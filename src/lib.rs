@@ -12,18 +12,82 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod advisor;
+pub mod aligned;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod cluster;
+mod codec;
+pub mod collection;
+pub mod diagnostics;
+pub mod distributed;
+pub mod encryption;
 pub mod error;
+pub mod eval;
+pub mod exact;
 mod ffi;
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod fixtures;
+pub mod flat;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hooks;
+pub mod hybrid;
+pub mod index;
+pub mod ingest;
+pub mod ivf;
+mod kernels;
+#[cfg(feature = "sled")]
+pub mod kv;
+pub mod local;
+pub mod mapped;
+pub mod metric;
+#[cfg(feature = "mock-ffi")]
+pub mod mock;
+pub mod multi_vector;
+pub mod params;
+pub mod partitioned;
+pub mod preprocess;
+pub mod progress;
+pub mod query;
+pub mod report;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod sharded;
+pub mod snapshot;
+pub mod tiered;
+pub mod topk;
+mod trace;
+pub mod ttl;
 
+use std::borrow::Cow;
+use std::ffi::CStr;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::ops::Deref;
 use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
 use ffi::dump_index;
 
-use crate::error::Result;
+use crate::codec::Fnv1a;
+use crate::error::{Error, ErrorType, Result};
 use crate::ffi::{
-    build_index, create_index, free_index, from_c_error, from_c_f32_vector, from_c_i64_vector,
-    knn_search_index, to_c_string,
+    build_index, create_index, free_index, from_c_error, knn_search_index, to_c_string,
+    with_c_string, CVec,
 };
+use crate::hooks::Hooks;
+use crate::kernels::IdMap;
+use crate::metric::{json_compact, Metric};
+use crate::params::SearchParams;
+use crate::progress::{BuildProgress, BuildReport};
+use crate::trace::traced;
 
 /// `VsagIndex` is a wrapper around the C++ index object.
 ///
@@ -31,6 +95,66 @@ use crate::ffi::{
 pub struct VsagIndex {
     /// Pointer to the C++ index object.
     ptr: *const c_void,
+    /// Options applied on the Rust side.
+    options: IndexOptions,
+    /// Type of the index, e.g. `hnsw`.
+    index_type: String,
+    /// Parameters the index was created with.
+    params: String,
+    /// Metric type parsed from the index parameters.
+    metric: Option<Metric>,
+    /// Number of vectors in the index, [`NUM_VECTORS_UNKNOWN`] for loaded indexes.
+    num_vectors: AtomicUsize,
+    /// Set when vsag failed in a way that may leave the C++ object inconsistent.
+    poisoned: AtomicBool,
+}
+
+/// [`VsagIndex::num_vectors`] of indexes whose number of vectors isn't known.
+const NUM_VECTORS_UNKNOWN: usize = usize::MAX;
+/// Maximum number of positions listed in errors about invalid IDs.
+const MAX_REPORTED_IDS: usize = 10;
+
+/// Options of a [`VsagIndex`] that are applied on the Rust side, before calling into vsag.
+///
+/// They aren't persisted by [`VsagIndex::dump`], so pass the same options when loading.
+#[derive(Debug, Clone, Default)]
+pub struct IndexOptions {
+    /// L2-normalizes vectors on build and query vectors on search.
+    ///
+    /// vsag expects normalized data for the `cosine` metric, enable this unless your vectors are
+    /// already normalized.
+    pub normalize: bool,
+    /// How duplicate IDs passed to [`VsagIndex::build`] are handled.
+    pub dedup_policy: DedupPolicy,
+    /// Rejects vectors containing NaN or infinite values on build and search with
+    /// [`ErrorType::InvalidArgument`].
+    ///
+    /// Such values silently break graph construction in vsag, but scanning for them costs an
+    /// extra pass over the input, so it's opt-in.
+    pub validate_vectors: bool,
+    /// Rejects negative IDs on build with [`ErrorType::InvalidArgument`] listing their
+    /// positions.
+    ///
+    /// vsag accepts any `i64`, but applications commonly use negative IDs as "no result"
+    /// placeholders, e.g. when padding the output of [`VsagIndex::knn_search_into`], where a
+    /// real negative ID would be mistaken for one.
+    pub validate_ids: bool,
+    /// Callbacks on the lifecycle of the index.
+    pub hooks: Option<Arc<dyn Hooks>>,
+}
+
+/// How duplicate IDs in the input of [`VsagIndex::build`] are handled.
+///
+/// Duplicates are detected on the Rust side, so vsag never sees them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DedupPolicy {
+    /// Fails with [`ErrorType::InvalidArgument`].
+    #[default]
+    Error,
+    /// Keeps the first vector of each ID.
+    KeepFirst,
+    /// Keeps the last vector of each ID.
+    KeepLast,
 }
 
 /// The index in c doesn't contains any thread-locals variables, so it's sendable.
@@ -81,6 +205,11 @@ impl VsagIndex {
     ///         }
     ///      }
     pub fn new(index_type: &str, params: &str) -> Result<Self> {
+        Self::with_options(index_type, params, IndexOptions::default())
+    }
+
+    /// Creates a new vsag index with `options`, see [`VsagIndex::new`].
+    pub fn with_options(index_type: &str, params: &str, options: IndexOptions) -> Result<Self> {
         let index_type_c = to_c_string(index_type);
         let parameters_c = to_c_string(params);
 
@@ -90,9 +219,18 @@ impl VsagIndex {
 
             if !err.is_null() {
                 Err(from_c_error(err))
+            } else if (*out_index_ptr).is_null() {
+                Err(null_index_error("create"))
             } else {
+                trace::track_index(1.0);
                 Ok(VsagIndex {
                     ptr: *out_index_ptr,
+                    options,
+                    index_type: index_type.to_string(),
+                    params: params.to_string(),
+                    metric: Metric::from_params(params),
+                    num_vectors: AtomicUsize::new(0),
+                    poisoned: AtomicBool::new(false),
                 })
             }
         }
@@ -102,8 +240,15 @@ impl VsagIndex {
     ///
     /// All vectors are passed as a single slice of f32. If you have `num_vectors` vectors of dimension `dim`,
     /// you should pass a `vectors` slice of length `num_vectors * dim` and `ids` slice of length `num_vectors`.
+    /// [`ErrorType::InvalidArgument`] is returned if `dim` is zero, there are no vectors or
+    /// `vectors` is too short.
     ///
     /// Returns IDs of vectors that failed to be added to the index.
+    ///
+    /// Peak memory: vectors are passed to vsag without copying, unless
+    /// [`IndexOptions::normalize`] is set or there are duplicate IDs, in which case a single
+    /// copy of the input is made. vsag then needs the memory of the index on top of that, see
+    /// [`VsagIndex::build_chunked`] to avoid holding the whole input twice.
     pub fn build(
         &self,
         num_vectors: usize,
@@ -111,6 +256,215 @@ impl VsagIndex {
         ids: &[i64],
         vectors: &[f32],
     ) -> Result<Vec<i64>> {
+        self.build_with(num_vectors, dim, Instant::now(), || {
+            self.build_untraced(num_vectors, dim, ids, vectors)
+        })
+    }
+
+    /// Same as [`VsagIndex::build`], but returns a [`BuildReport`].
+    pub fn build_with_report(
+        &self,
+        num_vectors: usize,
+        dim: usize,
+        ids: &[i64],
+        vectors: &[f32],
+    ) -> Result<BuildReport> {
+        let start = Instant::now();
+        let failed_ids = self.build(num_vectors, dim, ids, vectors)?;
+        Ok(BuildReport {
+            num_vectors: num_vectors.min(ids.len()),
+            duration: start.elapsed(),
+            failed_ids,
+            peak_memory: progress::peak_memory(),
+        })
+    }
+
+    /// Same as [`VsagIndex::build`], but consumes the input as a sequence of chunks of IDs and
+    /// vectors, e.g. read from disk, bounding the memory used on top of the index.
+    ///
+    /// Each chunk is validated, deduplicated and normalized according to the options while
+    /// being copied into the single buffer vsag builds from, so peak memory is that buffer, one
+    /// chunk and the index, instead of the whole input held by the caller plus its copies.
+    ///
+    /// Progress is reported to [`Hooks::on_build_progress`] after each chunk, the total being
+    /// estimated from the number of chunks left if the iterator knows it.
+    pub fn build_chunked<I, V>(
+        &self,
+        dim: usize,
+        chunks: impl IntoIterator<Item = (I, V)>,
+    ) -> Result<BuildReport>
+    where
+        I: AsRef<[i64]>,
+        V: AsRef<[f32]>,
+    {
+        let start = Instant::now();
+        let mut chunks = chunks.into_iter();
+        let mut staged_ids: Vec<i64> = Vec::new();
+        let mut staged_vectors: Vec<f32> = Vec::new();
+        // id -> position of its vector in the staging buffer.
+        let mut positions: IdMap<usize> = IdMap::default();
+        let mut offset = 0;
+        check_dim(dim)?;
+        while let Some((ids, vectors)) = chunks.next() {
+            let (ids, vectors) = (ids.as_ref(), vectors.as_ref());
+            if vectors.len() != ids.len() * dim {
+                return Err(Error::new(
+                    ErrorType::InvalidArgument,
+                    format!("chunk at position {offset} has mismatched ids and vectors"),
+                ));
+            }
+            if self.options.validate_vectors {
+                if let Some(pos) = kernels::first_non_finite(vectors, dim) {
+                    return Err(Error::new(
+                        ErrorType::InvalidArgument,
+                        format!(
+                            "vector at position {} contains NaN or infinite values",
+                            offset + pos
+                        ),
+                    ));
+                }
+            }
+            if self.options.validate_ids {
+                check_ids(ids, offset)?;
+            }
+
+            for (pos, (&id, vector)) in ids.iter().zip(vectors.chunks_exact(dim)).enumerate() {
+                let target = match positions.entry(id) {
+                    std::collections::hash_map::Entry::Vacant(entry) => {
+                        entry.insert(staged_ids.len());
+                        staged_ids.push(id);
+                        staged_vectors.extend_from_slice(vector);
+                        staged_ids.len() - 1
+                    }
+                    std::collections::hash_map::Entry::Occupied(entry) => {
+                        match self.options.dedup_policy {
+                            DedupPolicy::Error => {
+                                return Err(Error::new(
+                                    ErrorType::InvalidArgument,
+                                    format!("duplicate id {id} at position {}", offset + pos),
+                                ))
+                            }
+                            DedupPolicy::KeepFirst => continue,
+                            DedupPolicy::KeepLast => {
+                                let target = *entry.get();
+                                staged_vectors[target * dim..(target + 1) * dim]
+                                    .copy_from_slice(vector);
+                                target
+                            }
+                        }
+                    }
+                };
+                if self.options.normalize {
+                    kernels::normalize(&mut staged_vectors[target * dim..(target + 1) * dim]);
+                }
+            }
+            offset += ids.len();
+
+            if let Some(hooks) = &self.options.hooks {
+                let (chunks_left, exact) = chunks.size_hint();
+                let progress = BuildProgress {
+                    vectors_done: offset,
+                    total_vectors: (exact == Some(chunks_left))
+                        .then(|| offset + chunks_left * ids.len()),
+                    elapsed: start.elapsed(),
+                };
+                hooks.on_build_progress(&self.index_type, &progress);
+            }
+        }
+        drop(positions);
+
+        let failed_ids = self.build_with(staged_ids.len(), dim, start, || {
+            self.build_raw(staged_ids.len(), dim, &staged_ids, &staged_vectors)
+        })?;
+        Ok(BuildReport {
+            num_vectors: staged_ids.len(),
+            duration: start.elapsed(),
+            failed_ids,
+            peak_memory: progress::peak_memory(),
+        })
+    }
+
+    /// Runs the build `op` of `num_vectors` vectors with tracing and hooks, `build_start` being
+    /// when the whole build started.
+    fn build_with(
+        &self,
+        num_vectors: usize,
+        dim: usize,
+        build_start: Instant,
+        op: impl FnOnce() -> Result<Vec<i64>>,
+    ) -> Result<Vec<i64>> {
+        let hooks = self.options.hooks.as_deref();
+        if let Some(hooks) = hooks {
+            hooks.on_build_start(&self.index_type, num_vectors, dim);
+        }
+        let start = Instant::now();
+        let result = traced!(
+            "build",
+            None,
+            op(),
+            index_type = %self.index_type,
+            num_vectors,
+            dim
+        );
+        if let Some(hooks) = hooks {
+            hooks.on_build_complete(&self.index_type, result.as_deref(), start.elapsed());
+            if result.is_ok() {
+                let progress = BuildProgress {
+                    vectors_done: num_vectors,
+                    total_vectors: Some(num_vectors),
+                    elapsed: build_start.elapsed(),
+                };
+                hooks.on_build_progress(&self.index_type, &progress);
+            }
+        }
+        result
+    }
+
+    fn build_untraced(
+        &self,
+        num_vectors: usize,
+        dim: usize,
+        ids: &[i64],
+        vectors: &[f32],
+    ) -> Result<Vec<i64>> {
+        check_dim(dim)?;
+        check_build_input(num_vectors, dim, ids, vectors)?;
+        if self.options.validate_vectors {
+            if let Some(pos) = kernels::first_non_finite(vectors, dim) {
+                return Err(Error::new(
+                    ErrorType::InvalidArgument,
+                    format!("vector at position {pos} contains NaN or infinite values"),
+                ));
+            }
+        }
+        if self.options.validate_ids {
+            check_ids(&ids[..num_vectors], 0)?;
+        }
+        let (ids, vectors) = dedup(self.options.dedup_policy, num_vectors, dim, ids, vectors)?;
+        let num_vectors = ids.len();
+        let vectors = match vectors {
+            Cow::Owned(mut vectors) if self.options.normalize => {
+                if dim > 0 {
+                    vectors.chunks_exact_mut(dim).for_each(kernels::normalize);
+                }
+                Cow::Owned(vectors)
+            }
+            vectors if self.options.normalize => Cow::Owned(kernels::normalized(&vectors, dim)),
+            vectors => vectors,
+        };
+        self.build_raw(num_vectors, dim, &ids, &vectors)
+    }
+
+    /// Builds the index from vectors already prepared according to the options.
+    fn build_raw(
+        &self,
+        num_vectors: usize,
+        dim: usize,
+        ids: &[i64],
+        vectors: &[f32],
+    ) -> Result<Vec<i64>> {
+        self.check_poisoned()?;
+        check_build_input(num_vectors, dim, ids, vectors)?;
         unsafe {
             let out_failed_ids: *mut *const i64 = &mut std::ptr::null();
             let out_num_failed: *mut usize = &mut 0;
@@ -125,15 +479,36 @@ impl VsagIndex {
             );
 
             if !err.is_null() {
-                Err(from_c_error(err))
+                Err(self.poison_on_fatal(from_c_error(err)))
             } else {
-                Ok(from_c_i64_vector(*out_failed_ids, *out_num_failed))
+                self.num_vectors
+                    .store(num_vectors - *out_num_failed, Ordering::Relaxed);
+                Ok(CVec::from_raw(*out_failed_ids, *out_num_failed).to_vec())
             }
         }
     }
 
+    /// Same as [`VsagIndex::build`], but accepts `f64` vectors.
+    ///
+    /// The index stores vectors as `f32`, so `vectors` is converted in a single pass before
+    /// building, and any precision beyond `f32` is lost.
+    pub fn build_f64(
+        &self,
+        num_vectors: usize,
+        dim: usize,
+        ids: &[i64],
+        vectors: &[f64],
+    ) -> Result<Vec<i64>> {
+        self.build(num_vectors, dim, ids, &kernels::to_f32(vectors))
+    }
+
     /// Searches for the `k` nearest neighbors of the `query_vector`.
     ///
+    /// `query_vector` must not be empty and `k` must be positive, otherwise
+    /// [`ErrorType::InvalidArgument`] is returned. At most
+    /// `k` results are returned, fewer if the index holds fewer vectors, and none if it isn't
+    /// built yet.
+    ///
     /// `search_params` is a JSON string that specifies the search parameters.
     ///
     /// HNSW.search_params in JSON format:
@@ -167,8 +542,112 @@ impl VsagIndex {
         k: usize,
         search_params: &str,
     ) -> Result<KnnSearchOutput> {
-        let search_params = to_c_string(search_params);
+        self.knn_search_ref(query_vector, k, search_params)
+            .map(|output| output.to_output())
+    }
 
+    /// Same as [`VsagIndex::knn_search`], but returns the result buffers allocated by vsag
+    /// without copying them.
+    ///
+    /// The buffers are freed when the returned [`KnnSearchOutputRef`] is dropped.
+    pub fn knn_search_ref(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+    ) -> Result<KnnSearchOutputRef> {
+        with_c_string(search_params, |c_search_params| {
+            self.knn_search_c(query_vector, k, search_params, c_search_params)
+        })
+    }
+
+    /// Same as [`VsagIndex::knn_search_ref`], but the output borrows the index, so it can't be
+    /// used after the index is freed.
+    pub fn knn_search_borrowed(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+    ) -> Result<KnnSearchRef<'_>> {
+        let output = self.knn_search_ref(query_vector, k, search_params)?;
+        Ok(KnnSearchRef {
+            output,
+            _index: PhantomData,
+        })
+    }
+
+    /// Searches with `search_params` already converted into `c_search_params`.
+    pub(crate) fn knn_search_c(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+        c_search_params: &CStr,
+    ) -> Result<KnnSearchOutputRef> {
+        let start = Instant::now();
+        let result = traced!(
+            "knn_search",
+            Some(KnnSearchOutputRef::len),
+            self.knn_search_untraced(query_vector, k, c_search_params),
+            index_type = %self.index_type,
+            dim = query_vector.len(),
+            k,
+            ef_search = trace::ef_search(search_params)
+        );
+        if let Some(hooks) = &self.options.hooks {
+            let elapsed = start.elapsed();
+            if hooks
+                .slow_search_threshold()
+                .is_some_and(|threshold| elapsed >= threshold)
+            {
+                hooks.on_search_slow(&self.index_type, k, search_params, elapsed);
+            }
+        }
+        result
+    }
+
+    fn knn_search_untraced(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &CStr,
+    ) -> Result<KnnSearchOutputRef> {
+        self.check_poisoned()?;
+        if query_vector.is_empty() {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "query vector is empty",
+            ));
+        }
+        if k == 0 {
+            return Err(Error::new(ErrorType::InvalidArgument, "k must be positive"));
+        }
+        let k = match self.num_vectors.load(Ordering::Relaxed) {
+            NUM_VECTORS_UNKNOWN => k,
+            0 => {
+                // SAFETY: null buffers of no elements are never freed.
+                return Ok(unsafe {
+                    KnnSearchOutputRef {
+                        ids: CVec::from_raw(std::ptr::null(), 0),
+                        distances: CVec::from_raw(std::ptr::null(), 0),
+                    }
+                });
+            }
+            num_vectors => k.min(num_vectors),
+        };
+        if self.options.validate_vectors
+            && kernels::first_non_finite(query_vector, query_vector.len()).is_some()
+        {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "query vector contains NaN or infinite values",
+            ));
+        }
+        let query_vector = if self.options.normalize {
+            Cow::Owned(kernels::normalized(query_vector, query_vector.len()))
+        } else {
+            Cow::Borrowed(query_vector)
+        };
         unsafe {
             let out_ids: *mut *const i64 = &mut std::ptr::null();
             let out_distances: *mut *const f32 = &mut std::ptr::null();
@@ -185,24 +664,172 @@ impl VsagIndex {
             );
 
             if !err.is_null() {
-                Err(from_c_error(err))
+                Err(self.poison_on_fatal(from_c_error(err)))
             } else {
-                Ok(KnnSearchOutput {
-                    ids: from_c_i64_vector(*out_ids, *out_num_results),
-                    distances: from_c_f32_vector(*out_distances, *out_num_results),
+                Ok(KnnSearchOutputRef {
+                    ids: CVec::from_raw(*out_ids, *out_num_results),
+                    distances: CVec::from_raw(*out_distances, *out_num_results),
                 })
             }
         }
     }
 
+    /// Same as [`VsagIndex::knn_search`], but writes the results into `ids` and `distances`.
+    ///
+    /// Both vectors are cleared first, so their capacity can be reused across searches.
+    pub fn knn_search_into(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+        ids: &mut Vec<i64>,
+        distances: &mut Vec<f32>,
+    ) -> Result<()> {
+        let output = self.knn_search_ref(query_vector, k, search_params)?;
+        ids.clear();
+        ids.extend_from_slice(output.ids());
+        distances.clear();
+        distances.extend_from_slice(output.distances());
+        Ok(())
+    }
+
+    /// Same as [`VsagIndex::knn_search`], but accepts an `f64` query vector.
+    ///
+    /// The query is converted to `f32` before searching, since that's how vectors are stored.
+    pub fn knn_search_f64(
+        &self,
+        query_vector: &[f64],
+        k: usize,
+        search_params: &str,
+    ) -> Result<KnnSearchOutput> {
+        self.knn_search(&kernels::to_f32(query_vector), k, search_params)
+    }
+
+    /// Returns the number of vectors in the index, `None` for a loaded index since vsag
+    /// doesn't persist it.
+    pub fn num_vectors(&self) -> Option<usize> {
+        match self.num_vectors.load(Ordering::Relaxed) {
+            NUM_VECTORS_UNKNOWN => None,
+            num_vectors => Some(num_vectors),
+        }
+    }
+
+    /// Returns `true` if a previous call failed with an internal error of vsag, after which
+    /// building, searching and dumping fail with [`ErrorType::IndexPoisoned`] instead of
+    /// calling into a possibly inconsistent C++ object.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    /// Frees the index, like dropping it, but fails with [`ErrorType::IndexPoisoned`] if it
+    /// was poisoned, see [`VsagIndex::is_poisoned`], so a clean shutdown can be told apart from
+    /// one after an internal error. The index is freed either way.
+    ///
+    /// `free_index` itself doesn't report errors.
+    pub fn close(mut self) -> Result<()> {
+        let result = self.check_poisoned();
+        if !self.ptr.is_null() {
+            unsafe {
+                free_index(self.ptr);
+            }
+            self.ptr = std::ptr::null();
+            trace::track_index(-1.0);
+        }
+        result
+    }
+
+    fn check_poisoned(&self) -> Result<()> {
+        if self.is_poisoned() {
+            return Err(Error::new(
+                ErrorType::IndexPoisoned,
+                "index is unusable after a previous internal error, recreate or reload it",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Poisons the index if `err`, returned by vsag, may have left the C++ object in an
+    /// inconsistent state, i.e. unknown and internal errors, or an allocation failure half way
+    /// through, rather than errors of validation.
+    fn poison_on_fatal(&self, err: Error) -> Error {
+        if matches!(
+            err.error_type,
+            ErrorType::UnknownError | ErrorType::InternalError | ErrorType::NoEnoughMemory
+        ) {
+            self.poisoned.store(true, Ordering::Relaxed);
+        }
+        err
+    }
+
+    /// Returns a hash of the structure of the index: its type, parameters and number of
+    /// vectors, identical on all machines for identical indexes, e.g. for replicas to check
+    /// they serve the same generation before serving, or as a cache key.
+    ///
+    /// Whitespace in the parameters is ignored, but not the order of their fields, so
+    /// parameters must be written with their fields in the same order to match.
+    ///
+    /// The number of vectors of a loaded index is unknown, see [`VsagIndex::num_vectors`], so
+    /// loaded indexes only match each other. Compare [`VsagIndex::fingerprint_file`] of the
+    /// dumps to tell generations with the same structure apart.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        hasher.write_field(self.index_type.as_bytes());
+        hasher.write_field(json_compact(&self.params).as_bytes());
+        match self.num_vectors() {
+            Some(num_vectors) => hasher.write_field(&(num_vectors as u64).to_le_bytes()),
+            None => hasher.write_field(b"loaded"),
+        }
+        hasher.finish()
+    }
+
+    /// Returns a hash of the content of the index dumped at `path`, see
+    /// [`VsagIndex::fingerprint`]. It reads the whole file.
+    pub fn fingerprint_file(path: &str) -> Result<u64> {
+        let mut reader = File::open(path)?;
+        let mut hasher = Fnv1a::default();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let len = reader.read(&mut buf)?;
+            if len == 0 {
+                return Ok(hasher.finish());
+            }
+            hasher.write(&buf[..len]);
+        }
+    }
+
+    /// Returns the type of the index, e.g. `hnsw`.
+    pub fn index_type(&self) -> &str {
+        &self.index_type
+    }
+
+    /// Returns the metric type of the index, `None` if it's missing from the parameters.
+    pub fn metric(&self) -> Option<Metric> {
+        self.metric
+    }
+
     /// Dumps the index to the file at `path`.
-    pub fn dump(self, path: &str) -> Result<()> {
+    pub fn dump(&self, path: &str) -> Result<()> {
+        let result = traced!(
+            "dump",
+            None,
+            self.dump_untraced(path),
+            index_type = %self.index_type,
+            path
+        );
+        if let Some(hooks) = &self.options.hooks {
+            hooks.on_dump(&self.index_type, path, result.as_ref().map(|_| ()));
+        }
+        result
+    }
+
+    fn dump_untraced(&self, path: &str) -> Result<()> {
+        self.check_poisoned()?;
         let path = to_c_string(path);
 
         unsafe {
             let err = dump_index(self.ptr, path.as_ptr());
             if !err.is_null() {
-                Err(from_c_error(err))
+                Err(self.poison_on_fatal(from_c_error(err)))
             } else {
                 Ok(())
             }
@@ -213,6 +840,39 @@ impl VsagIndex {
     ///
     /// `index_type` and `params` should be the same as the ones used to create the index.
     pub fn load(path: &str, index_type: &str, params: &str) -> Result<Self> {
+        Self::load_with_options(path, index_type, params, IndexOptions::default())
+    }
+
+    /// Loads an index from the file at `path` with `options`, see [`VsagIndex::load`].
+    pub fn load_with_options(
+        path: &str,
+        index_type: &str,
+        params: &str,
+        options: IndexOptions,
+    ) -> Result<Self> {
+        let hooks = options.hooks.clone();
+        let result = traced!(
+            "load",
+            None,
+            Self::load_untraced(path, index_type, params, options),
+            index_type,
+            path
+        );
+        if let Some(hooks) = hooks {
+            hooks.on_load(index_type, path, result.as_ref().map(|_| ()));
+        }
+        result
+    }
+
+    fn load_untraced(
+        path: &str,
+        index_type: &str,
+        params: &str,
+        options: IndexOptions,
+    ) -> Result<Self> {
+        let metric = Metric::from_params(params);
+        let index_type_str = index_type.to_string();
+        let params_str = params.to_string();
         let path = to_c_string(path);
         let index_type = to_c_string(index_type);
         let params = to_c_string(params);
@@ -228,13 +888,66 @@ impl VsagIndex {
 
             if !err.is_null() {
                 Err(from_c_error(err))
+            } else if (*out_index_ptr).is_null() {
+                Err(null_index_error("load"))
             } else {
+                trace::track_index(1.0);
                 Ok(VsagIndex {
                     ptr: *out_index_ptr,
+                    options,
+                    index_type: index_type_str,
+                    params: params_str,
+                    metric,
+                    num_vectors: AtomicUsize::new(NUM_VECTORS_UNKNOWN),
+                    poisoned: AtomicBool::new(false),
                 })
             }
         }
     }
+
+    /// Warms the index up by searching `sample_queries` and discarding the results, so the
+    /// regions of the graph they traverse are paged in before serving traffic, e.g. right after
+    /// [`VsagIndex::load`].
+    ///
+    /// `sample_queries` holds query vectors of dimension `dim` in a single slice. Queries that
+    /// resemble the production traffic warm up the regions that matter.
+    pub fn warmup(
+        &self,
+        sample_queries: &[f32],
+        dim: usize,
+        k: usize,
+        search_params: &str,
+    ) -> Result<()> {
+        if dim == 0 || !sample_queries.chunks_exact(dim).remainder().is_empty() {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                format!("length of sample queries is not a multiple of dim {dim}"),
+            ));
+        }
+
+        for query in sample_queries.chunks_exact(dim) {
+            self.knn_search_ref(query, k, search_params)?;
+        }
+        Ok(())
+    }
+
+    /// Hints that `query_vector` is about to be searched, e.g. when it's known a few
+    /// milliseconds ahead in a pipeline, so the nodes around the path from the entry point
+    /// towards it are paged in and cached by the time of the real search.
+    ///
+    /// It runs a greedy search with `ef_search` 1 and discards the result: vsag doesn't expose
+    /// its graph, so that's the cheapest way to touch the nodes a search would visit first.
+    pub fn prefetch(&self, query_vector: &[f32]) -> Result<()> {
+        let mut params = SearchParams::default_for(&self.index_type).ok_or_else(|| {
+            Error::new(
+                ErrorType::UnsupportedIndex,
+                format!("prefetch is not supported by {}", self.index_type),
+            )
+        })?;
+        params.set_ef_search(1);
+        self.knn_search_ref(query_vector, 1, &params.to_json())?;
+        Ok(())
+    }
 }
 
 impl Drop for VsagIndex {
@@ -243,11 +956,132 @@ impl Drop for VsagIndex {
             unsafe {
                 free_index(self.ptr);
             }
+            trace::track_index(-1.0);
         }
     }
 }
 
+fn check_dim(dim: usize) -> Result<()> {
+    if dim == 0 {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            "dim must be positive",
+        ));
+    }
+    Ok(())
+}
+
+/// Fails unless there are `num_vectors` ids and vectors of `dim` to build from.
+fn check_build_input(num_vectors: usize, dim: usize, ids: &[i64], vectors: &[f32]) -> Result<()> {
+    // vsag would read past the end of shorter or empty buffers.
+    if num_vectors == 0 {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            "no vectors to build the index from",
+        ));
+    }
+    if ids.len() < num_vectors || vectors.len() < num_vectors * dim {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            format!(
+                "expect {num_vectors} ids and {} vector values, got {} and {}",
+                num_vectors * dim,
+                ids.len(),
+                vectors.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails if `ids`, starting at position `offset` of the input, contains negative IDs, listing
+/// the positions of the first ones.
+fn check_ids(ids: &[i64], offset: usize) -> Result<()> {
+    let mut negative = ids.iter().enumerate().filter(|(_, &id)| id < 0);
+    let Some(first) = negative.next() else {
+        return Ok(());
+    };
+    let positions: Vec<String> = std::iter::once(first)
+        .chain(negative)
+        .take(MAX_REPORTED_IDS)
+        .map(|(pos, id)| format!("{} (id {id})", offset + pos))
+        .collect();
+    let count = ids.iter().filter(|&&id| id < 0).count();
+    Err(Error::new(
+        ErrorType::InvalidArgument,
+        format!(
+            "{count} negative ids, at positions {}{}",
+            positions.join(", "),
+            if count > MAX_REPORTED_IDS {
+                ", ..."
+            } else {
+                ""
+            }
+        ),
+    ))
+}
+
+/// Error of a `create` or `load` call of vsag which returned neither an error nor an index.
+fn null_index_error(call: &str) -> Error {
+    Error::new(
+        ErrorType::InternalError,
+        format!("vsag {call} returned no error but a null index"),
+    )
+}
+
+/// IDs and vectors passed to vsag on build, borrowed from the input unless rewritten.
+type BuildInput<'a> = (Cow<'a, [i64]>, Cow<'a, [f32]>);
+
+/// Applies `policy` to duplicate IDs among the first `num_vectors` of `ids`.
+///
+/// The input is borrowed as is when there are no duplicates.
+fn dedup<'a>(
+    policy: DedupPolicy,
+    num_vectors: usize,
+    dim: usize,
+    ids: &'a [i64],
+    vectors: &'a [f32],
+) -> Result<BuildInput<'a>> {
+    let ids = &ids[..num_vectors.min(ids.len())];
+
+    // id -> position of the vector to keep
+    let mut kept: IdMap<usize> = IdMap::with_capacity_and_hasher(ids.len(), Default::default());
+    let mut has_duplicates = false;
+    for (pos, &id) in ids.iter().enumerate() {
+        if let Some(prev) = kept.insert(id, pos) {
+            has_duplicates = true;
+            match policy {
+                DedupPolicy::Error => {
+                    return Err(Error::new(
+                        ErrorType::InvalidArgument,
+                        format!("duplicate id {id} at positions {prev} and {pos}"),
+                    ))
+                }
+                DedupPolicy::KeepFirst => {
+                    kept.insert(id, prev);
+                }
+                DedupPolicy::KeepLast => {}
+            }
+        }
+    }
+    if !has_duplicates {
+        return Ok((Cow::Borrowed(ids), Cow::Borrowed(vectors)));
+    }
+
+    let mut dedup_ids = Vec::with_capacity(kept.len());
+    let mut dedup_vectors = Vec::with_capacity(kept.len() * dim);
+    for (pos, &id) in ids.iter().enumerate() {
+        if kept[&id] == pos {
+            dedup_ids.push(id);
+            dedup_vectors.extend_from_slice(&vectors[pos * dim..(pos + 1) * dim]);
+        }
+    }
+    Ok((Cow::Owned(dedup_ids), Cow::Owned(dedup_vectors)))
+}
+
 /// Output of a k-NN search.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct KnnSearchOutput {
     /// IDs of the k-NNs.
     pub ids: Vec<i64>,
@@ -255,12 +1089,118 @@ pub struct KnnSearchOutput {
     pub distances: Vec<f32>,
 }
 
-#[cfg(test)]
+#[cfg(feature = "serde")]
+impl KnnSearchOutput {
+    /// Serializes the output as a JSON object, e.g. `{"ids":[1,2],"distances":[0.1,0.2]}`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("KnnSearchOutput is always serializable")
+    }
+}
+
+/// Output of a k-NN search backed by the buffers allocated by vsag.
+///
+/// When the `KnnSearchOutputRef` is dropped, the buffers are freed.
+pub struct KnnSearchOutputRef {
+    ids: CVec<i64>,
+    distances: CVec<f32>,
+}
+
+impl KnnSearchOutputRef {
+    /// IDs of the k-NNs.
+    pub fn ids(&self) -> &[i64] {
+        &self.ids
+    }
+
+    /// Distances of the k-NNs.
+    pub fn distances(&self) -> &[f32] {
+        &self.distances
+    }
+
+    /// Number of results.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns `true` if the search found nothing.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Copies the results into an owned [`KnnSearchOutput`].
+    pub fn to_output(&self) -> KnnSearchOutput {
+        KnnSearchOutput {
+            ids: self.ids().to_vec(),
+            distances: self.distances().to_vec(),
+        }
+    }
+}
+
+/// Output of [`VsagIndex::knn_search_borrowed`], a [`KnnSearchOutputRef`] that can't outlive
+/// the index it comes from.
+///
+/// The buffers are freed when it's dropped, and the borrow of the index ensures the index is
+/// still alive meanwhile:
+///
+/// ```compile_fail
+/// # use vsag::VsagIndex;
+/// let index = VsagIndex::new("hnsw", "{}").unwrap();
+/// let output = index.knn_search_borrowed(&[0.0], 1, "{}").unwrap();
+/// drop(index);
+/// output.ids();
+/// ```
+pub struct KnnSearchRef<'a> {
+    output: KnnSearchOutputRef,
+    _index: PhantomData<&'a VsagIndex>,
+}
+
+impl KnnSearchRef<'_> {
+    /// Releases the borrow of the index, keeping the buffers.
+    pub fn into_output_ref(self) -> KnnSearchOutputRef {
+        self.output
+    }
+}
+
+impl Deref for KnnSearchRef<'_> {
+    type Target = KnnSearchOutputRef;
+
+    fn deref(&self) -> &KnnSearchOutputRef {
+        &self.output
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
 mod tests {
     use simsimd::SpatialSimilarity;
 
     use super::*;
 
+    /// Dimension of the vectors of [`random_index`].
+    const DIM: usize = 8;
+    const CON_PARAMS: &str = r#"{
+        "dtype": "float32",
+        "metric_type": "l2",
+        "dim": 8,
+        "hnsw": {
+            "max_degree": 16,
+            "ef_construction": 100
+        }
+    }"#;
+    const SEARCH_PARAMS: &str = r#"{"hnsw": {"ef_search": 100}}"#;
+
+    /// Builds an hnsw index of `num_vectors` random vectors with IDs from 0, returns it with the
+    /// vectors.
+    fn random_index(num_vectors: usize) -> (VsagIndex, Vec<f32>) {
+        let index = VsagIndex::new("hnsw", CON_PARAMS).unwrap();
+        let ids: Vec<i64> = (0..num_vectors as i64).collect();
+        let vectors: Vec<f32> = (0..num_vectors * DIM).map(|_| rand::random()).collect();
+        index.build(num_vectors, DIM, &ids, &vectors).unwrap();
+        (index, vectors)
+    }
+
+    fn random_vector() -> Vec<f32> {
+        (0..DIM).map(|_| rand::random()).collect()
+    }
+
     #[test]
     fn test_create_build_search_index_hnsw_l2() {
         let index_type = "hnsw";
@@ -394,4 +1334,467 @@ mod tests {
         assert_eq!(output.ids, output2.ids);
         assert_eq!(output.distances, output2.distances);
     }
+
+    #[test]
+    fn test_knn_search_ref() {
+        let (index, _) = random_index(100);
+        let query_vector = random_vector();
+        let output = index.knn_search(&query_vector, 10, SEARCH_PARAMS).unwrap();
+        let output_ref = index
+            .knn_search_ref(&query_vector, 10, SEARCH_PARAMS)
+            .unwrap();
+        assert_eq!(output_ref.len(), 10);
+        assert_eq!(output.ids, output_ref.ids());
+        assert_eq!(output.distances, output_ref.distances());
+    }
+
+    #[test]
+    fn test_knn_search_borrowed() {
+        let (index, _) = random_index(100);
+        let query_vector = random_vector();
+        let output = index.knn_search(&query_vector, 10, SEARCH_PARAMS).unwrap();
+        let output_borrowed = index
+            .knn_search_borrowed(&query_vector, 10, SEARCH_PARAMS)
+            .unwrap();
+        assert_eq!(output.ids, output_borrowed.ids());
+        assert_eq!(output.distances, output_borrowed.distances());
+
+        // the released buffers outlive the index.
+        let output_ref = output_borrowed.into_output_ref();
+        drop(index);
+        assert_eq!(output.ids, output_ref.ids());
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let (index, _) = random_index(100);
+        let query_vector = random_vector();
+        let output = index.knn_search(&query_vector, 10, SEARCH_PARAMS).unwrap();
+        index.prefetch(&query_vector).unwrap();
+        // the hint doesn't change the results of the real search.
+        let prefetched = index.knn_search(&query_vector, 10, SEARCH_PARAMS).unwrap();
+        assert_eq!(output.ids, prefetched.ids);
+        assert_eq!(output.distances, prefetched.distances);
+        // the query is still checked.
+        assert!(index.prefetch(&query_vector[1..]).is_err());
+    }
+
+    #[test]
+    fn test_warmup() {
+        let (index, vectors) = random_index(100);
+        index
+            .warmup(&vectors[..10 * DIM], DIM, 10, SEARCH_PARAMS)
+            .unwrap();
+        // not a whole number of queries.
+        assert!(index
+            .warmup(&vectors[..DIM + 1], DIM, 10, SEARCH_PARAMS)
+            .is_err());
+
+        let dir = tempdir::TempDir::new("test_warmup").unwrap();
+        let path = dir.path().join("index").display().to_string();
+        index.dump(&path).unwrap();
+        let loaded = VsagIndex::load(&path, "hnsw", CON_PARAMS).unwrap();
+        loaded.warmup(&vectors, DIM, 10, SEARCH_PARAMS).unwrap();
+        let query_vector = random_vector();
+        assert_eq!(
+            loaded
+                .knn_search(&query_vector, 10, SEARCH_PARAMS)
+                .unwrap()
+                .ids,
+            index
+                .knn_search(&query_vector, 10, SEARCH_PARAMS)
+                .unwrap()
+                .ids
+        );
+    }
+
+    #[test]
+    fn test_f64() {
+        let (index, vectors) = random_index(100);
+        let query_vector = random_vector();
+        let output = index.knn_search(&query_vector, 10, SEARCH_PARAMS).unwrap();
+        let query_vector_f64: Vec<f64> = query_vector.iter().map(|&v| v as f64).collect();
+        let output_f64 = index
+            .knn_search_f64(&query_vector_f64, 10, SEARCH_PARAMS)
+            .unwrap();
+        assert_eq!(output.ids, output_f64.ids);
+        assert_eq!(output.distances, output_f64.distances);
+
+        let index_f64 = VsagIndex::new("hnsw", CON_PARAMS).unwrap();
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors_f64: Vec<f64> = vectors.iter().map(|&v| v as f64).collect();
+        index_f64.build_f64(100, DIM, &ids, &vectors_f64).unwrap();
+        let output_f64 = index_f64
+            .knn_search(&query_vector, 10, SEARCH_PARAMS)
+            .unwrap();
+        assert_eq!(output.ids, output_f64.ids);
+    }
+
+    #[test]
+    fn test_knn_search_into() {
+        let (index, _) = random_index(100);
+        let query_vector = random_vector();
+        let output = index.knn_search(&query_vector, 10, SEARCH_PARAMS).unwrap();
+
+        // the buffers are overwritten, whatever they held.
+        let mut ids = vec![-1; 32];
+        let mut distances = Vec::new();
+        index
+            .knn_search_into(&query_vector, 10, SEARCH_PARAMS, &mut ids, &mut distances)
+            .unwrap();
+        assert_eq!(output.ids, ids);
+        assert_eq!(output.distances, distances);
+
+        let capacity = ids.capacity();
+        index
+            .knn_search_into(&query_vector, 5, SEARCH_PARAMS, &mut ids, &mut distances)
+            .unwrap();
+        assert_eq!(ids, output.ids[..5]);
+        assert_eq!(distances, output.distances[..5]);
+        assert_eq!(ids.capacity(), capacity);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_knn_search_output_json() {
+        let output = KnnSearchOutput {
+            ids: vec![1, 2],
+            distances: vec![0.5, 1.5],
+        };
+        let json = output.to_json();
+        assert_eq!(json, r#"{"ids":[1,2],"distances":[0.5,1.5]}"#);
+
+        let output2: KnnSearchOutput = serde_json::from_str(&json).unwrap();
+        assert_eq!(output.ids, output2.ids);
+        assert_eq!(output.distances, output2.distances);
+    }
+
+    #[test]
+    fn test_normalize_option() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "ip",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let options = IndexOptions {
+            normalize: true,
+            ..Default::default()
+        };
+
+        let index = VsagIndex::with_options("hnsw", con_params, options).unwrap();
+        index.build(2, 2, &[0, 1], &[2.0, 0.0, 0.0, 3.0]).unwrap();
+
+        let output = index.knn_search(&[5.0, 0.0], 2, search_params).unwrap();
+        assert_eq!(output.ids, vec![0, 1]);
+        assert!(output.distances[0].abs() < 1e-6);
+        assert!((output.distances[1] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let ids = [1, 2, 1, 3];
+        let vectors = [1.0, 2.0, 3.0, 4.0];
+
+        let err = dedup(DedupPolicy::Error, 4, 1, &ids, &vectors).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+
+        let (dedup_ids, dedup_vectors) =
+            dedup(DedupPolicy::KeepFirst, 4, 1, &ids, &vectors).unwrap();
+        assert_eq!(&*dedup_ids, &[1, 2, 3]);
+        assert_eq!(&*dedup_vectors, &[1.0, 2.0, 4.0]);
+
+        let (dedup_ids, dedup_vectors) =
+            dedup(DedupPolicy::KeepLast, 4, 1, &ids, &vectors).unwrap();
+        assert_eq!(&*dedup_ids, &[2, 1, 3]);
+        assert_eq!(&*dedup_vectors, &[2.0, 3.0, 4.0]);
+
+        let (dedup_ids, _) = dedup(DedupPolicy::Error, 2, 1, &ids, &vectors).unwrap();
+        assert!(matches!(dedup_ids, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_build_short_vectors() {
+        let options = IndexOptions {
+            dedup_policy: DedupPolicy::KeepLast,
+            normalize: true,
+            ..Default::default()
+        };
+        let index = VsagIndex::with_options("hnsw", CON_PARAMS, options).unwrap();
+        let ids = [1, 2, 1, 3];
+        let vectors = vec![1.0; DIM * 2];
+        let err = index.build(ids.len(), DIM, &ids, &vectors).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+        assert!(err.message.contains("vector values"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_empty_inputs() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        fn invalid<T>(result: Result<T>) {
+            assert!(matches!(
+                result.map(drop).unwrap_err().error_type,
+                ErrorType::InvalidArgument
+            ))
+        }
+
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        invalid(index.build(0, 2, &[], &[]));
+        invalid(index.build(1, 0, &[1], &[]));
+        invalid(index.build(2, 2, &[1, 2], &[0.0; 3]));
+        invalid(index.build_f64(0, 2, &[], &[]));
+        invalid(index.build_chunked(0, [(vec![1], vec![])]));
+        invalid(index.build_chunked(2, Vec::<(Vec<i64>, Vec<f32>)>::new()));
+        assert_eq!(index.num_vectors(), Some(0));
+
+        index.build(2, 2, &[1, 2], &[0.0; 4]).unwrap();
+        invalid(index.knn_search(&[], 1, search_params));
+        invalid(index.knn_search_ref(&[], 1, search_params));
+        invalid(index.knn_search_borrowed(&[], 1, search_params));
+        invalid(index.knn_search_f64(&[], 1, search_params));
+        invalid(index.knn_search_into(&[], 1, search_params, &mut vec![], &mut vec![]));
+        invalid(index.prefetch(&[]));
+        invalid(index.warmup(&[], 0, 1, search_params));
+        index.warmup(&[], 2, 1, search_params).unwrap();
+    }
+
+    #[test]
+    fn test_knn_search_k() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        assert_eq!(index.num_vectors(), Some(0));
+        assert!(index
+            .knn_search(&[0.0], 3, search_params)
+            .unwrap()
+            .ids
+            .is_empty());
+
+        index
+            .build(5, 1, &[0, 1, 2, 3, 4], &[0.0, 1.0, 2.0, 3.0, 4.0])
+            .unwrap();
+        assert_eq!(index.num_vectors(), Some(5));
+        let err = index.knn_search(&[0.0], 0, search_params).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+        let output = index.knn_search(&[0.0], 10, search_params).unwrap();
+        assert_eq!(output.ids, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        index
+            .build(5, 1, &[0, 1, 2, 3, 4], &[0.0, 1.0, 2.0, 3.0, 4.0])
+            .unwrap();
+
+        // the fingerprint covers the parameters and the number of vectors, not the data.
+        let other = VsagIndex::new("hnsw", con_params).unwrap();
+        assert_ne!(other.fingerprint(), index.fingerprint());
+        other.build(5, 1, &[5, 6, 7, 8, 9], &[0.0; 5]).unwrap();
+        assert_eq!(other.fingerprint(), index.fingerprint());
+
+        // whitespace in the parameters doesn't matter.
+        let compact = json_compact(con_params);
+        let compacted = VsagIndex::new("hnsw", &compact).unwrap();
+        compacted.build(5, 1, &[5, 6, 7, 8, 9], &[0.0; 5]).unwrap();
+        assert_eq!(compacted.fingerprint(), index.fingerprint());
+
+        // the fingerprint of a dump covers the data.
+        let dir = tempdir::TempDir::new("test_fingerprint").unwrap();
+        let path = dir.path().join("index").display().to_string();
+        let other_path = dir.path().join("other").display().to_string();
+        index.dump(&path).unwrap();
+        other.dump(&other_path).unwrap();
+        let fingerprint = VsagIndex::fingerprint_file(&path).unwrap();
+        assert_eq!(VsagIndex::fingerprint_file(&path).unwrap(), fingerprint);
+        assert_ne!(
+            VsagIndex::fingerprint_file(&other_path).unwrap(),
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn test_close() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        index.build(2, 1, &[0, 1], &[0.0, 1.0]).unwrap();
+        index.close().unwrap();
+
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        index.poison_on_fatal(Error::new(ErrorType::InternalError, "corrupted"));
+        let err = index.close().unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::IndexPoisoned));
+    }
+
+    #[test]
+    fn test_poison_on_fatal() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        index.build(2, 1, &[0, 1], &[0.0, 1.0]).unwrap();
+
+        let err = index.poison_on_fatal(Error::new(ErrorType::InvalidArgument, "bad"));
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+        assert!(!index.is_poisoned());
+        index.knn_search(&[0.0], 1, search_params).unwrap();
+
+        index.poison_on_fatal(Error::new(ErrorType::InternalError, "corrupted"));
+        assert!(index.is_poisoned());
+        let err = index.knn_search(&[0.0], 1, search_params).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::IndexPoisoned));
+    }
+
+    #[test]
+    fn test_validate_vectors_option() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let options = IndexOptions {
+            validate_vectors: true,
+            ..Default::default()
+        };
+
+        let index = VsagIndex::with_options("hnsw", con_params, options).unwrap();
+        let err = index
+            .build(2, 2, &[0, 1], &[0.0, 0.0, f32::NAN, 1.0])
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+        assert!(err.message.contains("position 1"));
+
+        index.build(2, 2, &[0, 1], &[0.0, 0.0, 1.0, 1.0]).unwrap();
+        let err = index
+            .knn_search(&[f32::INFINITY, 0.0], 1, search_params)
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+    }
+
+    #[test]
+    fn test_validate_ids_option() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let options = IndexOptions {
+            validate_ids: true,
+            ..Default::default()
+        };
+
+        let index = VsagIndex::with_options("hnsw", con_params, options).unwrap();
+        let err = index.build(2, 1, &[0, -3], &[0.0, 1.0]).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+        assert!(err.message.contains("positions 1 (id -3)"));
+        let ids: Vec<i64> = (-12..0).collect();
+        let err = index.build(12, 1, &ids, &[0.0; 12]).unwrap_err();
+        assert!(err.message.starts_with("12 negative ids"));
+        assert!(err.message.ends_with("9 (id -3), ..."));
+        // positions count from the start of the first chunk.
+        let chunks = vec![(vec![0, 1], vec![0.0, 1.0]), (vec![2, -1], vec![2.0, 3.0])];
+        let err = index.build_chunked(1, chunks).unwrap_err();
+        assert!(err.message.contains("positions 3 (id -1)"));
+        index.build(2, 1, &[0, 1], &[0.0, 1.0]).unwrap();
+
+        // negative IDs are accepted by default.
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        index.build(2, 1, &[0, -3], &[0.0, 1.0]).unwrap();
+    }
+
+    #[test]
+    fn test_build_chunked() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "ip",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let options = IndexOptions {
+            normalize: true,
+            dedup_policy: DedupPolicy::KeepLast,
+            ..Default::default()
+        };
+        let chunks = vec![
+            (vec![1, 2], vec![3.0, 0.0, 0.0, 2.0]),
+            (vec![3, 1], vec![1.0, 1.0, -4.0, 0.0]),
+        ];
+
+        let index = VsagIndex::with_options("hnsw", con_params, options.clone()).unwrap();
+        let report = index.build_chunked(2, chunks.clone()).unwrap();
+        assert!(report.failed_ids.is_empty());
+        assert_eq!(report.num_vectors, 3);
+        let output = index.knn_search(&[-1.0, 0.0], 3, search_params).unwrap();
+        // 1 was replaced by (-1, 0) once normalized.
+        assert_eq!(output.ids, vec![1, 2, 3]);
+        assert!(output.distances[0].abs() < 1e-6);
+
+        let options = IndexOptions {
+            dedup_policy: DedupPolicy::Error,
+            ..options
+        };
+        let index = VsagIndex::with_options("hnsw", con_params, options).unwrap();
+        let err = index.build_chunked(2, chunks).unwrap_err();
+        assert!(err.message.contains("position 3"));
+        let chunks = [([1i64].as_slice(), [1.0f32].as_slice())];
+        assert!(index.build_chunked(2, chunks).is_err());
+    }
 }
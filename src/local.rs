@@ -0,0 +1,104 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A [`VsagIndex`] confined to the thread that created it.
+
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use crate::error::Result;
+use crate::{IndexOptions, VsagIndex};
+
+/// `LocalVsagIndex` is a [`VsagIndex`] that is neither `Send` nor `Sync`, for embedders that
+/// want the type system to guarantee the index is only ever used by one thread.
+///
+/// [`VsagIndex`] doesn't lock either, so both have the same cost; this only removes the
+/// ability to move the index to another thread. All methods of [`VsagIndex`] are available
+/// through `Deref`.
+///
+/// ```compile_fail
+/// use vsag::local::LocalVsagIndex;
+///
+/// fn assert_send<T: Send>() {}
+/// assert_send::<LocalVsagIndex>();
+/// ```
+pub struct LocalVsagIndex {
+    index: VsagIndex,
+    /// Raw pointers are neither `Send` nor `Sync`.
+    _local: PhantomData<*const ()>,
+}
+
+impl LocalVsagIndex {
+    /// Creates a new index, see [`VsagIndex::new`].
+    pub fn new(index_type: &str, params: &str) -> Result<Self> {
+        VsagIndex::new(index_type, params).map(Self::from)
+    }
+
+    /// Creates a new index with `options`, see [`VsagIndex::with_options`].
+    pub fn with_options(index_type: &str, params: &str, options: IndexOptions) -> Result<Self> {
+        VsagIndex::with_options(index_type, params, options).map(Self::from)
+    }
+
+    /// Loads an index, see [`VsagIndex::load_with_options`].
+    pub fn load(path: &str, index_type: &str, params: &str, options: IndexOptions) -> Result<Self> {
+        VsagIndex::load_with_options(path, index_type, params, options).map(Self::from)
+    }
+
+    /// Returns the underlying index, which may be sent to other threads again.
+    pub fn into_inner(self) -> VsagIndex {
+        self.index
+    }
+}
+
+impl From<VsagIndex> for LocalVsagIndex {
+    fn from(index: VsagIndex) -> Self {
+        LocalVsagIndex {
+            index,
+            _local: PhantomData,
+        }
+    }
+}
+
+impl Deref for LocalVsagIndex {
+    type Target = VsagIndex;
+
+    fn deref(&self) -> &VsagIndex {
+        &self.index
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_index() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = LocalVsagIndex::new("hnsw", con_params).unwrap();
+        index.build(3, 1, &[0, 1, 2], &[0.0, 1.0, 2.0]).unwrap();
+        let output = index
+            .knn_search(&[1.1], 1, r#"{"hnsw": {"ef_search": 100}}"#)
+            .unwrap();
+        assert_eq!(output.ids, vec![1]);
+        assert_eq!(index.into_inner().num_vectors(), Some(3));
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mapping between application keys and the `i64` IDs used by vsag.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use crate::codec::{read_bytes, read_u64, write_bytes, write_u64};
+use crate::error::{Error, ErrorType, Result};
+use crate::VsagIndex;
+
+/// File name of the vsag index inside a dumped [`MappedIndex`] directory.
+const INDEX_FILE: &str = "index";
+/// File name of the key mapping inside a dumped [`MappedIndex`] directory.
+const KEYS_FILE: &str = "keys";
+
+/// A key that can be mapped to an internal `i64` ID of a [`MappedIndex`].
+///
+/// Keys are persisted next to the index, so they must be convertible to and from bytes.
+pub trait IndexKey: Clone + Eq + Hash {
+    /// Encodes the key as bytes.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Decodes a key encoded by [`IndexKey::to_bytes`], returns `None` if `bytes` is invalid.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl IndexKey for String {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl IndexKey for Vec<u8> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+macro_rules! impl_index_key_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl IndexKey for $t {
+                fn to_bytes(&self) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+
+                fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                    Some(<$t>::from_le_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_index_key_for_int!(u64, i64, u128);
+
+#[cfg(feature = "uuid")]
+impl IndexKey for uuid::Uuid {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        uuid::Uuid::from_slice(bytes).ok()
+    }
+}
+
+/// `MappedIndex` is a [`VsagIndex`] addressed by arbitrary keys instead of `i64` IDs.
+///
+/// Keys are assigned consecutive internal IDs starting from 0 when the index is built, keys
+/// that fail to be added to the index are forgotten.
+pub struct MappedIndex<K> {
+    index: VsagIndex,
+    key_to_id: HashMap<K, i64>,
+    id_to_key: HashMap<i64, K>,
+}
+
+/// Output of a k-NN search on a [`MappedIndex`].
+pub struct MappedSearchOutput<K> {
+    /// Keys of the k-NNs.
+    pub keys: Vec<K>,
+    /// Distances of the k-NNs.
+    pub distances: Vec<f32>,
+}
+
+impl<K: IndexKey> MappedIndex<K> {
+    /// Creates a new mapped index, see [`VsagIndex::new`] for `index_type` and `params`.
+    pub fn new(index_type: &str, params: &str) -> Result<Self> {
+        Ok(MappedIndex {
+            index: VsagIndex::new(index_type, params)?,
+            key_to_id: HashMap::new(),
+            id_to_key: HashMap::new(),
+        })
+    }
+
+    /// Builds index with all vectors, see [`VsagIndex::build`].
+    ///
+    /// Returns keys of vectors that failed to be added to the index.
+    pub fn build(&mut self, dim: usize, keys: &[K], vectors: &[f32]) -> Result<Vec<K>> {
+        let mut key_to_id = HashMap::with_capacity(keys.len());
+        for (id, key) in keys.iter().enumerate() {
+            if key_to_id.insert(key.clone(), id as i64).is_some() {
+                return Err(Error::new(
+                    ErrorType::InvalidArgument,
+                    format!("duplicate key at position {id}"),
+                ));
+            }
+        }
+
+        let ids: Vec<i64> = (0..keys.len() as i64).collect();
+        let failed_ids = self.index.build(keys.len(), dim, &ids, vectors)?;
+        self.key_to_id = key_to_id;
+        self.id_to_key = keys
+            .iter()
+            .cloned()
+            .zip(0..)
+            .map(|(key, id)| (id, key))
+            .collect();
+
+        Ok(self.forget(&failed_ids))
+    }
+
+    /// Removes the keys of `ids` from the mapping, returning them.
+    fn forget(&mut self, ids: &[i64]) -> Vec<K> {
+        ids.iter()
+            .filter_map(|id| {
+                let key = self.id_to_key.remove(id)?;
+                self.key_to_id.remove(&key);
+                Some(key)
+            })
+            .collect()
+    }
+
+    /// Searches for the `k` nearest neighbors of the `query_vector`, see
+    /// [`VsagIndex::knn_search`].
+    pub fn knn_search(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+    ) -> Result<MappedSearchOutput<K>> {
+        let output = self.index.knn_search_ref(query_vector, k, search_params)?;
+        let keys = output
+            .ids()
+            .iter()
+            .map(|&id| {
+                self.key_of(id)
+                    .cloned()
+                    .ok_or_else(|| Error::new(ErrorType::InternalError, format!("unknown id {id}")))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(MappedSearchOutput {
+            keys,
+            distances: output.distances().to_vec(),
+        })
+    }
+
+    /// Returns the internal ID of `key`.
+    pub fn id_of(&self, key: &K) -> Option<i64> {
+        self.key_to_id.get(key).copied()
+    }
+
+    /// Returns the key of the internal `id`.
+    pub fn key_of(&self, id: i64) -> Option<&K> {
+        self.id_to_key.get(&id)
+    }
+
+    /// Returns the underlying [`VsagIndex`].
+    pub fn index(&self) -> &VsagIndex {
+        &self.index
+    }
+
+    /// Dumps the index and its key mapping into the directory at `dir`.
+    ///
+    /// The directory is created if it doesn't exist.
+    pub fn dump(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        self.index
+            .dump(&dir.join(INDEX_FILE).display().to_string())?;
+
+        // written last and renamed into place, so the keys always match a complete index.
+        let tmp_path = dir.join(format!("{KEYS_FILE}.tmp"));
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        let mut ids: Vec<_> = self.id_to_key.keys().copied().collect();
+        ids.sort_unstable();
+        write_u64(&mut writer, ids.len() as u64)?;
+        for id in ids {
+            write_u64(&mut writer, id as u64)?;
+            write_bytes(&mut writer, &self.id_to_key[&id].to_bytes())?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        std::fs::rename(tmp_path, dir.join(KEYS_FILE))?;
+        Ok(())
+    }
+
+    /// Loads an index dumped by [`MappedIndex::dump`] from the directory at `dir`.
+    ///
+    /// `index_type` and `params` should be the same as the ones used to create the index.
+    pub fn load(dir: impl AsRef<Path>, index_type: &str, params: &str) -> Result<Self> {
+        let dir = dir.as_ref();
+
+        let mut reader = BufReader::new(File::open(dir.join(KEYS_FILE))?);
+        let num_keys = read_u64(&mut reader)?;
+
+        let mut key_to_id = HashMap::new();
+        let mut id_to_key = HashMap::new();
+        for _ in 0..num_keys {
+            let id = read_u64(&mut reader)? as i64;
+            let bytes = read_bytes(&mut reader)?;
+            let key = K::from_bytes(&bytes).ok_or_else(|| {
+                Error::new(ErrorType::InvalidBinary, format!("invalid key at id {id}"))
+            })?;
+            key_to_id.insert(key.clone(), id);
+            id_to_key.insert(id, key);
+        }
+
+        let index = VsagIndex::load(
+            &dir.join(INDEX_FILE).display().to_string(),
+            index_type,
+            params,
+        )?;
+
+        Ok(MappedIndex {
+            index,
+            key_to_id,
+            id_to_key,
+        })
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapped_index() {
+        let index_type = "hnsw";
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 4,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+
+        let mut index = MappedIndex::<String>::new(index_type, con_params).unwrap();
+        let keys: Vec<String> = (0..10).map(|i| format!("doc-{i}")).collect();
+        let vectors: Vec<f32> = (0..10).flat_map(|i| [i as f32; 4]).collect();
+        let failed_keys = index.build(4, &keys, &vectors).unwrap();
+        assert!(failed_keys.is_empty());
+        assert_eq!(index.id_of(&"doc-3".to_string()), Some(3));
+
+        let output = index.knn_search(&[3.1; 4], 2, search_params).unwrap();
+        assert_eq!(output.keys, vec!["doc-3".to_string(), "doc-4".to_string()]);
+
+        let dir = tempdir::TempDir::new("test_mapped_index").unwrap();
+        index.dump(dir.path()).unwrap();
+        let index = MappedIndex::<String>::load(dir.path(), index_type, con_params).unwrap();
+        let output2 = index.knn_search(&[3.1; 4], 2, search_params).unwrap();
+        assert_eq!(output.keys, output2.keys);
+    }
+
+    #[test]
+    fn test_mapped_index_duplicate_keys() {
+        let con_params = r#"{"dtype": "float32", "metric_type": "l2", "dim": 2,
+            "hnsw": {"max_degree": 16, "ef_construction": 100}}"#;
+        let mut index = MappedIndex::<u64>::new("hnsw", con_params).unwrap();
+        let err = index.build(2, &[1, 1], &[0.0; 4]).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+    }
+
+    #[test]
+    fn test_mapped_index_failed_keys() {
+        let con_params = r#"{"dtype": "float32", "metric_type": "l2", "dim": 2,
+            "hnsw": {"max_degree": 16, "ef_construction": 100}}"#;
+        let mut index = MappedIndex::<u64>::new("hnsw", con_params).unwrap();
+        index.build(2, &[10, 11, 12], &[0.0; 6]).unwrap();
+        assert_eq!(index.forget(&[1]), vec![11]);
+        assert_eq!(index.id_of(&11), None);
+        assert_eq!(index.key_of(1), None);
+        assert_eq!(index.id_of(&12), Some(2));
+
+        let dir = tempdir::TempDir::new("test_mapped_index_failed_keys").unwrap();
+        index.dump(dir.path()).unwrap();
+        assert!(!dir.path().join("keys.tmp").exists());
+        let index = MappedIndex::<u64>::load(dir.path(), "hnsw", con_params).unwrap();
+        assert_eq!(index.id_of(&11), None);
+        assert_eq!(index.key_of(2), Some(&12));
+    }
+}
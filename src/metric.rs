@@ -0,0 +1,197 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metric types and conversion between the distances returned by vsag and similarity scores.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, ErrorType};
+use crate::{kernels, KnnSearchOutput};
+
+/// Metric type of an index, the `metric_type` of the index parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Metric {
+    /// Euclidean distance, vsag returns the squared L2 distance.
+    L2,
+    /// Inner product, vsag returns `1 - <a, b>`.
+    Ip,
+    /// Cosine similarity, vsag returns `1 - cos(a, b)`.
+    Cosine,
+}
+
+impl Metric {
+    /// Returns the `metric_type` string of the metric.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::L2 => "l2",
+            Metric::Ip => "ip",
+            Metric::Cosine => "cosine",
+        }
+    }
+
+    /// Extracts the metric from index parameters in JSON format, see
+    /// [`VsagIndex::new`](crate::VsagIndex::new).
+    pub fn from_params(params: &str) -> Option<Metric> {
+        json_string_field(params, "metric_type")?.parse().ok()
+    }
+
+    /// Computes the distance between `a` and `b` the same way vsag does.
+    pub fn distance_between(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::L2 => kernels::l2_sq(a, b),
+            Metric::Ip => 1.0 - kernels::inner_product(a, b),
+            Metric::Cosine => 1.0 - kernels::cosine(a, b),
+        }
+    }
+
+    /// Converts a distance returned by vsag into a similarity score, where bigger is closer:
+    /// - `l2`: `1 / (1 + distance)`, in range (0, 1]
+    /// - `ip`: the inner product
+    /// - `cosine`: the cosine similarity, in range [-1, 1]
+    pub fn similarity(&self, distance: f32) -> f32 {
+        match self {
+            Metric::L2 => 1.0 / (1.0 + distance),
+            Metric::Ip | Metric::Cosine => 1.0 - distance,
+        }
+    }
+
+    /// Converts a similarity score back into the distance returned by vsag, the inverse of
+    /// [`Metric::similarity`].
+    pub fn distance(&self, similarity: f32) -> f32 {
+        match self {
+            Metric::L2 => 1.0 / similarity - 1.0,
+            Metric::Ip | Metric::Cosine => 1.0 - similarity,
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "l2" => Ok(Metric::L2),
+            "ip" => Ok(Metric::Ip),
+            "cosine" => Ok(Metric::Cosine),
+            _ => Err(Error::new(
+                ErrorType::InvalidArgument,
+                format!("unknown metric type {s}"),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl KnnSearchOutput {
+    /// Converts the distances into similarity scores of `metric`, see [`Metric::similarity`].
+    pub fn similarities(&self, metric: Metric) -> Vec<f32> {
+        self.distances
+            .iter()
+            .map(|&distance| metric.similarity(distance))
+            .collect()
+    }
+}
+
+/// Returns the value of the first string field named `key` in `json`.
+///
+/// This is only meant for flat, well-formed parameters, where keys are unique.
+pub(crate) fn json_string_field<'a>(json: &'a str, key: &str) -> Option<&'a str> {
+    let pattern = format!("\"{key}\"");
+    let rest = &json[json.find(&pattern)? + pattern.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    Some(&rest[..rest.find('"')?])
+}
+
+/// Returns the value of the first unsigned integer field named `key` in `json`, see
+/// [`json_string_field`].
+pub(crate) fn json_u64_field(json: &str, key: &str) -> Option<u64> {
+    let pattern = format!("\"{key}\"");
+    let rest = &json[json.find(&pattern)? + pattern.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/// Returns `json` without the whitespace outside of strings.
+pub(crate) fn json_compact(json: &str) -> String {
+    let mut compact = String::with_capacity(json.len());
+    let (mut in_string, mut escaped) = (false, false);
+    for c in json.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c.is_whitespace() {
+            continue;
+        }
+        compact.push(c);
+    }
+    compact
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metric_from_params() {
+        let params = r#"{
+            "dtype": "float32",
+            "metric_type" : "cosine",
+            "dim": 128
+        }"#;
+        assert_eq!(Metric::from_params(params), Some(Metric::Cosine));
+        assert_eq!(Metric::from_params(r#"{"dim": 128}"#), None);
+        assert_eq!(Metric::from_params(r#"{"metric_type": "foo"}"#), None);
+    }
+
+    #[test]
+    fn test_json_compact() {
+        let json = "{\n  \"index type\" : \"a \\\" b\",\t\"dim\": 4 }";
+        assert_eq!(json_compact(json), r#"{"index type":"a \" b","dim":4}"#);
+    }
+
+    #[test]
+    fn test_similarity() {
+        for metric in [Metric::L2, Metric::Ip, Metric::Cosine] {
+            let distance = 0.25;
+            assert_eq!(metric.distance(metric.similarity(distance)), distance);
+        }
+        assert_eq!(Metric::L2.similarity(0.0), 1.0);
+        assert_eq!(Metric::Cosine.similarity(0.0), 1.0);
+        assert_eq!(Metric::Ip.similarity(-2.0), 3.0);
+    }
+
+    #[test]
+    fn test_distance_between() {
+        let (a, b) = ([1.0, 0.0], [3.0, 4.0]);
+        assert_eq!(Metric::L2.distance_between(&a, &b), 20.0);
+        assert_eq!(Metric::Ip.distance_between(&a, &b), -2.0);
+        assert!((Metric::Cosine.distance_between(&a, &b) - 0.4).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An in-crate replacement of the vsag C API with the `mock-ffi` feature, so code using
+//! [`VsagIndex`](crate::VsagIndex) can be unit tested without libvsag, which isn't linked then.
+//!
+//! Mock indexes are backed by [`FlatIndex`], so searches return exact results unless canned
+//! results are set with [`set_search_results`]. All calls are recorded, see [`calls`].
+//!
+//! Recorded calls, canned results and pending errors are kept per thread, so tests running
+//! concurrently don't see each other's. Calls made by threads spawned by the code under test,
+//! e.g. [`ShardedIndex`](crate::sharded::ShardedIndex), are recorded on those threads.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::{LazyLock, Mutex, MutexGuard};
+
+use crate::error::{Error, ErrorType, Result};
+use crate::ffi::CError;
+use crate::flat::FlatIndex;
+
+/// Index types accepted by the mock, as by vsag.
+const INDEX_TYPES: &[&str] = &["hnsw", "diskann"];
+
+/// A call to the vsag C API recorded by the mock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockCall {
+    CreateIndex {
+        index_type: String,
+        params: String,
+    },
+    BuildIndex {
+        num_vectors: usize,
+        dim: usize,
+    },
+    KnnSearch {
+        k: usize,
+        search_params: String,
+    },
+    DumpIndex {
+        path: String,
+    },
+    LoadIndex {
+        path: String,
+        index_type: String,
+        params: String,
+    },
+    FreeIndex,
+}
+
+#[derive(Default)]
+struct MockState {
+    calls: Vec<MockCall>,
+    search_results: Option<(Vec<i64>, Vec<f32>)>,
+    next_error: Option<(ErrorType, String)>,
+}
+
+enum Buffer {
+    I64(Box<[i64]>),
+    F32(Box<[f32]>),
+}
+
+thread_local! {
+    static STATE: RefCell<MockState> = RefCell::default();
+}
+
+/// Result buffers handed out and not freed yet, by address. They may be freed by another
+/// thread than the one they were handed out to.
+static BUFFERS: LazyLock<Mutex<HashMap<usize, Buffer>>> = LazyLock::new(Default::default);
+
+fn with_state<T>(f: impl FnOnce(&mut MockState) -> T) -> T {
+    STATE.with(|state| f(&mut state.borrow_mut()))
+}
+
+fn buffers() -> MutexGuard<'static, HashMap<usize, Buffer>> {
+    BUFFERS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns the calls recorded on this thread since the last [`reset`].
+pub fn calls() -> Vec<MockCall> {
+    with_state(|state| state.calls.clone())
+}
+
+/// Clears recorded calls, canned results and pending errors of this thread.
+pub fn reset() {
+    with_state(|state| *state = MockState::default());
+}
+
+/// Makes all following searches on this thread return `ids` and `distances`, instead of
+/// searching the index, until [`reset`].
+///
+/// # Panics
+///
+/// If `ids` and `distances` have different lengths, since both are handed out as buffers of
+/// the same number of results.
+pub fn set_search_results(ids: Vec<i64>, distances: Vec<f32>) {
+    assert_eq!(
+        ids.len(),
+        distances.len(),
+        "ids and distances have mismatched lengths"
+    );
+    with_state(|state| state.search_results = Some((ids, distances)));
+}
+
+/// Makes the next call on this thread fail with `error_type` and `message`.
+pub fn fail_next(error_type: ErrorType, message: impl Into<String>) {
+    with_state(|state| state.next_error = Some((error_type, message.into())));
+}
+
+/// Returns the number of result buffers of all threads that haven't been freed, to check for
+/// leaks.
+pub fn outstanding_buffers() -> usize {
+    buffers().len()
+}
+
+/// Records `call`, then runs `op` unless an error is pending.
+fn mock_call(call: MockCall, op: impl FnOnce(&MockState) -> Result<()>) -> *const CError {
+    let result = with_state(|state| {
+        state.calls.push(call);
+        match state.next_error.take() {
+            Some((error_type, message)) => Err(Error::new(error_type, message)),
+            None => op(state),
+        }
+    });
+    match result {
+        Ok(()) => std::ptr::null(),
+        Err(err) => to_c_error(err),
+    }
+}
+
+fn to_c_error(err: Error) -> *const CError {
+    let mut message = [0; 256];
+    let len = err.message.len().min(message.len() - 1);
+    message[..len].copy_from_slice(&err.message.as_bytes()[..len]);
+    Box::into_raw(Box::new(CError {
+        type_: err.error_type as c_int,
+        message,
+    }))
+}
+
+unsafe fn to_string(s: *const c_char) -> String {
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+/// Fails like vsag for index types it doesn't support.
+fn check_index_type(index_type: &str) -> Result<()> {
+    if !INDEX_TYPES.contains(&index_type) {
+        return Err(Error::new(
+            ErrorType::UnsupportedIndex,
+            format!("unsupported index type {index_type}"),
+        ));
+    }
+    Ok(())
+}
+
+unsafe fn flat_index<'a>(index_ptr: *const c_void) -> &'a FlatIndex {
+    &*(index_ptr as *const FlatIndex)
+}
+
+pub(crate) unsafe extern "C" fn create_index(
+    in_index_type: *const c_char,
+    in_parameters: *const c_char,
+
+    out_index_ptr: *mut *const c_void,
+) -> *const CError {
+    let index_type = to_string(in_index_type);
+    let params = to_string(in_parameters);
+    let call = MockCall::CreateIndex {
+        index_type: index_type.clone(),
+        params: params.clone(),
+    };
+    mock_call(call, |_| {
+        check_index_type(&index_type)?;
+        let index = FlatIndex::new(&index_type, &params)?;
+        *out_index_ptr = Box::into_raw(Box::new(index)) as *const c_void;
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn build_index(
+    in_index_ptr: *const c_void,
+    in_num_vectors: usize,
+    in_dim: usize,
+    in_ids: *const i64,
+    in_vectors: *const f32,
+
+    out_failed_ids: *mut *const i64,
+    out_num_failed: *mut usize,
+) -> *const CError {
+    let call = MockCall::BuildIndex {
+        num_vectors: in_num_vectors,
+        dim: in_dim,
+    };
+    mock_call(call, |_| {
+        let ids = std::slice::from_raw_parts(in_ids, in_num_vectors);
+        let vectors = std::slice::from_raw_parts(in_vectors, in_num_vectors * in_dim);
+        let failed_ids = flat_index(in_index_ptr).build(in_num_vectors, in_dim, ids, vectors)?;
+        *out_num_failed = failed_ids.len();
+        *out_failed_ids = hand_out(Buffer::I64(failed_ids.into_boxed_slice())) as *const i64;
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn knn_search_index(
+    in_index_ptr: *const c_void,
+    in_dim: usize,
+    in_query_vector: *const f32,
+    in_k: usize,
+    in_search_parameters: *const c_char,
+
+    out_ids: *mut *const i64,
+    out_distances: *mut *const f32,
+    out_num_results: *mut usize,
+) -> *const CError {
+    let search_params = to_string(in_search_parameters);
+    let call = MockCall::KnnSearch {
+        k: in_k,
+        search_params: search_params.clone(),
+    };
+    mock_call(call, |state| {
+        let (ids, distances) = match &state.search_results {
+            Some(results) => results.clone(),
+            None => {
+                let query_vector = std::slice::from_raw_parts(in_query_vector, in_dim);
+                let output =
+                    flat_index(in_index_ptr).knn_search(query_vector, in_k, &search_params)?;
+                (output.ids, output.distances)
+            }
+        };
+        *out_num_results = ids.len();
+        *out_ids = hand_out(Buffer::I64(ids.into_boxed_slice())) as *const i64;
+        *out_distances = hand_out(Buffer::F32(distances.into_boxed_slice())) as *const f32;
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn dump_index(
+    in_index_ptr: *const c_void,
+    in_file_path: *const c_char,
+) -> *const CError {
+    let path = to_string(in_file_path);
+    let call = MockCall::DumpIndex { path: path.clone() };
+    mock_call(call, |_| flat_index(in_index_ptr).dump(&path))
+}
+
+pub(crate) unsafe extern "C" fn load_index(
+    in_file_path: *const c_char,
+    in_index_type: *const c_char,
+    in_parameters: *const c_char,
+
+    out_index_ptr: *mut *const c_void,
+) -> *const CError {
+    let path = to_string(in_file_path);
+    let index_type = to_string(in_index_type);
+    let params = to_string(in_parameters);
+    let call = MockCall::LoadIndex {
+        path: path.clone(),
+        index_type: index_type.clone(),
+        params: params.clone(),
+    };
+    mock_call(call, |_| {
+        check_index_type(&index_type)?;
+        let index = FlatIndex::load(&path, &index_type, &params)?;
+        *out_index_ptr = Box::into_raw(Box::new(index)) as *const c_void;
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn free_index(index_ptr: *const c_void) {
+    with_state(|state| state.calls.push(MockCall::FreeIndex));
+    drop(Box::from_raw(index_ptr as *mut FlatIndex));
+}
+
+pub(crate) unsafe extern "C" fn free_error(error: *const CError) {
+    drop(Box::from_raw(error as *mut CError));
+}
+
+pub(crate) unsafe extern "C" fn free_i64_vector(vector: *const i64) {
+    take_back(vector as usize);
+}
+
+pub(crate) unsafe extern "C" fn free_f32_vector(vector: *const f32) {
+    take_back(vector as usize);
+}
+
+fn hand_out(buffer: Buffer) -> usize {
+    let (addr, is_empty) = match &buffer {
+        Buffer::I64(values) => (values.as_ptr() as usize, values.is_empty()),
+        Buffer::F32(values) => (values.as_ptr() as usize, values.is_empty()),
+    };
+    // empty buffers don't own an allocation, and share the same dangling address.
+    if !is_empty {
+        buffers().insert(addr, buffer);
+    }
+    addr
+}
+
+fn take_back(addr: usize) {
+    buffers().remove(&addr);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VsagIndex;
+
+    #[test]
+    fn test_mock_ffi() {
+        let con_params = r#"{"dtype": "float32", "metric_type": "l2", "dim": 1}"#;
+        let search_params = r#"{"hnsw": {"ef_search": 10}}"#;
+        reset();
+
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        index.build(3, 1, &[1, 2, 3], &[1.0, 2.0, 3.0]).unwrap();
+        let output = index.knn_search(&[2.9], 2, search_params).unwrap();
+        assert_eq!(output.ids, vec![3, 2]);
+
+        set_search_results(vec![42], vec![0.5]);
+        let output = index.knn_search(&[2.9], 2, search_params).unwrap();
+        assert_eq!(output.ids, vec![42]);
+
+        fail_next(ErrorType::NoEnoughMemory, "out of memory");
+        let err = index.knn_search(&[2.9], 2, search_params).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::NoEnoughMemory));
+        assert_eq!(err.message, "out of memory");
+
+        drop(index);
+        assert_eq!(
+            calls(),
+            vec![
+                MockCall::CreateIndex {
+                    index_type: "hnsw".to_string(),
+                    params: con_params.to_string(),
+                },
+                MockCall::BuildIndex {
+                    num_vectors: 3,
+                    dim: 1
+                },
+                MockCall::KnnSearch {
+                    k: 2,
+                    search_params: search_params.to_string(),
+                },
+                MockCall::KnnSearch {
+                    k: 2,
+                    search_params: search_params.to_string(),
+                },
+                MockCall::KnnSearch {
+                    k: 2,
+                    search_params: search_params.to_string(),
+                },
+                MockCall::FreeIndex,
+            ]
+        );
+
+        let err = VsagIndex::new("ivf", con_params).map(drop).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::UnsupportedIndex));
+    }
+
+    #[test]
+    #[should_panic(expected = "ids and distances have mismatched lengths")]
+    fn test_set_search_results_mismatched() {
+        set_search_results(vec![1, 2], vec![0.5]);
+    }
+}
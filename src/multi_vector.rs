@@ -0,0 +1,167 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Indexing several vectors (e.g. chunks or passages) per document.
+
+use std::collections::HashMap;
+
+use crate::error::{Error, ErrorType, Result};
+use crate::{KnnSearchOutput, VsagIndex};
+
+/// How the distances of a document's vectors are combined into its score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    /// The smallest distance among the document's matched vectors, i.e. its best chunk.
+    Max,
+    /// The mean distance of the document's matched vectors.
+    Mean,
+}
+
+/// `MultiVectorIndex` is a [`VsagIndex`] where each vector belongs to a document, and searches
+/// return documents instead of vectors.
+pub struct MultiVectorIndex {
+    index: VsagIndex,
+    /// Document ID of each vector, indexed by the internal vector ID.
+    doc_ids: Vec<i64>,
+    /// Largest number of vectors of a single document.
+    max_vectors_per_doc: usize,
+}
+
+impl MultiVectorIndex {
+    /// Creates a new multi-vector index, see [`VsagIndex::new`] for `index_type` and `params`.
+    pub fn new(index_type: &str, params: &str) -> Result<Self> {
+        Ok(MultiVectorIndex {
+            index: VsagIndex::new(index_type, params)?,
+            doc_ids: Vec::new(),
+            max_vectors_per_doc: 0,
+        })
+    }
+
+    /// Builds index with all vectors, see [`VsagIndex::build`].
+    ///
+    /// `doc_ids` holds the document ID of each vector, a document ID appears once per vector of
+    /// that document.
+    ///
+    /// Returns document IDs of vectors that failed to be added to the index.
+    pub fn build(&mut self, dim: usize, doc_ids: &[i64], vectors: &[f32]) -> Result<Vec<i64>> {
+        let mut vectors_per_doc: HashMap<i64, usize> = HashMap::new();
+        for doc_id in doc_ids {
+            *vectors_per_doc.entry(*doc_id).or_default() += 1;
+        }
+
+        let ids: Vec<i64> = (0..doc_ids.len() as i64).collect();
+        let failed_ids = self.index.build(doc_ids.len(), dim, &ids, vectors)?;
+        self.doc_ids = doc_ids.to_vec();
+        self.max_vectors_per_doc = vectors_per_doc.values().copied().max().unwrap_or(0);
+
+        Ok(failed_ids
+            .into_iter()
+            .map(|id| self.doc_ids[id as usize])
+            .collect())
+    }
+
+    /// Searches for the `k` nearest documents of the `query_vector`.
+    ///
+    /// `k * max_vectors_per_doc` vectors are retrieved so that at least `k` distinct documents
+    /// are found, then their distances are combined per document according to `aggregation`.
+    /// The returned `ids` are document IDs sorted by the aggregated distance.
+    pub fn knn_search(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+        aggregation: Aggregation,
+    ) -> Result<KnnSearchOutput> {
+        let num_candidates = k
+            .saturating_mul(self.max_vectors_per_doc)
+            .min(self.doc_ids.len());
+        let output = self
+            .index
+            .knn_search_ref(query_vector, num_candidates, search_params)?;
+
+        // document id -> (sum or min of distances, number of matched vectors)
+        let mut scores: HashMap<i64, (f32, usize)> = HashMap::new();
+        for (&id, &distance) in output.ids().iter().zip(output.distances()) {
+            let doc_id = usize::try_from(id)
+                .ok()
+                .and_then(|id| self.doc_ids.get(id))
+                .copied()
+                .ok_or_else(|| Error::new(ErrorType::InternalError, format!("unknown id {id}")))?;
+            let (score, count) = scores.entry(doc_id).or_insert((0.0, 0));
+            *score = match (aggregation, *count) {
+                (Aggregation::Max, 0) => distance,
+                (Aggregation::Max, _) => score.min(distance),
+                (Aggregation::Mean, _) => *score + distance,
+            };
+            *count += 1;
+        }
+
+        let mut docs: Vec<(i64, f32)> = scores
+            .into_iter()
+            .map(|(doc_id, (score, count))| match aggregation {
+                Aggregation::Max => (doc_id, score),
+                Aggregation::Mean => (doc_id, score / count as f32),
+            })
+            .collect();
+        docs.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        docs.truncate(k);
+
+        Ok(KnnSearchOutput {
+            ids: docs.iter().map(|(doc_id, _)| *doc_id).collect(),
+            distances: docs.iter().map(|(_, distance)| *distance).collect(),
+        })
+    }
+
+    /// Returns the underlying [`VsagIndex`].
+    pub fn index(&self) -> &VsagIndex {
+        &self.index
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_vector_index() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+
+        let mut index = MultiVectorIndex::new("hnsw", con_params).unwrap();
+        // doc 1 has one close and one far chunk, doc 2 has two medium chunks.
+        let doc_ids = [1, 1, 2, 2, 3];
+        let vectors = [0.0, 0.0, 4.0, 0.0, 1.0, 0.0, 1.0, 0.0, 9.0, 9.0];
+        index.build(2, &doc_ids, &vectors).unwrap();
+
+        let output = index
+            .knn_search(&[0.0, 0.0], 2, search_params, Aggregation::Max)
+            .unwrap();
+        assert_eq!(output.ids, vec![1, 2]);
+        assert_eq!(output.distances, vec![0.0, 1.0]);
+
+        let output = index
+            .knn_search(&[0.0, 0.0], 2, search_params, Aggregation::Mean)
+            .unwrap();
+        assert_eq!(output.ids, vec![2, 1]);
+        assert_eq!(output.distances, vec![1.0, 8.0]);
+    }
+}
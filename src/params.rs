@@ -0,0 +1,358 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed parameters, rendered into the JSON strings expected by vsag.
+
+use std::ffi::CString;
+
+use crate::error::{Error, ErrorType, Result};
+use crate::{KnnSearchOutput, KnnSearchOutputRef, VsagIndex};
+
+/// `ef_search` used when it isn't specified.
+pub const DEFAULT_EF_SEARCH: usize = 100;
+/// `beam_search` of DiskANN used when it isn't specified.
+pub const DEFAULT_BEAM_SEARCH: usize = 4;
+/// `io_limit` of DiskANN used when it isn't specified.
+pub const DEFAULT_IO_LIMIT: usize = 200;
+
+/// Search parameters of an HNSW index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HnswSearchParams {
+    /// Size of the dynamic candidate list, bigger is more accurate but slower.
+    pub ef_search: usize,
+    /// Whether to search the conjugate graph, vsag defaults to `true`.
+    pub use_conjugate_graph_search: Option<bool>,
+}
+
+impl HnswSearchParams {
+    /// Creates search parameters with `ef_search`, leaving the other ones to vsag's defaults.
+    pub fn new(ef_search: usize) -> Self {
+        HnswSearchParams {
+            ef_search,
+            use_conjugate_graph_search: None,
+        }
+    }
+
+    /// Enables or disables searching the conjugate graph.
+    pub fn with_conjugate_graph_search(mut self, enabled: bool) -> Self {
+        self.use_conjugate_graph_search = Some(enabled);
+        self
+    }
+}
+
+impl Default for HnswSearchParams {
+    fn default() -> Self {
+        HnswSearchParams::new(DEFAULT_EF_SEARCH)
+    }
+}
+
+/// Search parameters of a DiskANN index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskAnnSearchParams {
+    /// Size of the dynamic candidate list, bigger is more accurate but slower.
+    pub ef_search: usize,
+    /// Number of sectors read in parallel in each step.
+    pub beam_search: usize,
+    /// Maximum number of IO requests of a search.
+    pub io_limit: usize,
+    /// Whether to rerank candidates with the raw vectors, vsag defaults to `false`.
+    ///
+    /// DiskANN navigates the graph with PQ-compressed vectors, so distances are approximate.
+    /// Reordering reads the raw vector of each candidate from disk to compute exact distances,
+    /// which improves recall at the cost of extra sector reads per query. The raw vectors must
+    /// have been kept on disk when the index was built.
+    pub use_reorder: Option<bool>,
+}
+
+impl Default for DiskAnnSearchParams {
+    fn default() -> Self {
+        DiskAnnSearchParams {
+            ef_search: DEFAULT_EF_SEARCH,
+            beam_search: DEFAULT_BEAM_SEARCH,
+            io_limit: DEFAULT_IO_LIMIT,
+            use_reorder: None,
+        }
+    }
+}
+
+/// Search parameters of an index, see [`VsagIndex::knn_search`](crate::VsagIndex::knn_search).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchParams {
+    Hnsw(HnswSearchParams),
+    DiskAnn(DiskAnnSearchParams),
+}
+
+impl SearchParams {
+    /// Returns the default search parameters of `index_type`, `None` if it's unknown.
+    pub fn default_for(index_type: &str) -> Option<Self> {
+        match index_type {
+            "hnsw" => Some(SearchParams::Hnsw(HnswSearchParams::default())),
+            "diskann" => Some(SearchParams::DiskAnn(DiskAnnSearchParams::default())),
+            _ => None,
+        }
+    }
+
+    /// Returns `ef_search` of the parameters.
+    pub fn ef_search(&self) -> usize {
+        match self {
+            SearchParams::Hnsw(params) => params.ef_search,
+            SearchParams::DiskAnn(params) => params.ef_search,
+        }
+    }
+
+    /// Sets `ef_search` of the parameters.
+    pub fn set_ef_search(&mut self, ef_search: usize) {
+        match self {
+            SearchParams::Hnsw(params) => params.ef_search = ef_search,
+            SearchParams::DiskAnn(params) => params.ef_search = ef_search,
+        }
+    }
+
+    /// Checks the parameters are accepted by vsag.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            SearchParams::Hnsw(params) if params.ef_search == 0 => Err(Error::new(
+                ErrorType::InvalidArgument,
+                "hnsw.ef_search must be positive",
+            )),
+            SearchParams::Hnsw(_) => Ok(()),
+            SearchParams::DiskAnn(params) => params.validate(),
+        }
+    }
+
+    /// Renders the parameters in JSON format.
+    pub fn to_json(&self) -> String {
+        match self {
+            SearchParams::Hnsw(params) => {
+                let mut json = format!(r#"{{"hnsw":{{"ef_search":{}"#, params.ef_search);
+                if let Some(v) = params.use_conjugate_graph_search {
+                    json.push_str(&format!(r#","use_conjugate_graph_search":{v}"#));
+                }
+                json.push_str("}}");
+                json
+            }
+            SearchParams::DiskAnn(params) => {
+                let mut json = format!(
+                    r#"{{"diskann":{{"ef_search":{},"beam_search":{},"io_limit":{}"#,
+                    params.ef_search, params.beam_search, params.io_limit
+                );
+                if let Some(v) = params.use_reorder {
+                    json.push_str(&format!(r#","use_reorder":{v}"#));
+                }
+                json.push_str("}}");
+                json
+            }
+        }
+    }
+}
+
+impl DiskAnnSearchParams {
+    /// Enables or disables reordering candidates with the raw vectors, see
+    /// [`DiskAnnSearchParams::use_reorder`].
+    pub fn with_reorder(mut self, use_reorder: bool) -> Self {
+        self.use_reorder = Some(use_reorder);
+        self
+    }
+
+    /// Checks the parameters are accepted by vsag.
+    ///
+    /// `ef_search`, `beam_search` and `io_limit` must be positive.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::new(ErrorType::InvalidArgument, msg));
+        if self.ef_search == 0 {
+            return invalid("diskann.ef_search must be positive");
+        }
+        if self.beam_search == 0 {
+            return invalid("diskann.beam_search must be positive");
+        }
+        if self.io_limit == 0 {
+            return invalid("diskann.io_limit must be positive");
+        }
+        Ok(())
+    }
+}
+
+impl VsagIndex {
+    /// Same as [`VsagIndex::knn_search`], but takes typed search parameters.
+    ///
+    /// ```ignore
+    /// let output = index.knn_search_with(&query, 10, HnswSearchParams::new(200))?;
+    /// ```
+    ///
+    /// The parameters are validated, then rendered into the JSON expected by vsag.
+    pub fn knn_search_with(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        params: impl Into<SearchParams>,
+    ) -> Result<KnnSearchOutput> {
+        let params = params.into();
+        params.validate()?;
+        self.knn_search(query_vector, k, &params.to_json())
+    }
+}
+
+/// Search parameters validated and converted for vsag once, to be reused across many searches,
+/// see [`VsagIndex::knn_search_handle`].
+///
+/// vsag's C API only takes JSON, so it still parses the parameters on each search; the handle
+/// saves the validation, rendering and allocation done on the Rust side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchParamsHandle {
+    json: String,
+    /// `json` converted for vsag.
+    c_json: CString,
+}
+
+impl SearchParamsHandle {
+    /// Validates and renders typed search parameters.
+    pub fn new(params: impl Into<SearchParams>) -> Result<Self> {
+        let params = params.into();
+        params.validate()?;
+        Self::from_json(&params.to_json())
+    }
+
+    /// Wraps search parameters in JSON format, fails if they contain a 0 byte.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let c_json = CString::new(json).map_err(|_| {
+            Error::new(ErrorType::InvalidArgument, "search params contain a 0 byte")
+        })?;
+        Ok(SearchParamsHandle {
+            json: json.to_string(),
+            c_json,
+        })
+    }
+
+    /// Returns the parameters in JSON format.
+    pub fn as_str(&self) -> &str {
+        &self.json
+    }
+}
+
+impl VsagIndex {
+    /// Same as [`VsagIndex::knn_search_ref`], but with search parameters prepared once.
+    pub fn knn_search_handle(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        params: &SearchParamsHandle,
+    ) -> Result<KnnSearchOutputRef> {
+        self.knn_search_c(query_vector, k, &params.json, &params.c_json)
+    }
+}
+
+impl From<HnswSearchParams> for SearchParams {
+    fn from(params: HnswSearchParams) -> Self {
+        SearchParams::Hnsw(params)
+    }
+}
+
+impl From<DiskAnnSearchParams> for SearchParams {
+    fn from(params: DiskAnnSearchParams) -> Self {
+        SearchParams::DiskAnn(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_params_to_json() {
+        let params = SearchParams::Hnsw(HnswSearchParams {
+            ef_search: 200,
+            use_conjugate_graph_search: Some(false),
+        });
+        assert_eq!(
+            params.to_json(),
+            r#"{"hnsw":{"ef_search":200,"use_conjugate_graph_search":false}}"#
+        );
+
+        let mut params = SearchParams::default_for("diskann").unwrap();
+        params.set_ef_search(50);
+        assert_eq!(
+            params.to_json(),
+            r#"{"diskann":{"ef_search":50,"beam_search":4,"io_limit":200}}"#
+        );
+
+        assert!(SearchParams::default_for("ivf").is_none());
+
+        let params = SearchParams::from(DiskAnnSearchParams::default().with_reorder(true));
+        assert_eq!(
+            params.to_json(),
+            r#"{"diskann":{"ef_search":100,"beam_search":4,"io_limit":200,"use_reorder":true}}"#
+        );
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_knn_search_with() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        index.build(100, 1, &ids, &vectors).unwrap();
+
+        let params = HnswSearchParams::new(50).with_conjugate_graph_search(false);
+        let output = index.knn_search_with(&[10.2], 3, params.clone()).unwrap();
+        let expected = index
+            .knn_search(&[10.2], 3, &SearchParams::from(params).to_json())
+            .unwrap();
+        assert_eq!(output.ids, expected.ids);
+        assert_eq!(output.ids, vec![10, 11, 9]);
+
+        assert!(index
+            .knn_search_with(&[10.2], 3, HnswSearchParams::new(0))
+            .is_err());
+
+        let handle = SearchParamsHandle::new(HnswSearchParams::new(50)).unwrap();
+        for _ in 0..2 {
+            let output = index.knn_search_handle(&[10.2], 3, &handle).unwrap();
+            assert_eq!(output.ids(), &[10, 11, 9]);
+        }
+        assert!(SearchParamsHandle::new(HnswSearchParams::new(0)).is_err());
+        assert!(SearchParamsHandle::from_json("{\0}").is_err());
+    }
+
+    #[test]
+    fn test_validate_search_params() {
+        assert!(SearchParams::default_for("hnsw")
+            .unwrap()
+            .validate()
+            .is_ok());
+        assert!(SearchParams::default_for("diskann")
+            .unwrap()
+            .validate()
+            .is_ok());
+
+        let params = DiskAnnSearchParams {
+            io_limit: 0,
+            ..Default::default()
+        };
+        assert!(params.validate().is_err());
+        let params = DiskAnnSearchParams {
+            ef_search: 0,
+            ..Default::default()
+        };
+        assert!(SearchParams::from(params).validate().is_err());
+    }
+}
@@ -0,0 +1,500 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Partitions (e.g. per tenant or per date) within one logical index.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::error::{Error, ErrorType, Result};
+use crate::topk::merge_topk;
+use crate::{IndexOptions, KnnSearchOutput, VsagIndex};
+
+/// Limits of a partition, e.g. of a tenant, `None` being unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quota {
+    /// Maximum number of vectors of the partition.
+    pub max_vectors: Option<usize>,
+    /// Maximum memory of the partition in bytes, estimated from the size of its raw vectors.
+    pub max_memory: Option<usize>,
+    /// Maximum searches per second of the partition, allowing bursts of up to one second of
+    /// searches, or of one search below 1 QPS.
+    pub max_qps: Option<f64>,
+}
+
+/// Token bucket enforcing [`Quota::max_qps`].
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(max_qps: f64) -> Self {
+        TokenBucket {
+            tokens: Self::capacity(max_qps),
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Returns the maximum number of tokens, at least one so a single search can be served
+    /// below 1 QPS.
+    fn capacity(max_qps: f64) -> f64 {
+        max_qps.max(1.0)
+    }
+
+    /// Refills at `max_qps` for the time elapsed since the last refill.
+    fn refill(&mut self, max_qps: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * max_qps).min(Self::capacity(max_qps));
+        self.refilled = now;
+    }
+}
+
+/// `PartitionedIndex` routes vectors to one [`VsagIndex`] per partition key, and searches
+/// across a selection of partitions.
+///
+/// All partitions are created with the same index type, parameters and options.
+///
+/// Each partition is subject to a [`Quota`], set with [`PartitionedIndex::set_quota`] or else
+/// the default one, and operations exceeding it fail with [`ErrorType::QuotaExceeded`].
+pub struct PartitionedIndex<P> {
+    index_type: String,
+    params: String,
+    options: IndexOptions,
+    partitions: HashMap<P, VsagIndex>,
+    default_quota: Quota,
+    quotas: HashMap<P, Quota>,
+    buckets: Mutex<HashMap<P, TokenBucket>>,
+}
+
+impl<P: Eq + Hash + Clone> PartitionedIndex<P> {
+    /// Creates an index without partitions, see [`VsagIndex::new`] for `index_type` and
+    /// `params`.
+    pub fn new(index_type: &str, params: &str) -> Self {
+        Self::with_options(index_type, params, IndexOptions::default())
+    }
+
+    /// Creates an index without partitions, whose partitions are created with `options`.
+    pub fn with_options(index_type: &str, params: &str, options: IndexOptions) -> Self {
+        PartitionedIndex {
+            index_type: index_type.to_string(),
+            params: params.to_string(),
+            options,
+            partitions: HashMap::new(),
+            default_quota: Quota::default(),
+            quotas: HashMap::new(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the quota of partitions without their own quota, unlimited by default.
+    pub fn set_default_quota(&mut self, quota: Quota) {
+        self.default_quota = quota;
+        self.buckets.get_mut().unwrap().clear();
+    }
+
+    /// Sets the quota of `partition`, whether it exists yet or not.
+    pub fn set_quota(&mut self, partition: P, quota: Quota) {
+        self.buckets.get_mut().unwrap().remove(&partition);
+        self.quotas.insert(partition, quota);
+    }
+
+    /// Returns the quota of `partition`.
+    pub fn quota(&self, partition: &P) -> Quota {
+        self.quotas
+            .get(partition)
+            .copied()
+            .unwrap_or(self.default_quota)
+    }
+
+    fn check_size_quota(&self, partition: &P, num_vectors: usize, dim: usize) -> Result<()> {
+        let quota = self.quota(partition);
+        if let Some(max_vectors) = quota.max_vectors.filter(|&max| num_vectors > max) {
+            return Err(Error::new(
+                ErrorType::QuotaExceeded,
+                format!("{num_vectors} vectors exceed the quota of {max_vectors}"),
+            ));
+        }
+        let memory = num_vectors * dim * std::mem::size_of::<f32>();
+        if let Some(max_memory) = quota.max_memory.filter(|&max| memory > max) {
+            return Err(Error::new(
+                ErrorType::QuotaExceeded,
+                format!("{memory} bytes of vectors exceed the quota of {max_memory} bytes"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Takes a token from the bucket of each of `partitions` if all of them have enough left,
+    /// otherwise takes none.
+    fn take_qps_quota(&self, partitions: &[&P]) -> Result<()> {
+        // partition -> (max QPS, tokens needed)
+        let mut needed: HashMap<&P, (f64, f64)> = HashMap::new();
+        for &partition in partitions {
+            if let Some(max_qps) = self.quota(partition).max_qps {
+                needed.entry(partition).or_insert((max_qps, 0.0)).1 += 1.0;
+            }
+        }
+
+        let mut buckets = self.buckets.lock().unwrap();
+        for (&partition, &(max_qps, tokens)) in &needed {
+            let bucket = buckets
+                .entry(partition.clone())
+                .or_insert_with(|| TokenBucket::new(max_qps));
+            bucket.refill(max_qps);
+            if bucket.tokens < tokens {
+                return Err(Error::new(
+                    ErrorType::QuotaExceeded,
+                    format!("searches exceed the quota of {max_qps} per second"),
+                ));
+            }
+        }
+        for (partition, (_, tokens)) in needed {
+            if let Some(bucket) = buckets.get_mut(partition) {
+                bucket.tokens -= tokens;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds a new partition with all its vectors, see [`VsagIndex::build`].
+    ///
+    /// Fails with [`ErrorType::BuildTwice`] if the partition already exists, or
+    /// [`ErrorType::QuotaExceeded`] if the vectors exceed its quota.
+    pub fn build_partition(
+        &mut self,
+        partition: P,
+        num_vectors: usize,
+        dim: usize,
+        ids: &[i64],
+        vectors: &[f32],
+    ) -> Result<Vec<i64>> {
+        self.check_new_partition(&partition, num_vectors, dim)?;
+        let (index, failed_ids) = self.build_index(num_vectors, dim, ids, vectors)?;
+        self.partitions.insert(partition, index);
+        Ok(failed_ids)
+    }
+
+    fn check_new_partition(&self, partition: &P, num_vectors: usize, dim: usize) -> Result<()> {
+        if self.partitions.contains_key(partition) {
+            return Err(Error::new(
+                ErrorType::BuildTwice,
+                "partition has been built already",
+            ));
+        }
+        self.check_size_quota(partition, num_vectors, dim)
+    }
+
+    fn build_index(
+        &self,
+        num_vectors: usize,
+        dim: usize,
+        ids: &[i64],
+        vectors: &[f32],
+    ) -> Result<(VsagIndex, Vec<i64>)> {
+        let index = VsagIndex::with_options(&self.index_type, &self.params, self.options.clone())?;
+        let failed_ids = index.build(num_vectors, dim, ids, vectors)?;
+        Ok((index, failed_ids))
+    }
+
+    /// Builds partitions from vectors of mixed partitions, `partitions` holds the partition key
+    /// of each vector.
+    ///
+    /// Partitions are built in the order they first appear in, and only added once all of them
+    /// are built: if one already exists, exceeds its quota or fails to build, none is added.
+    ///
+    /// Returns IDs of vectors that failed to be added to the index.
+    pub fn build(
+        &mut self,
+        dim: usize,
+        partitions: &[P],
+        ids: &[i64],
+        vectors: &[f32],
+    ) -> Result<Vec<i64>> {
+        if partitions.len() != ids.len() || vectors.len() != ids.len() * dim {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "partitions, ids and vectors have mismatched lengths",
+            ));
+        }
+
+        // partition -> position in `routed`
+        let mut positions: HashMap<&P, usize> = HashMap::new();
+        let mut routed: Vec<(&P, Vec<i64>, Vec<f32>)> = Vec::new();
+        for (pos, (partition, id)) in partitions.iter().zip(ids).enumerate() {
+            let target = *positions.entry(partition).or_insert_with(|| {
+                routed.push((partition, Vec::new(), Vec::new()));
+                routed.len() - 1
+            });
+            let (_, ids, partition_vectors) = &mut routed[target];
+            ids.push(*id);
+            partition_vectors.extend_from_slice(&vectors[pos * dim..(pos + 1) * dim]);
+        }
+        for (partition, ids, _) in &routed {
+            self.check_new_partition(partition, ids.len(), dim)?;
+        }
+
+        let built = routed
+            .iter()
+            .map(|(_, ids, vectors)| self.build_index(ids.len(), dim, ids, vectors))
+            .collect::<Result<Vec<_>>>()?;
+        let mut failed_ids = Vec::new();
+        for ((partition, _, _), (index, partition_failed_ids)) in routed.iter().zip(built) {
+            self.partitions.insert((*partition).clone(), index);
+            failed_ids.extend(partition_failed_ids);
+        }
+        Ok(failed_ids)
+    }
+
+    /// Searches for the `k` nearest neighbors of the `query_vector` across `partitions`, see
+    /// [`VsagIndex::knn_search`].
+    ///
+    /// Partitions that don't exist are skipped. Fails with [`ErrorType::QuotaExceeded`] if a
+    /// partition has no searches left in its quota.
+    pub fn knn_search(
+        &self,
+        partitions: &[P],
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+    ) -> Result<KnnSearchOutput> {
+        let partitions: Vec<(&P, &VsagIndex)> = partitions
+            .iter()
+            .filter_map(|partition| Some((partition, self.partitions.get(partition)?)))
+            .collect();
+        let keys: Vec<&P> = partitions.iter().map(|(partition, _)| *partition).collect();
+        self.take_qps_quota(&keys)?;
+        let outputs = partitions
+            .iter()
+            .map(|(_, index)| index.knn_search(query_vector, k, search_params))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(merge_topk(&outputs, k))
+    }
+
+    /// Returns the index of `partition`.
+    pub fn partition(&self, partition: &P) -> Option<&VsagIndex> {
+        self.partitions.get(partition)
+    }
+
+    /// Returns the keys of all partitions.
+    pub fn partition_keys(&self) -> impl Iterator<Item = &P> {
+        self.partitions.keys()
+    }
+
+    /// Removes `partition`, returning its index.
+    pub fn remove_partition(&mut self, partition: &P) -> Option<VsagIndex> {
+        self.partitions.remove(partition)
+    }
+
+    /// Dumps the index of `partition` to the file at `path`.
+    pub fn dump_partition(&self, partition: &P, path: &str) -> Result<()> {
+        match self.partitions.get(partition) {
+            Some(index) => index.dump(path),
+            None => Err(Error::new(
+                ErrorType::InvalidArgument,
+                "partition doesn't exist",
+            )),
+        }
+    }
+
+    /// Loads the index of `partition` from the file at `path`, replacing the existing one.
+    pub fn load_partition(&mut self, partition: P, path: &str) -> Result<()> {
+        let index = VsagIndex::load_with_options(
+            path,
+            &self.index_type,
+            &self.params,
+            self.options.clone(),
+        )?;
+        self.partitions.insert(partition, index);
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partitioned_index() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+
+        let mut index = PartitionedIndex::new("hnsw", con_params);
+        index
+            .build(
+                1,
+                &["a", "b", "a", "b"],
+                &[1, 2, 3, 4],
+                &[1.0, 2.0, 3.0, 4.0],
+            )
+            .unwrap();
+
+        let output = index.knn_search(&["a"], &[2.0], 2, search_params).unwrap();
+        assert_eq!(output.ids, vec![1, 3]);
+        let output = index
+            .knn_search(&["a", "b"], &[2.0], 2, search_params)
+            .unwrap();
+        assert_eq!(output.ids[0], 2);
+        let output = index.knn_search(&["c"], &[2.0], 2, search_params).unwrap();
+        assert!(output.ids.is_empty());
+
+        let dir = tempdir::TempDir::new("test_partitioned_index").unwrap();
+        let path = dir.path().join("b").display().to_string();
+        index.dump_partition(&"b", &path).unwrap();
+        index.remove_partition(&"b").unwrap();
+        index.load_partition("b", &path).unwrap();
+        let output = index.knn_search(&["b"], &[2.0], 1, search_params).unwrap();
+        assert_eq!(output.ids, vec![2]);
+    }
+
+    #[test]
+    fn test_quotas() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let vectors = [0.0; 8];
+
+        let mut index = PartitionedIndex::new("hnsw", con_params);
+        index.set_default_quota(Quota {
+            max_vectors: Some(3),
+            ..Default::default()
+        });
+        index.set_quota(
+            "b",
+            Quota {
+                max_memory: Some(16),
+                max_qps: Some(2.0),
+                ..Default::default()
+            },
+        );
+        let err = index
+            .build_partition("a", 4, 2, &[1, 2, 3, 4], &vectors)
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::QuotaExceeded));
+        let err = index
+            .build_partition("b", 3, 2, &[1, 2, 3], &vectors[..6])
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::QuotaExceeded));
+        index
+            .build_partition("b", 2, 2, &[1, 2], &vectors[..4])
+            .unwrap();
+
+        for _ in 0..2 {
+            index
+                .knn_search(&["b"], &[0.0, 0.0], 1, search_params)
+                .unwrap();
+        }
+        let err = index
+            .knn_search(&["b"], &[0.0, 0.0], 1, search_params)
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::QuotaExceeded));
+        // searches of partitions that don't exist are free.
+        index
+            .knn_search(&["a"], &[0.0, 0.0], 1, search_params)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_qps_quota() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let qps = |max_qps| Quota {
+            max_qps: Some(max_qps),
+            ..Default::default()
+        };
+
+        let mut index = PartitionedIndex::new("hnsw", con_params);
+        index.set_quota("slow", qps(0.5));
+        index.set_quota("fast", qps(10.0));
+        for partition in ["slow", "fast"] {
+            index
+                .build_partition(partition, 1, 2, &[1], &[0.0, 0.0])
+                .unwrap();
+        }
+
+        // below 1 QPS, a single search is still allowed.
+        index
+            .knn_search(&["fast", "slow"], &[0.0, 0.0], 1, search_params)
+            .unwrap();
+        let err = index
+            .knn_search(&["fast", "slow"], &[0.0, 0.0], 1, search_params)
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::QuotaExceeded));
+        // the rejected search took no token of "fast".
+        for _ in 0..9 {
+            index
+                .knn_search(&["fast"], &[0.0, 0.0], 1, search_params)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_build_checks_all_partitions_first() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 2,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let mut index = PartitionedIndex::new("hnsw", con_params);
+        index.set_quota(
+            "b",
+            Quota {
+                max_vectors: Some(1),
+                ..Default::default()
+            },
+        );
+
+        let err = index
+            .build(2, &["a", "b", "b"], &[1, 2, 3], &[0.0; 6])
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::QuotaExceeded));
+        assert_eq!(index.partition_keys().count(), 0);
+
+        index.build(2, &["a", "b"], &[1, 2], &[0.0; 4]).unwrap();
+        let err = index.build(2, &["c", "a"], &[3, 4], &[0.0; 4]).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::BuildTwice));
+        assert!(index.partition(&"c").is_none());
+    }
+}
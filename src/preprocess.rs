@@ -0,0 +1,360 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Preprocessing of embeddings, applied the same way at build and query time.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::codec::{read_f32s, read_u64, write_f32s, write_u64};
+use crate::error::{Error, ErrorType, Result};
+use crate::{kernels, KnnSearchOutput, VsagIndex};
+
+/// File name of the pipeline inside a [`PreprocessedIndex`] directory.
+const PIPELINE_FILE: &str = "pipeline";
+/// File name of the vsag index inside a [`PreprocessedIndex`] directory.
+const INDEX_FILE: &str = "index";
+
+/// A step of a [`Pipeline`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// L2-normalizes vectors.
+    Normalize,
+    /// Subtracts `mean` from vectors, see [`Step::center_on`].
+    Center { mean: Vec<f32> },
+    /// Keeps the first `dim` components, e.g. of Matryoshka embeddings.
+    Truncate { dim: usize },
+    /// Multiplies vectors by `matrix`, which holds `output_dim` rows of the input dimension,
+    /// e.g. the principal components of a PCA.
+    Project { matrix: Vec<f32>, output_dim: usize },
+}
+
+impl Step {
+    /// Returns a [`Step::Center`] on the mean of `samples`, which holds vectors of dimension
+    /// `dim` in a single slice.
+    pub fn center_on(samples: &[f32], dim: usize) -> Step {
+        let mut mean = vec![0.0; dim];
+        let mut count = 0;
+        for sample in samples.chunks_exact(dim.max(1)) {
+            mean.iter_mut().zip(sample).for_each(|(m, v)| *m += v);
+            count += 1;
+        }
+        if count > 0 {
+            mean.iter_mut().for_each(|m| *m /= count as f32);
+        }
+        Step::Center { mean }
+    }
+
+    /// Returns the output dimension of the step for an input dimension of `dim`.
+    fn output_dim(&self, dim: usize) -> Result<usize> {
+        let invalid = |msg: String| Err(Error::new(ErrorType::InvalidArgument, msg));
+        match self {
+            Step::Normalize => Ok(dim),
+            Step::Center { mean } if mean.len() != dim => invalid(format!(
+                "mean of dim {} on vectors of dim {dim}",
+                mean.len()
+            )),
+            Step::Center { .. } => Ok(dim),
+            Step::Truncate { dim: truncated } if *truncated == 0 || *truncated > dim => invalid(
+                format!("can't truncate vectors of dim {dim} to {truncated}"),
+            ),
+            Step::Truncate { dim: truncated } => Ok(*truncated),
+            Step::Project { matrix, output_dim } if matrix.len() != output_dim * dim => invalid(
+                format!("projection matrix doesn't have {output_dim} rows of dim {dim}"),
+            ),
+            Step::Project { output_dim, .. } => Ok(*output_dim),
+        }
+    }
+
+    fn apply(&self, vector: &[f32], output: &mut Vec<f32>) {
+        match self {
+            Step::Normalize => {
+                let start = output.len();
+                output.extend_from_slice(vector);
+                kernels::normalize(&mut output[start..]);
+            }
+            Step::Center { mean } => output.extend(vector.iter().zip(mean).map(|(v, m)| v - m)),
+            Step::Truncate { dim } => output.extend_from_slice(&vector[..*dim]),
+            Step::Project { matrix, .. } => output.extend(
+                matrix
+                    .chunks_exact(vector.len())
+                    .map(|row| kernels::inner_product(row, vector)),
+            ),
+        }
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            Step::Normalize => write_u64(writer, 0),
+            Step::Center { mean } => {
+                write_u64(writer, 1)?;
+                write_f32s(writer, mean)
+            }
+            Step::Truncate { dim } => {
+                write_u64(writer, 2)?;
+                write_u64(writer, *dim as u64)
+            }
+            Step::Project { matrix, output_dim } => {
+                write_u64(writer, 3)?;
+                write_u64(writer, *output_dim as u64)?;
+                write_f32s(writer, matrix)
+            }
+        }
+    }
+
+    fn read_from(reader: &mut impl Read) -> Result<Step> {
+        match read_u64(reader)? {
+            0 => Ok(Step::Normalize),
+            1 => Ok(Step::Center {
+                mean: read_f32s(reader)?,
+            }),
+            2 => Ok(Step::Truncate {
+                dim: read_u64(reader)? as usize,
+            }),
+            3 => Ok(Step::Project {
+                output_dim: read_u64(reader)? as usize,
+                matrix: read_f32s(reader)?,
+            }),
+            tag => Err(Error::new(
+                ErrorType::InvalidBinary,
+                format!("unknown preprocessing step {tag}"),
+            )),
+        }
+    }
+}
+
+/// A sequence of [`Step`]s turning vectors of `input_dim` into the vectors indexed.
+///
+/// ```ignore
+/// let pipeline = Pipeline::new(768)
+///     .then(Step::Truncate { dim: 256 })?
+///     .then(Step::Normalize)?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    input_dim: usize,
+    output_dim: usize,
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline without steps over vectors of `input_dim`.
+    pub fn new(input_dim: usize) -> Self {
+        Pipeline {
+            input_dim,
+            output_dim: input_dim,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Appends `step`, failing if it doesn't fit the output dimension of the previous steps.
+    pub fn then(mut self, step: Step) -> Result<Self> {
+        self.output_dim = step.output_dim(self.output_dim)?;
+        self.steps.push(step);
+        Ok(self)
+    }
+
+    /// Returns the dimension of the input vectors.
+    pub fn input_dim(&self) -> usize {
+        self.input_dim
+    }
+
+    /// Returns the dimension of the output vectors, i.e. of the index.
+    pub fn output_dim(&self) -> usize {
+        self.output_dim
+    }
+
+    /// Returns the steps.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Applies all steps to `vectors`, which holds vectors of the input dimension in a single
+    /// slice.
+    pub fn apply(&self, vectors: &[f32]) -> Result<Vec<f32>> {
+        if self.input_dim == 0 || !vectors.chunks_exact(self.input_dim).remainder().is_empty() {
+            return Err(Error::new(
+                ErrorType::DimensionNotEqual,
+                format!("vectors aren't of dim {}", self.input_dim),
+            ));
+        }
+
+        let mut vectors = vectors.to_vec();
+        let mut dim = self.input_dim;
+        for step in &self.steps {
+            let output_dim = step.output_dim(dim)?;
+            let mut output = Vec::with_capacity(vectors.len() / dim * output_dim);
+            for vector in vectors.chunks_exact(dim) {
+                step.apply(vector, &mut output);
+            }
+            vectors = output;
+            dim = output_dim;
+        }
+        Ok(vectors)
+    }
+
+    /// Saves the pipeline into the file at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_u64(&mut writer, self.input_dim as u64)?;
+        write_u64(&mut writer, self.steps.len() as u64)?;
+        for step in &self.steps {
+            step.write_to(&mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads a pipeline saved by [`Pipeline::save`] from the file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut pipeline = Pipeline::new(read_u64(&mut reader)? as usize);
+        for _ in 0..read_u64(&mut reader)? {
+            pipeline = pipeline.then(Step::read_from(&mut reader)?)?;
+        }
+        Ok(pipeline)
+    }
+}
+
+/// `PreprocessedIndex` runs a [`Pipeline`] on vectors before building and searching a
+/// [`VsagIndex`], and persists the pipeline along with the index.
+///
+/// The `dim` of the index parameters must be the output dimension of the pipeline.
+pub struct PreprocessedIndex {
+    pipeline: Pipeline,
+    index: VsagIndex,
+}
+
+impl PreprocessedIndex {
+    /// Creates an index, see [`VsagIndex::new`] for `index_type` and `params`.
+    pub fn new(index_type: &str, params: &str, pipeline: Pipeline) -> Result<Self> {
+        Ok(PreprocessedIndex {
+            pipeline,
+            index: VsagIndex::new(index_type, params)?,
+        })
+    }
+
+    /// Builds the index with all `vectors` of the input dimension of the pipeline, see
+    /// [`VsagIndex::build`].
+    pub fn build(&self, ids: &[i64], vectors: &[f32]) -> Result<Vec<i64>> {
+        let vectors = self.pipeline.apply(vectors)?;
+        self.index
+            .build(ids.len(), self.pipeline.output_dim(), ids, &vectors)
+    }
+
+    /// Searches for the `k` nearest neighbors of the `query_vector` of the input dimension of
+    /// the pipeline, see [`VsagIndex::knn_search`].
+    pub fn knn_search(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+    ) -> Result<KnnSearchOutput> {
+        let query_vector = self.pipeline.apply(query_vector)?;
+        self.index.knn_search(&query_vector, k, search_params)
+    }
+
+    /// Returns the pipeline.
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    /// Returns the underlying [`VsagIndex`].
+    pub fn index(&self) -> &VsagIndex {
+        &self.index
+    }
+
+    /// Dumps the index and its pipeline into the directory at `dir`.
+    ///
+    /// The directory is created if it doesn't exist.
+    pub fn dump(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        self.pipeline.save(dir.join(PIPELINE_FILE))?;
+        self.index.dump(&dir.join(INDEX_FILE).display().to_string())
+    }
+
+    /// Loads an index dumped by [`PreprocessedIndex::dump`] from the directory at `dir`.
+    ///
+    /// `index_type` and `params` should be the same as the ones used to create the index.
+    pub fn load(dir: impl AsRef<Path>, index_type: &str, params: &str) -> Result<Self> {
+        let dir = dir.as_ref();
+        Ok(PreprocessedIndex {
+            pipeline: Pipeline::load(dir.join(PIPELINE_FILE))?,
+            index: VsagIndex::load(
+                &dir.join(INDEX_FILE).display().to_string(),
+                index_type,
+                params,
+            )?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipeline() {
+        let samples = [1.0, 2.0, 3.0, 3.0, 4.0, 5.0];
+        let pipeline = Pipeline::new(3)
+            .then(Step::center_on(&samples, 3))
+            .unwrap()
+            .then(Step::Truncate { dim: 2 })
+            .unwrap()
+            .then(Step::Project {
+                matrix: vec![0.0, 2.0],
+                output_dim: 1,
+            })
+            .unwrap();
+        assert_eq!(pipeline.output_dim(), 1);
+        assert_eq!(pipeline.apply(&samples).unwrap(), vec![-2.0, 2.0]);
+        assert!(pipeline.apply(&samples[1..]).is_err());
+
+        let pipeline = Pipeline::new(2).then(Step::Normalize).unwrap();
+        assert_eq!(pipeline.apply(&[3.0, 4.0]).unwrap(), vec![0.6, 0.8]);
+        assert!(pipeline.then(Step::Truncate { dim: 3 }).is_err());
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_preprocessed_index() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let pipeline = Pipeline::new(2).then(Step::Truncate { dim: 1 }).unwrap();
+        let index = PreprocessedIndex::new("hnsw", con_params, pipeline).unwrap();
+
+        let ids: Vec<i64> = (0..10).collect();
+        let vectors: Vec<f32> = (0..10).flat_map(|i| [i as f32, 100.0]).collect();
+        index.build(&ids, &vectors).unwrap();
+        let output = index.knn_search(&[3.2, -100.0], 1, search_params).unwrap();
+        assert_eq!(output.ids, vec![3]);
+
+        let dir = tempdir::TempDir::new("test_preprocessed_index").unwrap();
+        index.dump(dir.path()).unwrap();
+        let loaded = PreprocessedIndex::load(dir.path(), "hnsw", con_params).unwrap();
+        assert_eq!(loaded.pipeline(), index.pipeline());
+        let output2 = loaded.knn_search(&[3.2, -100.0], 1, search_params).unwrap();
+        assert_eq!(output.ids, output2.ids);
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Progress and statistics of builds, for scheduling ingestion.
+
+use std::time::Duration;
+
+/// Progress of a build, reported to
+/// [`Hooks::on_build_progress`](crate::hooks::Hooks::on_build_progress).
+///
+/// vsag builds an index in a single call without reporting progress, so it's reported as
+/// [`VsagIndex::build_chunked`](crate::VsagIndex::build_chunked) consumes chunks, and once any
+/// build is complete.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildProgress {
+    /// Number of vectors processed so far.
+    pub vectors_done: usize,
+    /// Total number of vectors, `None` if unknown yet.
+    pub total_vectors: Option<usize>,
+    /// Time since the build started.
+    pub elapsed: Duration,
+}
+
+impl BuildProgress {
+    /// Returns the number of vectors processed per second so far.
+    pub fn vectors_per_sec(&self) -> f64 {
+        per_sec(self.vectors_done, self.elapsed)
+    }
+
+    /// Returns the estimated time left at the current throughput, `None` if the total is
+    /// unknown or nothing has been processed yet.
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.total_vectors?;
+        if self.vectors_done == 0 {
+            return None;
+        }
+        let left = total.saturating_sub(self.vectors_done);
+        Some(self.elapsed.mul_f64(left as f64 / self.vectors_done as f64))
+    }
+}
+
+/// Statistics of a completed build.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildReport {
+    /// Number of vectors the index was built with, after deduplication.
+    pub num_vectors: usize,
+    /// Duration of the whole build.
+    pub duration: Duration,
+    /// IDs of vectors that failed to be added to the index.
+    pub failed_ids: Vec<i64>,
+    /// Peak resident memory of the process in bytes, on Linux only.
+    pub peak_memory: Option<usize>,
+}
+
+impl BuildReport {
+    /// Returns the number of vectors built per second.
+    pub fn vectors_per_sec(&self) -> f64 {
+        per_sec(self.num_vectors, self.duration)
+    }
+}
+
+fn per_sec(count: usize, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    count as f64 / elapsed.as_secs_f64()
+}
+
+/// Returns the peak resident memory of the process in bytes, `None` if unavailable.
+pub(crate) fn peak_memory() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: usize = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_progress() {
+        let progress = BuildProgress {
+            vectors_done: 250,
+            total_vectors: Some(1000),
+            elapsed: Duration::from_secs(5),
+        };
+        assert_eq!(progress.vectors_per_sec(), 50.0);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(15)));
+        let progress = BuildProgress {
+            total_vectors: None,
+            ..progress
+        };
+        assert_eq!(progress.eta(), None);
+
+        #[cfg(target_os = "linux")]
+        assert!(peak_memory().unwrap() > 0);
+    }
+}
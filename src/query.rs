@@ -0,0 +1,233 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A fluent builder for k-NN searches.
+
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, ErrorType, Result};
+use crate::params::SearchParams;
+use crate::topk::ScoredPoint;
+use crate::{KnnSearchOutput, VsagIndex};
+
+/// Number of results searched for when `k` isn't specified.
+const DEFAULT_K: usize = 10;
+
+/// A k-NN search built with [`VsagIndex::query`].
+///
+/// ```ignore
+/// let output = index
+///     .query(&query_vector)
+///     .k(10)
+///     .ef_search(200)
+///     .filter(|id| id % 2 == 0)
+///     .timeout(Duration::from_millis(50))
+///     .execute()?;
+/// ```
+pub struct Query<'a> {
+    index: &'a VsagIndex,
+    vector: &'a [f32],
+    k: usize,
+    params: Option<SearchParams>,
+    ef_search: Option<usize>,
+    filter: Option<Box<dyn Fn(i64) -> bool + 'a>>,
+    timeout: Option<Duration>,
+}
+
+impl VsagIndex {
+    /// Starts building a k-NN search of `vector`.
+    pub fn query<'a>(&'a self, vector: &'a [f32]) -> Query<'a> {
+        Query {
+            index: self,
+            vector,
+            k: DEFAULT_K,
+            params: None,
+            ef_search: None,
+            filter: None,
+            timeout: None,
+        }
+    }
+}
+
+impl<'a> Query<'a> {
+    /// Sets the number of results, defaults to 10.
+    pub fn k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// Sets the search parameters, defaults to [`SearchParams::default_for`] the index type.
+    pub fn params(mut self, params: impl Into<SearchParams>) -> Self {
+        self.params = Some(params.into());
+        self
+    }
+
+    /// Overrides `ef_search` of the search parameters.
+    pub fn ef_search(mut self, ef_search: usize) -> Self {
+        self.ef_search = Some(ef_search);
+        self
+    }
+
+    /// Only returns results whose ID satisfies `filter`.
+    ///
+    /// The filter is applied to the results of vsag, and the search is retried with a doubled
+    /// `k` until `k` results pass the filter or the index is exhausted. Very selective filters
+    /// are therefore expensive.
+    pub fn filter(mut self, filter: impl Fn(i64) -> bool + 'a) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Fails the search with [`ErrorType::Timeout`] once `timeout` has elapsed.
+    ///
+    /// A vsag search can't be interrupted, so the timeout is checked before each retry of a
+    /// [`Query::filter`]ed search, and a single search may exceed it.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Runs the search.
+    pub fn execute(self) -> Result<KnnSearchOutput> {
+        let start = Instant::now();
+        let mut params = match self.params {
+            Some(params) => params,
+            None => SearchParams::default_for(self.index.index_type()).ok_or_else(|| {
+                Error::new(
+                    ErrorType::UnsupportedIndex,
+                    format!("no default search params for {}", self.index.index_type()),
+                )
+            })?,
+        };
+        if let Some(ef_search) = self.ef_search {
+            params.set_ef_search(ef_search);
+        }
+        params.validate()?;
+        let mut search_params = params.to_json();
+
+        let Some(filter) = self.filter else {
+            return self.index.knn_search(self.vector, self.k, &search_params);
+        };
+
+        let mut num_candidates = self.k;
+        loop {
+            // only rendered again when the candidates outgrow `ef_search`.
+            if params.ef_search() < num_candidates {
+                params.set_ef_search(num_candidates);
+                search_params = params.to_json();
+            }
+            let output = self
+                .index
+                .knn_search_ref(self.vector, num_candidates, &search_params)?;
+            let filtered: KnnSearchOutput = output
+                .ids()
+                .iter()
+                .zip(output.distances())
+                .filter(|(&id, _)| filter(id))
+                .take(self.k)
+                .map(|(&id, &distance)| ScoredPoint { id, distance })
+                .collect();
+
+            if filtered.ids.len() == self.k || output.len() < num_candidates {
+                return Ok(filtered);
+            }
+            if let Some(timeout) = self.timeout.filter(|&timeout| start.elapsed() >= timeout) {
+                return Err(Error::new(
+                    ErrorType::Timeout,
+                    format!("query timed out after {timeout:?} with {num_candidates} candidates"),
+                ));
+            }
+            num_candidates = num_candidates.saturating_mul(2);
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+    use crate::params::HnswSearchParams;
+    use crate::VsagIndex;
+
+    #[test]
+    fn test_query() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        index.build(100, 1, &ids, &vectors).unwrap();
+
+        let output = index.query(&[10.2]).k(3).ef_search(200).execute().unwrap();
+        assert_eq!(output.ids, vec![10, 11, 9]);
+
+        let output = index
+            .query(&[10.2])
+            .k(3)
+            .params(HnswSearchParams::default())
+            .filter(|id| id % 10 == 0)
+            .execute()
+            .unwrap();
+        assert_eq!(output.ids, vec![10, 20, 0]);
+
+        let output = index
+            .query(&[10.2])
+            .k(3)
+            .filter(|id| id == 99)
+            .execute()
+            .unwrap();
+        assert_eq!(output.ids, vec![99]);
+    }
+
+    #[test]
+    fn test_query_timeout() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        index.build(100, 1, &ids, &vectors).unwrap();
+
+        // a single search isn't interrupted.
+        let output = index
+            .query(&[10.2])
+            .k(3)
+            .timeout(Duration::ZERO)
+            .execute()
+            .unwrap();
+        assert_eq!(output.ids.len(), 3);
+
+        let err = index
+            .query(&[10.2])
+            .k(3)
+            .filter(|id| id == 99)
+            .timeout(Duration::ZERO)
+            .execute()
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::Timeout));
+    }
+}
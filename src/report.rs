@@ -0,0 +1,203 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recall vs latency reports of parameter sweeps, as CSV, JSON or a human-readable table.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::error::{Error, ErrorType, Result};
+use crate::eval::{evaluate, EvalReport, EvalResult};
+use crate::index::VectorIndex;
+use crate::params::SearchParams;
+
+/// Header of [`EvalReport::to_csv`].
+const CSV_HEADER: &str = "search_params,recall,qps,mean_us,p50_us,p90_us,p99_us,max_us";
+
+/// Evaluates `index` with each of `ef_search_values`, other search parameters being the
+/// defaults of the index type, see [`evaluate`].
+pub fn sweep_ef_search(
+    index: &impl VectorIndex,
+    queries: &[f32],
+    dim: usize,
+    ground_truth: &[Vec<i64>],
+    k: usize,
+    ef_search_values: &[usize],
+) -> Result<EvalReport> {
+    let mut params = SearchParams::default_for(index.index_type()).ok_or_else(|| {
+        Error::new(
+            ErrorType::UnsupportedIndex,
+            format!("no default search params for {}", index.index_type()),
+        )
+    })?;
+    let search_params: Vec<String> = ef_search_values
+        .iter()
+        .map(|&ef_search| {
+            params.set_ef_search(ef_search);
+            params.to_json()
+        })
+        .collect();
+    evaluate(index, queries, dim, ground_truth, k, &search_params)
+}
+
+impl EvalReport {
+    /// Renders the report as CSV, one row per search parameters, latencies in microseconds.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{CSV_HEADER}\n");
+        for result in &self.results {
+            let latency = &result.latency;
+            csv.push_str(&format!(
+                "\"{}\",{:.6},{:.2},{},{},{},{},{}\n",
+                result.search_params.replace('"', "\"\""),
+                result.recall,
+                result.qps,
+                latency.mean.as_micros(),
+                latency.p50.as_micros(),
+                latency.p90.as_micros(),
+                latency.p99.as_micros(),
+                latency.max.as_micros(),
+            ));
+        }
+        csv
+    }
+
+    /// Renders the report in JSON format.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize EvalReport")
+    }
+
+    /// Returns the results that aren't beaten on both recall and QPS by another one, i.e. the
+    /// best trade-offs, by increasing recall.
+    pub fn pareto_frontier(&self) -> Vec<&EvalResult> {
+        let mut results: Vec<&EvalResult> = self.results.iter().collect();
+        results.sort_by(|a, b| b.recall.total_cmp(&a.recall).then(b.qps.total_cmp(&a.qps)));
+
+        let mut frontier: Vec<&EvalResult> = Vec::new();
+        for result in results {
+            if frontier.last().is_none_or(|best| result.qps > best.qps) {
+                frontier.push(result);
+            }
+        }
+        frontier.reverse();
+        frontier
+    }
+}
+
+/// A human-readable table of the report.
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "k = {}, {} queries", self.k, self.num_queries)?;
+        writeln!(
+            f,
+            "{:>8} {:>10} {:>10} {:>10} {:>10}  search params",
+            "recall", "qps", "p50", "p99", "max"
+        )?;
+        for result in &self.results {
+            let latency = &result.latency;
+            writeln!(
+                f,
+                "{:>8.4} {:>10.1} {:>10} {:>10} {:>10}  {}",
+                result.recall,
+                result.qps,
+                format_duration(latency.p50),
+                format_duration(latency.p99),
+                format_duration(latency.max),
+                result.search_params
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_millis(1) {
+        format!("{}us", duration.as_micros())
+    } else {
+        format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::LatencyStats;
+    #[cfg(not(feature = "pure-rust"))]
+    use crate::VsagIndex;
+
+    fn result(recall: f64, qps: f64) -> EvalResult {
+        EvalResult {
+            search_params: format!(r#"{{"recall": {recall}}}"#),
+            recall,
+            qps,
+            latency: LatencyStats {
+                p50: Duration::from_micros(120),
+                max: Duration::from_micros(2500),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_report_formats() {
+        let report = EvalReport {
+            k: 10,
+            num_queries: 100,
+            results: vec![
+                result(0.9, 1000.0),
+                result(0.95, 1200.0),
+                result(0.99, 500.0),
+            ],
+        };
+
+        let csv = report.to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert_eq!(
+            lines.next(),
+            Some(r#""{""recall"": 0.9}",0.900000,1000.00,0,120,0,0,2500"#)
+        );
+
+        let table = report.to_string();
+        assert!(table.contains("120us"));
+        assert!(table.contains("2.50ms"));
+
+        let frontier: Vec<f64> = report.pareto_frontier().iter().map(|r| r.recall).collect();
+        assert_eq!(frontier, vec![0.95, 0.99]);
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_sweep_ef_search() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        index.build(100, 1, &ids, &vectors).unwrap();
+
+        let report = sweep_ef_search(&index, &[10.1], 1, &[vec![10, 11]], 2, &[10, 50]).unwrap();
+        assert_eq!(report.results.len(), 2);
+        assert!(report.results[1]
+            .search_params
+            .contains(r#""ef_search":50"#));
+    }
+}
@@ -0,0 +1,465 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An embedded HTTP server over named [`Collection`]s, enabled by the `server` feature.
+//!
+//! Endpoints, all with JSON bodies:
+//! - `PUT /collections/{name}`: creates a collection, see [`CreateCollection`].
+//! - `PUT /collections/{name}/points`: upserts points, see [`UpsertPoints`].
+//! - `POST /collections/{name}/commit`: commits a collection, without body, see
+//!   [`CommitResponse`].
+//! - `POST /collections/{name}/search`: searches a collection, see [`SearchRequest`].
+//!
+//! Upserted points become searchable once the collection is committed, which rebuilds its
+//! index. Commits and searches run in tokio's blocking thread pool, one at a time per
+//! collection, since vsag indexes can't be shared between threads.
+//!
+//! ```ignore
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:6333").await?;
+//! vsag::server::serve(listener, Arc::new(Collections::default())).await?;
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{post, put};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::collection::{Collection, CollectionConfig, CollectionIndex};
+use crate::error::{Error, ErrorType};
+use crate::metric::json_u64_field;
+use crate::params::SearchParams;
+use crate::{IndexOptions, VsagIndex};
+
+/// Named collections served by [`router`], each behind its own lock.
+#[derive(Default)]
+pub struct Collections {
+    inner: Mutex<HashMap<String, Arc<Mutex<Collection>>>>,
+}
+
+impl Collections {
+    /// Adds `collection` under `name`, replacing any existing one.
+    pub fn insert(&self, name: impl Into<String>, collection: Collection) {
+        self.lock()
+            .insert(name.into(), Arc::new(Mutex::new(collection)));
+    }
+
+    /// Removes the collection named `name`.
+    pub fn remove(&self, name: &str) -> Option<Arc<Mutex<Collection>>> {
+        self.lock().remove(name)
+    }
+
+    /// Returns the collection named `name`.
+    pub fn get(&self, name: &str) -> Option<Arc<Mutex<Collection>>> {
+        self.lock().get(name).cloned()
+    }
+
+    /// Returns the names of all collections.
+    pub fn names(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    /// Runs `f` on the collection named `name` in tokio's blocking thread pool, `None` if
+    /// there's no such collection.
+    pub(crate) async fn with_collection<T: Send + 'static>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut Collection) -> T + Send + 'static,
+    ) -> Option<T> {
+        let collection = self.get(name)?;
+        let result = tokio::task::spawn_blocking(move || {
+            // a collection stays consistent even if a request panicked, so ignore poisoning.
+            let mut collection = collection.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut collection)
+        })
+        .await;
+        Some(result.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic())))
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<Mutex<Collection>>>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Body of `PUT /collections/{name}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCollection {
+    /// Type of the index, see [`VsagIndex::new`](crate::VsagIndex::new).
+    pub index_type: String,
+    /// Parameters of the index, see [`VsagIndex::new`](crate::VsagIndex::new).
+    pub params: serde_json::Value,
+    /// Dimension of the vectors.
+    pub dim: usize,
+    /// Whether to normalize vectors, see [`IndexOptions::normalize`].
+    #[serde(default)]
+    pub normalize: bool,
+}
+
+/// A point of [`UpsertPoints`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PointStruct {
+    pub id: i64,
+    pub vector: Vec<f32>,
+    /// Arbitrary JSON returned along with search hits.
+    #[serde(default)]
+    pub payload: serde_json::Value,
+}
+
+/// Body of `PUT /collections/{name}/points`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpsertPoints {
+    pub points: Vec<PointStruct>,
+}
+
+/// Response of `POST /collections/{name}/commit`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitResponse {
+    /// IDs of the points that failed to be indexed, which are deleted, see
+    /// [`Collection::commit`].
+    pub failed_ids: Vec<i64>,
+}
+
+/// Body of `POST /collections/{name}/search`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchRequest {
+    pub vector: Vec<f32>,
+    /// Number of results.
+    pub k: usize,
+    /// Search parameters, defaults to [`SearchParams::default_for`] the index type.
+    #[serde(default)]
+    pub params: Option<serde_json::Value>,
+}
+
+/// A hit of [`SearchResponse`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoredPointStruct {
+    pub id: i64,
+    pub distance: f32,
+    pub payload: serde_json::Value,
+}
+
+/// Response of `POST /collections/{name}/search`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub hits: Vec<ScoredPointStruct>,
+}
+
+/// Creates the router of the endpoints over `collections`.
+pub fn router(collections: Arc<Collections>) -> Router {
+    Router::new()
+        .route("/collections/{name}", put(create_collection))
+        .route("/collections/{name}/points", put(upsert_points))
+        .route("/collections/{name}/commit", post(commit))
+        .route("/collections/{name}/search", post(search))
+        .with_state(collections)
+}
+
+/// Serves the endpoints over `collections` on `listener` until the server fails.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    collections: Arc<Collections>,
+) -> std::io::Result<()> {
+    axum::serve(listener, router(collections)).await
+}
+
+/// An error response, with the message of the error as body.
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        let status = match err.error_type {
+            ErrorType::InvalidArgument
+            | ErrorType::DimensionNotEqual
+            | ErrorType::UnsupportedIndex => StatusCode::BAD_REQUEST,
+            ErrorType::QuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, err.message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+fn not_found(name: &str) -> ApiError {
+    ApiError(
+        StatusCode::NOT_FOUND,
+        format!("collection {name} not found"),
+    )
+}
+
+async fn create_collection(
+    State(collections): State<Arc<Collections>>,
+    Path(name): Path<String>,
+    Json(req): Json<CreateCollection>,
+) -> Result<StatusCode, ApiError> {
+    let config = CollectionConfig {
+        index_type: req.index_type,
+        params: req.params.to_string(),
+        dim: req.dim,
+        options: IndexOptions {
+            normalize: req.normalize,
+            ..Default::default()
+        },
+    };
+    let collection = checked_collection(config)?;
+
+    let mut collections = collections.lock();
+    if collections.contains_key(&name) {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!("collection {name} already exists"),
+        ));
+    }
+    collections.insert(name, Arc::new(Mutex::new(collection)));
+    Ok(StatusCode::CREATED)
+}
+
+/// Creates a collection with `config`, checked right away rather than on its first commit.
+pub(crate) fn checked_collection(config: CollectionConfig) -> Result<Collection, Error> {
+    if config.dim == 0 {
+        return Err(Error::new(
+            ErrorType::InvalidArgument,
+            "dim must be positive",
+        ));
+    }
+    if let Some(dim) = json_u64_field(&config.params, "dim").filter(|&dim| dim != config.dim as u64)
+    {
+        return Err(Error::new(
+            ErrorType::DimensionNotEqual,
+            format!("dim {} doesn't match dim {dim} of params", config.dim),
+        ));
+    }
+    // vsag checks the index type and parameters on creation.
+    VsagIndex::create(&config)?;
+    Ok(Collection::new(config))
+}
+
+/// Upserts all the points or none of them.
+async fn upsert_points(
+    State(collections): State<Arc<Collections>>,
+    Path(name): Path<String>,
+    Json(req): Json<UpsertPoints>,
+) -> Result<StatusCode, ApiError> {
+    let points = req
+        .points
+        .into_iter()
+        .map(|point| {
+            let payload = serde_json::to_vec(&point.payload)
+                .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+            Ok((point.id, point.vector, payload))
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+
+    collections
+        .with_collection(&name, move |collection| {
+            for (id, vector, _) in &points {
+                collection.check_point(*id, vector)?;
+            }
+            for (id, vector, payload) in points {
+                collection.upsert(id, vector, payload)?;
+            }
+            Ok(StatusCode::OK)
+        })
+        .await
+        .ok_or_else(|| not_found(&name))?
+}
+
+async fn commit(
+    State(collections): State<Arc<Collections>>,
+    Path(name): Path<String>,
+) -> Result<Json<CommitResponse>, ApiError> {
+    collections
+        .with_collection(&name, |collection| {
+            let failed_ids = collection.commit()?;
+            Ok(Json(CommitResponse { failed_ids }))
+        })
+        .await
+        .ok_or_else(|| not_found(&name))?
+}
+
+async fn search(
+    State(collections): State<Arc<Collections>>,
+    Path(name): Path<String>,
+    Json(req): Json<SearchRequest>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    collections
+        .with_collection(&name, move |collection| {
+            let params = match req.params {
+                Some(params) => params.to_string(),
+                None => SearchParams::default_for(&collection.config().index_type)
+                    .ok_or_else(|| {
+                        Error::new(
+                            ErrorType::UnsupportedIndex,
+                            "search params are required for this index type",
+                        )
+                    })?
+                    .to_json(),
+            };
+            let hits = collection
+                .search(&req.vector, req.k, &params)?
+                .into_iter()
+                .map(|hit| ScoredPointStruct {
+                    id: hit.id,
+                    distance: hit.distance,
+                    payload: serde_json::from_slice(&hit.payload).unwrap_or_default(),
+                })
+                .collect();
+            Ok(Json(SearchResponse { hits }))
+        })
+        .await
+        .ok_or_else(|| not_found(&name))?
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_server_handlers() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let collections = Arc::new(Collections::default());
+        let name = || Path("docs".to_string());
+
+        rt.block_on(async {
+            let req = CreateCollection {
+                index_type: "hnsw".to_string(),
+                params: json!({
+                    "dtype": "float32",
+                    "metric_type": "l2",
+                    "dim": 2,
+                    "hnsw": {"max_degree": 16, "ef_construction": 100}
+                }),
+                dim: 2,
+                normalize: false,
+            };
+            let status = create_collection(State(collections.clone()), name(), Json(req.clone()))
+                .await
+                .unwrap();
+            assert_eq!(status, StatusCode::CREATED);
+            let err = create_collection(State(collections.clone()), name(), Json(req))
+                .await
+                .unwrap_err();
+            assert_eq!(err.0, StatusCode::CONFLICT);
+
+            let points = UpsertPoints {
+                points: (0..10)
+                    .map(|i| PointStruct {
+                        id: i,
+                        vector: vec![i as f32, 0.0],
+                        payload: json!({ "title": format!("doc {i}") }),
+                    })
+                    .collect(),
+            };
+            upsert_points(State(collections.clone()), name(), Json(points))
+                .await
+                .unwrap();
+            let Json(resp) = commit(State(collections.clone()), name()).await.unwrap();
+            assert!(resp.failed_ids.is_empty());
+
+            let req = SearchRequest {
+                vector: vec![3.2, 0.0],
+                k: 2,
+                params: None,
+            };
+            let Json(resp) = search(State(collections.clone()), name(), Json(req.clone()))
+                .await
+                .unwrap();
+            let ids: Vec<i64> = resp.hits.iter().map(|hit| hit.id).collect();
+            assert_eq!(ids, vec![3, 4]);
+            assert_eq!(resp.hits[0].payload, json!({ "title": "doc 3" }));
+
+            let err = search(
+                State(collections.clone()),
+                Path("missing".to_string()),
+                Json(req),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(err.0, StatusCode::NOT_FOUND);
+        });
+    }
+
+    #[test]
+    fn test_server_validation() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let collections = Arc::new(Collections::default());
+        let name = || Path("docs".to_string());
+        let create = |dim| CreateCollection {
+            index_type: "hnsw".to_string(),
+            params: json!({
+                "dtype": "float32",
+                "metric_type": "l2",
+                "dim": 2,
+                "hnsw": {"max_degree": 16, "ef_construction": 100}
+            }),
+            dim,
+            normalize: false,
+        };
+
+        rt.block_on(async {
+            for dim in [0, 3] {
+                let err = create_collection(State(collections.clone()), name(), Json(create(dim)))
+                    .await
+                    .unwrap_err();
+                assert_eq!(err.0, StatusCode::BAD_REQUEST);
+            }
+            let mut req = create(2);
+            req.index_type = "unknown".to_string();
+            let err = create_collection(State(collections.clone()), name(), Json(req))
+                .await
+                .unwrap_err();
+            assert_eq!(err.0, StatusCode::BAD_REQUEST);
+            assert!(collections.names().is_empty());
+
+            create_collection(State(collections.clone()), name(), Json(create(2)))
+                .await
+                .unwrap();
+            let points = UpsertPoints {
+                points: vec![
+                    PointStruct {
+                        id: 1,
+                        vector: vec![0.0, 0.0],
+                        payload: json!(null),
+                    },
+                    PointStruct {
+                        id: 2,
+                        vector: vec![0.0],
+                        payload: json!(null),
+                    },
+                ],
+            };
+            let err = upsert_points(State(collections.clone()), name(), Json(points))
+                .await
+                .unwrap_err();
+            assert_eq!(err.0, StatusCode::BAD_REQUEST);
+            let collection = collections.get("docs").unwrap();
+            assert!(collection.lock().unwrap().is_empty());
+        });
+    }
+}
@@ -0,0 +1,584 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An index split into shards by ID, built and searched in parallel.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::thread;
+
+use crate::codec::{read_u64, write_u64};
+use crate::error::{Error, ErrorType, Result};
+use crate::index::VectorIndex;
+use crate::topk::{ScoreOrder, TopkMerger};
+use crate::{IndexOptions, KnnSearchOutput, VsagIndex};
+
+/// File name of the sharding and shard sizes inside a dump directory.
+const MANIFEST_FILE: &str = "manifest";
+
+/// Number of shards dumped or loaded at the same time by default.
+pub const DEFAULT_IO_PARALLELISM: usize = 4;
+
+/// How IDs are assigned to the shards of a [`ShardedIndex`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sharding {
+    /// By a hash of the ID, spreading any ID distribution evenly over `num_shards` shards.
+    Hash { num_shards: usize },
+    /// By ranges of IDs delimited by strictly increasing `bounds`: shard `i` holds IDs below `bounds[i]` and
+    /// not below `bounds[i - 1]`, the last shard holds the remaining IDs.
+    Range { bounds: Vec<i64> },
+}
+
+impl Sharding {
+    /// Returns the number of shards.
+    pub fn num_shards(&self) -> usize {
+        match self {
+            Sharding::Hash { num_shards } => *num_shards,
+            Sharding::Range { bounds } => bounds.len() + 1,
+        }
+    }
+
+    /// Fails if there are no shards, or if range bounds aren't strictly increasing, which would
+    /// leave shards that can't hold any ID.
+    pub fn check(&self) -> Result<()> {
+        match self {
+            Sharding::Hash { num_shards: 0 } => Err(Error::new(
+                ErrorType::InvalidArgument,
+                "number of shards must be positive",
+            )),
+            Sharding::Range { bounds } => {
+                match bounds.windows(2).position(|pair| pair[0] >= pair[1]) {
+                    Some(pos) => Err(Error::new(
+                        ErrorType::InvalidArgument,
+                        format!(
+                            "range bounds must be strictly increasing, got {} then {}",
+                            bounds[pos],
+                            bounds[pos + 1]
+                        ),
+                    )),
+                    None => Ok(()),
+                }
+            }
+            Sharding::Hash { .. } => Ok(()),
+        }
+    }
+
+    /// Returns the shard holding `id`.
+    pub fn shard_of(&self, id: i64) -> usize {
+        match self {
+            Sharding::Hash { num_shards } => {
+                // Fibonacci hashing, so sequential IDs don't land on the same shard in bursts.
+                let hash = (id as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                ((hash >> 32) % *num_shards as u64) as usize
+            }
+            Sharding::Range { bounds } => bounds.partition_point(|&bound| bound <= id),
+        }
+    }
+}
+
+/// `ShardedIndex` owns one index per shard, [`VsagIndex`] by default, routes vectors to shards
+/// by ID and fans searches out to all shards in parallel, merging their top `k`.
+///
+/// Searches take `&mut self`, since each shard is handed to its own thread and vsag indexes
+/// can't be shared between threads.
+pub struct ShardedIndex<I = VsagIndex> {
+    sharding: Sharding,
+    shards: Vec<I>,
+    /// Number of vectors in each shard, empty shards are skipped when searching.
+    sizes: Vec<usize>,
+    merger: TopkMerger,
+}
+
+impl ShardedIndex<VsagIndex> {
+    /// Creates the shards, see [`VsagIndex::new`] for `index_type` and `params`.
+    pub fn new(index_type: &str, params: &str, sharding: Sharding) -> Result<Self> {
+        Self::with_options(index_type, params, sharding, IndexOptions::default())
+    }
+
+    /// Creates the shards with `options`, see [`ShardedIndex::new`].
+    pub fn with_options(
+        index_type: &str,
+        params: &str,
+        sharding: Sharding,
+        options: IndexOptions,
+    ) -> Result<Self> {
+        let shards = (0..sharding.num_shards())
+            .map(|_| VsagIndex::with_options(index_type, params, options.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_shards(sharding, shards)
+    }
+
+    /// Loads an index dumped by [`ShardedIndex::dump`] from `dir`, see [`VsagIndex::load`] for
+    /// `index_type` and `params`.
+    ///
+    /// Up to `io_parallelism` shards are loaded at the same time.
+    pub fn load(
+        dir: impl AsRef<Path>,
+        index_type: &str,
+        params: &str,
+        options: IndexOptions,
+        io_parallelism: usize,
+    ) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut reader = BufReader::new(File::open(dir.join(MANIFEST_FILE))?);
+        let sharding = match read_u64(&mut reader)? {
+            0 => Sharding::Hash {
+                num_shards: read_u64(&mut reader)? as usize,
+            },
+            1 => {
+                let num_bounds = read_u64(&mut reader)?;
+                let bounds = (0..num_bounds)
+                    .map(|_| Ok(read_u64(&mut reader)? as i64))
+                    .collect::<Result<Vec<_>>>()?;
+                Sharding::Range { bounds }
+            }
+            kind => {
+                return Err(Error::new(
+                    ErrorType::InvalidArgument,
+                    format!("unknown sharding kind {kind} in {}", dir.display()),
+                ))
+            }
+        };
+        let sizes = (0..sharding.num_shards())
+            .map(|_| Ok(read_u64(&mut reader)? as usize))
+            .collect::<Result<Vec<_>>>()?;
+
+        let shards = for_each_bounded(
+            sizes.iter().enumerate().collect(),
+            io_parallelism,
+            |(shard, &size)| {
+                // empty indexes can't be dumped, empty shards are recreated instead.
+                if size > 0 {
+                    VsagIndex::load_with_options(
+                        &shard_path(dir, shard),
+                        index_type,
+                        params,
+                        options.clone(),
+                    )
+                } else {
+                    VsagIndex::with_options(index_type, params, options.clone())
+                }
+            },
+        )?;
+        Self::from_built_shards(sharding, shards, sizes)
+    }
+}
+
+impl<I: VectorIndex + Send> ShardedIndex<I> {
+    /// Creates a sharded index over `shards`, which must not be built yet, one per shard of
+    /// `sharding`.
+    pub fn from_shards(sharding: Sharding, shards: Vec<I>) -> Result<Self> {
+        sharding.check()?;
+        let num_shards = sharding.num_shards();
+        if shards.len() != num_shards {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                format!("expected {num_shards} shards, got {}", shards.len()),
+            ));
+        }
+
+        Ok(ShardedIndex {
+            sharding,
+            shards,
+            sizes: vec![0; num_shards],
+            merger: TopkMerger::default(),
+        })
+    }
+
+    /// Creates a sharded index over `shards` already built elsewhere, holding `sizes` vectors.
+    pub(crate) fn from_built_shards(
+        sharding: Sharding,
+        shards: Vec<I>,
+        sizes: Vec<usize>,
+    ) -> Result<Self> {
+        let mut index = Self::from_shards(sharding, shards)?;
+        index.sizes = sizes;
+        Ok(index)
+    }
+
+    /// Builds all shards from `vectors`, routing each vector to its shard by ID, see
+    /// [`VectorIndex::build`].
+    ///
+    /// Shards are built in parallel. Returns IDs of vectors that failed to be added.
+    pub fn build(&mut self, dim: usize, ids: &[i64], vectors: &[f32]) -> Result<Vec<i64>> {
+        self.sharding.check()?;
+        if dim == 0 {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "dim must be positive",
+            ));
+        }
+        if vectors.len() != ids.len() * dim {
+            return Err(Error::new(
+                ErrorType::InvalidArgument,
+                "ids and vectors have mismatched lengths",
+            ));
+        }
+
+        let mut routed = vec![(Vec::new(), Vec::new()); self.shards.len()];
+        for (&id, vector) in ids.iter().zip(vectors.chunks_exact(dim)) {
+            let (shard_ids, shard_vectors) = &mut routed[self.sharding.shard_of(id)];
+            shard_ids.push(id);
+            shard_vectors.extend_from_slice(vector);
+        }
+
+        let results = thread::scope(|s| {
+            let handles: Vec<_> = self
+                .shards
+                .iter_mut()
+                .zip(&routed)
+                .enumerate()
+                .filter(|(_, (_, (ids, _)))| !ids.is_empty())
+                .map(|(i, (shard, (ids, vectors)))| {
+                    (
+                        i,
+                        s.spawn(move || shard.build(ids.len(), dim, ids, vectors)),
+                    )
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|(i, handle)| (i, handle.join().expect("shard build thread panicked")))
+                .collect::<Vec<_>>()
+        });
+
+        // shards that were built must stay searchable even if another one failed.
+        let mut failed_ids = Vec::new();
+        let mut first_err = None;
+        for (i, result) in results {
+            match result {
+                Ok(shard_failed_ids) => {
+                    self.sizes[i] += routed[i].0.len().saturating_sub(shard_failed_ids.len());
+                    failed_ids.extend(shard_failed_ids);
+                }
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(failed_ids),
+        }
+    }
+
+    /// Searches for the `k` nearest neighbors of the `query_vector` in all shards in parallel,
+    /// see [`VectorIndex::knn_search`].
+    pub fn knn_search(
+        &mut self,
+        query_vector: &[f32],
+        k: usize,
+        search_params: &str,
+    ) -> Result<KnnSearchOutput> {
+        let outputs = thread::scope(|s| {
+            let handles: Vec<_> = self
+                .shards
+                .iter_mut()
+                .zip(&self.sizes)
+                .filter(|(_, &size)| size > 0)
+                .map(|(shard, _)| s.spawn(move || shard.knn_search(query_vector, k, search_params)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("shard search thread panicked"))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        Ok(self
+            .merger
+            .merge(&outputs, k, ScoreOrder::LowerIsCloser)
+            .iter()
+            .copied()
+            .collect())
+    }
+
+    /// Returns the sharding of the index.
+    pub fn sharding(&self) -> &Sharding {
+        &self.sharding
+    }
+
+    /// Returns the shards.
+    pub fn shards(&self) -> &[I] {
+        &self.shards
+    }
+
+    /// Returns the number of vectors in each shard.
+    pub fn shard_sizes(&self) -> &[usize] {
+        &self.sizes
+    }
+
+    /// Dumps the non-empty shards into `dir`, one file each, along with the sharding.
+    ///
+    /// Each shard is still dumped by a single call into vsag, but up to `io_parallelism` shards
+    /// are dumped at the same time, so more shards make snapshots faster. Takes `&mut self` for
+    /// the same reason as [`ShardedIndex::knn_search`].
+    pub fn dump(&mut self, dir: impl AsRef<Path>, io_parallelism: usize) -> Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let shards = self
+            .shards
+            .iter_mut()
+            .zip(&self.sizes)
+            .enumerate()
+            .filter(|(_, (_, &size))| size > 0)
+            .map(|(shard, (index, _))| (shard, index))
+            .collect();
+        for_each_bounded(shards, io_parallelism, |(shard, index)| {
+            index.dump(&shard_path(dir, shard))
+        })?;
+
+        // written last, so a directory with a manifest holds all the shards.
+        let mut writer = BufWriter::new(File::create(dir.join(MANIFEST_FILE))?);
+        match &self.sharding {
+            Sharding::Hash { num_shards } => {
+                write_u64(&mut writer, 0)?;
+                write_u64(&mut writer, *num_shards as u64)?;
+            }
+            Sharding::Range { bounds } => {
+                write_u64(&mut writer, 1)?;
+                write_u64(&mut writer, bounds.len() as u64)?;
+                for &bound in bounds {
+                    write_u64(&mut writer, bound as u64)?;
+                }
+            }
+        }
+        for &size in &self.sizes {
+            write_u64(&mut writer, size as u64)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn shard_path(dir: &Path, shard: usize) -> String {
+    dir.join(format!("shard-{shard}")).display().to_string()
+}
+
+/// Runs `f` on all `items` with at most `parallelism` threads, returning the results in the
+/// order of `items`, or the first error.
+fn for_each_bounded<T: Send, R: Send>(
+    items: Vec<T>,
+    parallelism: usize,
+    f: impl Fn(T) -> Result<R> + Sync,
+) -> Result<Vec<R>> {
+    let parallelism = parallelism.clamp(1, items.len().max(1));
+    let mut groups: Vec<Vec<(usize, T)>> = (0..parallelism).map(|_| Vec::new()).collect();
+    for (i, item) in items.into_iter().enumerate() {
+        groups[i % parallelism].push((i, item));
+    }
+
+    let f = &f;
+    let results = thread::scope(|s| {
+        let handles: Vec<_> = groups
+            .into_iter()
+            .map(|group| {
+                s.spawn(move || {
+                    group
+                        .into_iter()
+                        .map(|(i, item)| Ok((i, f(item)?)))
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("shard io thread panicked"))
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut results: Vec<(usize, R)> = results.into_iter().flatten().collect();
+    results.sort_by_key(|(i, _)| *i);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flat::FlatIndex;
+    use crate::metric::Metric;
+
+    #[test]
+    fn test_sharding() {
+        let sharding = Sharding::Range {
+            bounds: vec![10, 20],
+        };
+        assert_eq!(sharding.num_shards(), 3);
+        assert_eq!(sharding.shard_of(-5), 0);
+        assert_eq!(sharding.shard_of(10), 1);
+        assert_eq!(sharding.shard_of(25), 2);
+
+        let sharding = Sharding::Hash { num_shards: 4 };
+        let mut counts = [0; 4];
+        for id in 0..1000 {
+            counts[sharding.shard_of(id)] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 200));
+    }
+
+    #[test]
+    fn test_sharding_check() {
+        let flat = |num_shards: usize| -> Vec<FlatIndex> {
+            (0..num_shards)
+                .map(|_| FlatIndex::new("flat", r#"{"metric_type": "l2", "dim": 1}"#).unwrap())
+                .collect()
+        };
+        for bounds in [vec![10, 5], vec![10, 10]] {
+            let sharding = Sharding::Range { bounds };
+            let err = ShardedIndex::from_shards(sharding, flat(3))
+                .map(drop)
+                .unwrap_err();
+            assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+        }
+        let sharding = Sharding::Hash { num_shards: 0 };
+        assert!(ShardedIndex::from_shards(sharding, flat(0)).is_err());
+
+        let sharding = Sharding::Range {
+            bounds: vec![-5, 10],
+        };
+        let mut index = ShardedIndex::from_shards(sharding, flat(3)).unwrap();
+        let err = index.build(0, &[1, 2], &[]).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InvalidArgument));
+        assert_eq!(index.shard_sizes(), [0, 0, 0]);
+    }
+
+    #[cfg(not(feature = "pure-rust"))]
+    #[test]
+    fn test_sharded_index() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let sharding = Sharding::Range {
+            bounds: vec![50, 100, 1000],
+        };
+        let mut index = ShardedIndex::new("hnsw", con_params, sharding).unwrap();
+
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        assert!(index.build(1, &ids, &vectors).unwrap().is_empty());
+        assert_eq!(index.shard_sizes(), &[50, 50, 0, 0]);
+
+        let output = index.knn_search(&[49.8], 4, search_params).unwrap();
+        assert_eq!(output.ids, vec![50, 49, 51, 48]);
+
+        let dir = tempdir::TempDir::new("test_sharded_index").unwrap();
+        index.dump(dir.path(), 2).unwrap();
+        assert!(!dir.path().join("shard-2").exists());
+        let mut index = ShardedIndex::load(
+            dir.path(),
+            "hnsw",
+            con_params,
+            IndexOptions::default(),
+            DEFAULT_IO_PARALLELISM,
+        )
+        .unwrap();
+        assert_eq!(index.shard_sizes(), &[50, 50, 0, 0]);
+        let output = index.knn_search(&[49.8], 4, search_params).unwrap();
+        assert_eq!(output.ids, vec![50, 49, 51, 48]);
+    }
+
+    #[test]
+    fn test_sharded_flat_index() {
+        let con_params = r#"{"dtype": "float32", "metric_type": "l2", "dim": 1}"#;
+        let sharding = Sharding::Hash { num_shards: 3 };
+        let shards = (0..3)
+            .map(|_| FlatIndex::new("flat", con_params).unwrap())
+            .collect();
+        let mut index = ShardedIndex::from_shards(sharding.clone(), shards).unwrap();
+
+        let ids: Vec<i64> = (0..100).collect();
+        let vectors: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        assert!(index.build(1, &ids, &vectors).unwrap().is_empty());
+        let output = index.knn_search(&[49.8], 3, "").unwrap();
+        assert_eq!(output.ids, vec![50, 49, 51]);
+
+        assert!(ShardedIndex::<FlatIndex>::from_shards(sharding, Vec::new()).is_err());
+    }
+
+    /// Fails to add odd IDs, and fails to build at all from IDs above 100.
+    struct PickyIndex(FlatIndex);
+
+    impl VectorIndex for PickyIndex {
+        fn build(
+            &self,
+            _num_vectors: usize,
+            dim: usize,
+            ids: &[i64],
+            vectors: &[f32],
+        ) -> Result<Vec<i64>> {
+            if ids.iter().any(|&id| id > 100) {
+                return Err(Error::new(ErrorType::InternalError, "id above 100"));
+            }
+            let (mut kept_ids, mut kept_vectors, mut failed_ids) = (vec![], vec![], vec![]);
+            for (&id, vector) in ids.iter().zip(vectors.chunks_exact(dim)) {
+                if id % 2 == 1 {
+                    failed_ids.push(id);
+                } else {
+                    kept_ids.push(id);
+                    kept_vectors.extend_from_slice(vector);
+                }
+            }
+            self.0
+                .build(kept_ids.len(), dim, &kept_ids, &kept_vectors)?;
+            Ok(failed_ids)
+        }
+
+        fn knn_search(&self, query: &[f32], k: usize, params: &str) -> Result<KnnSearchOutput> {
+            self.0.knn_search(query, k, params)
+        }
+
+        fn dump(&self, path: &str) -> Result<()> {
+            self.0.dump(path)
+        }
+
+        fn load(path: &str, index_type: &str, params: &str) -> Result<Self> {
+            FlatIndex::load(path, index_type, params).map(PickyIndex)
+        }
+
+        fn index_type(&self) -> &str {
+            self.0.index_type()
+        }
+
+        fn metric(&self) -> Option<Metric> {
+            self.0.metric()
+        }
+    }
+
+    #[test]
+    fn test_sharded_build_failures() {
+        let con_params = r#"{"dtype": "float32", "metric_type": "l2", "dim": 1}"#;
+        let sharding = Sharding::Range {
+            bounds: vec![10, 100],
+        };
+        let shards = (0..3)
+            .map(|_| PickyIndex(FlatIndex::new("flat", con_params).unwrap()))
+            .collect();
+        let mut index = ShardedIndex::from_shards(sharding, shards).unwrap();
+
+        let ids: Vec<i64> = (0..20).chain([200]).collect();
+        let vectors: Vec<f32> = ids.iter().map(|&id| id as f32).collect();
+        let err = index.build(1, &ids, &vectors).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InternalError));
+        assert_eq!(index.shard_sizes(), &[5, 5, 0]);
+        let output = index.knn_search(&[12.2], 2, "").unwrap();
+        assert_eq!(output.ids, vec![12, 14]);
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Numbered snapshots of an index in a directory, with a retention policy.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::index::VectorIndex;
+use crate::{IndexOptions, VsagIndex};
+
+/// Extension of snapshot files, named after their zero-padded generation number.
+const SNAPSHOT_EXTENSION: &str = "index";
+/// Extension of snapshots being written, renamed once complete.
+const TMP_EXTENSION: &str = "tmp";
+
+/// Which snapshots a [`SnapshotManager`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Number of most recent snapshots kept, at least one is always kept.
+    pub keep_last: usize,
+    /// Minimum time between snapshots taken by [`SnapshotManager::snapshot_if_due`].
+    pub interval: Option<Duration>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            keep_last: 3,
+            interval: None,
+        }
+    }
+}
+
+/// `SnapshotManager` dumps an index into a directory as snapshots with increasing generation
+/// numbers, and restores the latest one.
+///
+/// Snapshots are written to a temporary file and renamed once complete, so a crash in the
+/// middle of a dump never leaves a partial snapshot behind.
+pub struct SnapshotManager {
+    dir: PathBuf,
+    index_type: String,
+    params: String,
+    options: IndexOptions,
+    policy: RetentionPolicy,
+    last_snapshot: Option<Instant>,
+}
+
+impl SnapshotManager {
+    /// Creates a manager of the snapshots in `dir`, creating it if it doesn't exist.
+    ///
+    /// `index_type` and `params` are the ones used to create the index, see
+    /// [`VsagIndex::load`].
+    pub fn new(
+        dir: impl AsRef<Path>,
+        index_type: &str,
+        params: &str,
+        policy: RetentionPolicy,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(SnapshotManager {
+            dir: dir.as_ref().to_path_buf(),
+            index_type: index_type.to_string(),
+            params: params.to_string(),
+            options: IndexOptions::default(),
+            policy,
+            last_snapshot: None,
+        })
+    }
+
+    /// Sets the options of restored indexes.
+    pub fn with_options(mut self, options: IndexOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the generation numbers of all snapshots, oldest first.
+    pub fn generations(&self) -> Result<Vec<u64>> {
+        let mut generations = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SNAPSHOT_EXTENSION) {
+                continue;
+            }
+            if let Some(generation) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            {
+                generations.push(generation);
+            }
+        }
+        generations.sort_unstable();
+        Ok(generations)
+    }
+
+    /// Returns the path of the snapshot of `generation`.
+    pub fn path_of(&self, generation: u64) -> PathBuf {
+        self.dir
+            .join(format!("{generation:020}.{SNAPSHOT_EXTENSION}"))
+    }
+
+    /// Dumps `index` as a new snapshot, then prunes old ones.
+    ///
+    /// Returns the generation number of the snapshot.
+    pub fn snapshot(&mut self, index: &impl VectorIndex) -> Result<u64> {
+        let generation = self.generations()?.last().map_or(1, |last| last + 1);
+        let path = self.path_of(generation);
+        let tmp_path = path.with_extension(TMP_EXTENSION);
+        index.dump(&tmp_path.display().to_string())?;
+        std::fs::rename(&tmp_path, &path)?;
+        self.last_snapshot = Some(Instant::now());
+
+        self.prune()?;
+        Ok(generation)
+    }
+
+    /// Same as [`SnapshotManager::snapshot`], but only if `interval` of the retention policy
+    /// has elapsed since the last snapshot taken by this manager.
+    ///
+    /// Meant to be called periodically, e.g. after each batch of writes. Returns the generation
+    /// number of the snapshot, `None` if it's not due yet.
+    pub fn snapshot_if_due(&mut self, index: &impl VectorIndex) -> Result<Option<u64>> {
+        let due = match (self.policy.interval, self.last_snapshot) {
+            (Some(interval), Some(last)) => last.elapsed() >= interval,
+            _ => true,
+        };
+        if due {
+            self.snapshot(index).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Removes the snapshots beyond `keep_last` of the retention policy, and temporary files
+    /// left by interrupted dumps.
+    pub fn prune(&self) -> Result<()> {
+        let generations = self.generations()?;
+        let num_removed = generations
+            .len()
+            .saturating_sub(self.policy.keep_last.max(1));
+        for &generation in &generations[..num_removed] {
+            std::fs::remove_file(self.path_of(generation))?;
+        }
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some(TMP_EXTENSION) {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the snapshot of `generation`.
+    pub fn restore(&self, generation: u64) -> Result<VsagIndex> {
+        VsagIndex::load_with_options(
+            &self.path_of(generation).display().to_string(),
+            &self.index_type,
+            &self.params,
+            self.options.clone(),
+        )
+    }
+
+    /// Loads the most recent snapshot, `None` if there is none.
+    pub fn restore_latest(&self) -> Result<Option<VsagIndex>> {
+        match self.generations()?.last() {
+            Some(&generation) => self.restore(generation).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_snapshot_manager() {
+        let con_params = r#"{
+            "dtype": "float32",
+            "metric_type": "l2",
+            "dim": 1,
+            "hnsw": {
+                "max_degree": 16,
+                "ef_construction": 100
+            }
+        }"#;
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let index = VsagIndex::new("hnsw", con_params).unwrap();
+        let ids: Vec<i64> = (0..10).collect();
+        let vectors: Vec<f32> = (0..10).map(|i| i as f32).collect();
+        index.build(10, 1, &ids, &vectors).unwrap();
+
+        let dir = TempDir::new("snapshot").unwrap();
+        let policy = RetentionPolicy {
+            keep_last: 2,
+            interval: Some(Duration::from_secs(3600)),
+        };
+        let mut manager = SnapshotManager::new(dir.path(), "hnsw", con_params, policy).unwrap();
+        assert!(manager.restore_latest().unwrap().is_none());
+
+        assert_eq!(manager.snapshot_if_due(&index).unwrap(), Some(1));
+        assert_eq!(manager.snapshot_if_due(&index).unwrap(), None);
+        assert_eq!(manager.snapshot(&index).unwrap(), 2);
+        assert_eq!(manager.snapshot(&index).unwrap(), 3);
+        assert_eq!(manager.generations().unwrap(), vec![2, 3]);
+
+        std::fs::write(manager.path_of(4).with_extension(TMP_EXTENSION), b"partial").unwrap();
+        manager.prune().unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let restored = manager.restore_latest().unwrap().unwrap();
+        let output = restored.knn_search(&[3.2], 1, search_params).unwrap();
+        assert_eq!(output.ids, vec![3]);
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An index split into a hot in-memory tier and a cold on-disk tier, with cold vectors migrated
+//! in the background.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::collection::{Collection, CollectionConfig};
+use crate::error::{Error, ErrorType, Result};
+use crate::topk::merge_topk;
+use crate::{IndexOptions, KnnSearchOutput, VsagIndex};
+
+/// Configuration of an [`AutoTierIndex`].
+#[derive(Debug, Clone)]
+pub struct TierConfig {
+    /// Dimension of the vectors.
+    pub dim: usize,
+    /// Parameters of the hot HNSW index in JSON format, see [`VsagIndex::new`].
+    pub hot_params: String,
+    /// Parameters of the cold DiskANN index in JSON format, see [`VsagIndex::new`].
+    pub cold_params: String,
+    /// Vectors not inserted nor returned by a search for this long are migrated.
+    pub cold_after: Duration,
+    /// Maximum number of vectors of the hot tier, the least recently used ones beyond it are
+    /// migrated even if they aren't cold yet.
+    pub max_hot: usize,
+    /// Counts how many times each vector is returned by a search, see
+    /// [`AutoTierIndex::access_counts`]. Off by default as it costs a map update per hit.
+    pub track_access: bool,
+    /// Options of both indexes.
+    pub options: IndexOptions,
+}
+
+/// A migration of vectors to the cold tier, whose index is being rebuilt in the background.
+struct Migration {
+    ids: Vec<i64>,
+    started: Instant,
+    handle: JoinHandle<Result<VsagIndex>>,
+}
+
+/// `AutoTierIndex` keeps recently used vectors in an HNSW index and migrates the others into a
+/// DiskANN index, searching both tiers and merging their results.
+///
+/// vsag indexes are built once, so a migration rebuilds the DiskANN index over all cold vectors
+/// on a background thread, and the hot tier is a [`Collection`] whose changes become searchable
+/// after [`AutoTierIndex::commit`]. Migrated vectors stay in the hot tier until the new cold
+/// index is swapped in, so they remain searchable meanwhile, and vectors used again meanwhile
+/// stay hot. The hot tier takes precedence over stale copies in the cold index. The cold vectors
+/// are kept in memory to rebuild the cold index.
+pub struct AutoTierIndex {
+    config: TierConfig,
+    hot: Collection,
+    /// Last time each hot vector was inserted or returned by a search.
+    last_used: HashMap<i64, Instant>,
+    cold_vectors: BTreeMap<i64, Vec<f32>>,
+    cold: Option<VsagIndex>,
+    migration: Option<Migration>,
+    /// Number of search hits of each vector, if tracked.
+    access_counts: HashMap<i64, u64>,
+    /// IDs never migrated to the cold tier.
+    pinned: HashSet<i64>,
+}
+
+impl AutoTierIndex {
+    /// Creates an empty tiered index.
+    pub fn new(config: TierConfig) -> Self {
+        let hot = Collection::new(CollectionConfig {
+            index_type: "hnsw".to_string(),
+            params: config.hot_params.clone(),
+            dim: config.dim,
+            options: config.options.clone(),
+        });
+        AutoTierIndex {
+            config,
+            hot,
+            last_used: HashMap::new(),
+            cold_vectors: BTreeMap::new(),
+            cold: None,
+            migration: None,
+            access_counts: HashMap::new(),
+            pinned: HashSet::new(),
+        }
+    }
+
+    /// Inserts or updates a vector into the hot tier, removing it from the cold tier.
+    pub fn upsert(&mut self, id: i64, vector: Vec<f32>) -> Result<()> {
+        self.hot.upsert(id, vector, Vec::new())?;
+        self.last_used.insert(id, Instant::now());
+        self.cold_vectors.remove(&id);
+        Ok(())
+    }
+
+    /// Makes the vectors inserted into the hot tier searchable, see [`Collection::commit`],
+    /// returns the IDs of the vectors that failed to be indexed, which are deleted.
+    pub fn commit(&mut self) -> Result<Vec<i64>> {
+        self.poll_migration(false)?;
+        self.commit_hot()
+    }
+
+    /// Pins `id` to the hot tier, so it's never migrated, moving it back from the cold tier if
+    /// needed. It may be pinned before being inserted.
+    ///
+    /// A vector moved back becomes searchable in the hot tier after [`AutoTierIndex::commit`].
+    pub fn pin(&mut self, id: i64) -> Result<()> {
+        self.pinned.insert(id);
+        if let Some(vector) = self.cold_vectors.remove(&id) {
+            self.hot.upsert(id, vector, Vec::new())?;
+            self.last_used.insert(id, Instant::now());
+        }
+        Ok(())
+    }
+
+    /// Unpins `id`, see [`AutoTierIndex::pin`], returns `false` if it wasn't pinned.
+    pub fn unpin(&mut self, id: i64) -> bool {
+        self.pinned.remove(&id)
+    }
+
+    /// Returns `true` if `id` is pinned to the hot tier.
+    pub fn is_pinned(&self, id: i64) -> bool {
+        self.pinned.contains(&id)
+    }
+
+    /// Returns the number of times each vector was returned by a search since the index was
+    /// created or [`AutoTierIndex::reset_access_counts`], empty unless
+    /// [`TierConfig::track_access`] is set. Vectors never returned are missing.
+    ///
+    /// Callers may use it to pin the most accessed vectors, or size `max_hot`.
+    pub fn access_counts(&self) -> &HashMap<i64, u64> {
+        &self.access_counts
+    }
+
+    /// Returns the `n` most accessed vectors with their counts, most accessed first, see
+    /// [`AutoTierIndex::access_counts`].
+    pub fn hottest(&self, n: usize) -> Vec<(i64, u64)> {
+        let mut counts: Vec<(i64, u64)> = self
+            .access_counts
+            .iter()
+            .map(|(&id, &count)| (id, count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Clears the access counts, e.g. to only consider recent traffic.
+    pub fn reset_access_counts(&mut self) {
+        self.access_counts.clear();
+    }
+
+    /// Starts migrating cold vectors, and the least recently used ones beyond `max_hot`, to the
+    /// cold tier, unless a migration is in progress. Pinned vectors are never migrated, nor
+    /// counted against `max_hot`.
+    ///
+    /// Returns the number of vectors being migrated.
+    pub fn migrate(&mut self) -> Result<usize> {
+        self.poll_migration(false)?;
+        if self.migration.is_some() {
+            return Ok(0);
+        }
+
+        let mut by_age: Vec<(i64, Instant)> = self
+            .last_used
+            .iter()
+            .filter(|(id, _)| !self.pinned.contains(id))
+            .map(|(&id, &last_used)| (id, last_used))
+            .collect();
+        by_age.sort_by_key(|&(id, last_used)| (last_used, id));
+        let num_over = by_age.len().saturating_sub(self.config.max_hot);
+        let ids: Vec<i64> = by_age
+            .iter()
+            .enumerate()
+            .filter(|&(i, &(_, last_used))| {
+                i < num_over || last_used.elapsed() >= self.config.cold_after
+            })
+            .map(|(_, &(id, _))| id)
+            .collect();
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut cold_vectors = self.cold_vectors.clone();
+        for &id in &ids {
+            if let Some(point) = self.hot.get(id) {
+                cold_vectors.insert(id, point.vector.clone());
+            }
+        }
+        let dim = self.config.dim;
+        let params = self.config.cold_params.clone();
+        let options = self.config.options.clone();
+        let handle = thread::spawn(move || {
+            let index = VsagIndex::with_options("diskann", &params, options)?;
+            let ids: Vec<i64> = cold_vectors.keys().copied().collect();
+            let vectors: Vec<f32> = cold_vectors.values().flatten().copied().collect();
+            index.build(ids.len(), dim, &ids, &vectors)?;
+            Ok(index)
+        });
+
+        let num_migrated = ids.len();
+        self.migration = Some(Migration {
+            ids,
+            started: Instant::now(),
+            handle,
+        });
+        Ok(num_migrated)
+    }
+
+    /// Waits for the migration in progress, if any, to complete.
+    pub fn wait_migration(&mut self) -> Result<()> {
+        self.poll_migration(true)
+    }
+
+    /// Returns `true` if a migration is in progress.
+    pub fn is_migrating(&self) -> bool {
+        self.migration.is_some()
+    }
+
+    /// Searches for the `k` nearest neighbors of the `query_vector` in both tiers, with
+    /// `hot_search_params` and `cold_search_params` respectively, see [`VsagIndex::knn_search`].
+    ///
+    /// Vectors returned from the hot tier are marked as recently used. A migration that
+    /// completed is swapped in after searching, so vectors returned while it was in progress
+    /// stay hot.
+    pub fn knn_search(
+        &mut self,
+        query_vector: &[f32],
+        k: usize,
+        hot_search_params: &str,
+        cold_search_params: &str,
+    ) -> Result<KnnSearchOutput> {
+        let hits = self.hot.search(query_vector, k, hot_search_params)?;
+        let now = Instant::now();
+        for hit in &hits {
+            if let Some(last_used) = self.last_used.get_mut(&hit.id) {
+                *last_used = now;
+            }
+        }
+        let mut outputs = vec![KnnSearchOutput {
+            ids: hits.iter().map(|hit| hit.id).collect(),
+            distances: hits.iter().map(|hit| hit.distance).collect(),
+        }];
+        if let Some(cold) = &self.cold {
+            let output = cold.knn_search(query_vector, k, cold_search_params)?;
+            // the hot tier takes precedence over stale copies.
+            let (ids, distances) = output
+                .ids
+                .iter()
+                .zip(&output.distances)
+                .filter(|(&id, _)| self.hot.get(id).is_none())
+                .unzip();
+            outputs.push(KnnSearchOutput { ids, distances });
+        }
+        let output = merge_topk(&outputs, k);
+        if self.config.track_access {
+            for &id in &output.ids {
+                *self.access_counts.entry(id).or_default() += 1;
+            }
+        }
+        self.poll_migration(false)?;
+        Ok(output)
+    }
+
+    /// Returns the number of vectors in the hot and cold tiers, vectors being migrated counting
+    /// as hot.
+    pub fn tier_sizes(&self) -> (usize, usize) {
+        (self.hot.len(), self.cold_vectors.len())
+    }
+
+    /// Swaps in the cold index of the migration in progress if it's built, or waits for it if
+    /// `wait` is `true`.
+    fn poll_migration(&mut self, wait: bool) -> Result<()> {
+        match &self.migration {
+            Some(migration) if wait || migration.handle.is_finished() => {}
+            _ => return Ok(()),
+        }
+        let Migration {
+            ids,
+            started,
+            handle,
+        } = self.migration.take().expect("migration in progress");
+        let cold = handle
+            .join()
+            .map_err(|_| Error::new(ErrorType::InternalError, "migration thread panicked"))??;
+
+        for id in ids {
+            // used or pinned since the migration started, it stays hot.
+            if self.pinned.contains(&id)
+                || self
+                    .last_used
+                    .get(&id)
+                    .is_none_or(|&last_used| last_used > started)
+            {
+                continue;
+            }
+            if let Some(point) = self.hot.delete(id) {
+                self.cold_vectors.insert(id, point.vector);
+            }
+            self.last_used.remove(&id);
+        }
+        self.cold = Some(cold);
+        self.commit_hot().map(drop)
+    }
+
+    fn commit_hot(&mut self) -> Result<Vec<i64>> {
+        let failed_ids = self.hot.commit()?;
+        for id in &failed_ids {
+            self.last_used.remove(id);
+        }
+        Ok(failed_ids)
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_tier_index() {
+        let config = TierConfig {
+            dim: 1,
+            hot_params: r#"{
+                "dtype": "float32",
+                "metric_type": "l2",
+                "dim": 1,
+                "hnsw": {"max_degree": 16, "ef_construction": 100}
+            }"#
+            .to_string(),
+            cold_params: r#"{
+                "dtype": "float32",
+                "metric_type": "l2",
+                "dim": 1,
+                "diskann": {
+                    "max_degree": 16,
+                    "ef_construction": 100,
+                    "pq_dims": 1,
+                    "pq_sample_rate": 0.5
+                }
+            }"#
+            .to_string(),
+            cold_after: Duration::from_secs(3600),
+            max_hot: 5,
+            track_access: true,
+            options: IndexOptions::default(),
+        };
+        let hot_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let cold_params = r#"{"diskann": {"ef_search": 100, "beam_search": 4, "io_limit": 200}}"#;
+
+        let mut index = AutoTierIndex::new(config);
+        for id in 0..10 {
+            index.upsert(id, vec![id as f32]).unwrap();
+        }
+        index.commit().unwrap();
+
+        assert_eq!(index.migrate().unwrap(), 5);
+        assert!(index.is_migrating());
+        let output = index
+            .knn_search(&[1.2], 2, hot_params, cold_params)
+            .unwrap();
+        assert_eq!(output.ids, vec![1, 2]);
+
+        index.wait_migration().unwrap();
+        assert_eq!(index.tier_sizes(), (7, 3));
+        // 1 and 2 were returned by the search above, so they stay hot.
+        let output = index
+            .knn_search(&[1.2], 3, hot_params, cold_params)
+            .unwrap();
+        assert_eq!(output.ids, vec![1, 2, 0]);
+
+        index.upsert(0, vec![100.0]).unwrap();
+        index.commit().unwrap();
+        let output = index
+            .knn_search(&[0.0], 1, hot_params, cold_params)
+            .unwrap();
+        assert_eq!(output.ids, vec![1]);
+
+        assert_eq!(index.hottest(2), vec![(1, 3), (2, 2)]);
+        index.reset_access_counts();
+        assert!(index.access_counts().is_empty());
+
+        // 3 and 4 went cold in the first migration, 0 too but it was upserted since.
+        index.pin(3).unwrap();
+        index.commit().unwrap();
+        assert_eq!(index.tier_sizes(), (9, 1));
+        assert!(index.is_pinned(3));
+        let output = index
+            .knn_search(&[3.0], 1, hot_params, cold_params)
+            .unwrap();
+        assert_eq!(output.ids, vec![3]);
+    }
+}
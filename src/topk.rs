@@ -0,0 +1,261 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Merging top-k results of several searches, e.g. from shards or partitions.
+//!
+//! vsag reports a distance for every metric, where smaller is always closer: squared L2 for
+//! `l2`, `1 - <a, b>` for `ip` and `1 - cos(a, b)` for `cosine`. Outputs of
+//! [`VsagIndex::knn_search`](crate::VsagIndex::knn_search) are therefore merged with
+//! [`ScoreOrder::LowerIsCloser`], while scores converted to similarities (where bigger is closer)
+//! need [`ScoreOrder::HigherIsCloser`].
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+use crate::kernels::IdMap;
+use crate::KnnSearchOutput;
+
+/// Whether a lower or a higher score means closer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreOrder {
+    /// Scores are distances, as returned by vsag.
+    LowerIsCloser,
+    /// Scores are similarities.
+    HigherIsCloser,
+}
+
+/// A search result with its distance.
+///
+/// `ScoredPoint`s are ordered by distance, closest first, with ties broken by ID.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoredPoint {
+    /// ID of the point.
+    pub id: i64,
+    /// Distance between the point and the query vector.
+    pub distance: f32,
+}
+
+impl Eq for ScoredPoint {}
+
+impl PartialOrd for ScoredPoint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScoredPoint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+impl KnnSearchOutput {
+    /// Returns the results as [`ScoredPoint`]s.
+    pub fn scored_points(&self) -> impl Iterator<Item = ScoredPoint> + '_ {
+        self.ids
+            .iter()
+            .zip(&self.distances)
+            .map(|(&id, &distance)| ScoredPoint { id, distance })
+    }
+}
+
+impl FromIterator<ScoredPoint> for KnnSearchOutput {
+    fn from_iter<T: IntoIterator<Item = ScoredPoint>>(iter: T) -> Self {
+        let (ids, distances) = iter
+            .into_iter()
+            .map(|point| (point.id, point.distance))
+            .unzip();
+        KnnSearchOutput { ids, distances }
+    }
+}
+
+/// Reusable buffers of a k-way merge of search outputs sorted closest first, as vsag returns
+/// them, see [`TopkMerger::merge`].
+///
+/// Unlike [`merge_topk`], the merge only visits the `k` closest results plus duplicates, and
+/// doesn't allocate once the buffers have grown to `k` results, so keep one around per
+/// searching thread when fanning out to many shards with a big `k`.
+#[derive(Default)]
+pub struct TopkMerger {
+    /// Heads of the outputs as (key, output, position), closest key on top.
+    heap: BinaryHeap<Reverse<(ScoredPoint, usize, usize)>>,
+    seen: IdMap<()>,
+    merged: Vec<ScoredPoint>,
+}
+
+impl TopkMerger {
+    /// Merges `outputs`, each sorted closest first according to `order`, into the overall `k`
+    /// closest results, see [`merge_topk`].
+    ///
+    /// Results of different outputs with the same score are taken by ID, smallest first, like
+    /// [`ScoredPoint`]s are ordered. Results of a single output keep their order.
+    pub fn merge(
+        &mut self,
+        outputs: &[KnnSearchOutput],
+        k: usize,
+        order: ScoreOrder,
+    ) -> &[ScoredPoint] {
+        debug_assert!(outputs.iter().all(|output| is_sorted(output, order)));
+        // a min-heap of points ordered by distance, negated if higher is closer.
+        let key = |output: &KnnSearchOutput, pos: usize| ScoredPoint {
+            id: output.ids[pos],
+            distance: match order {
+                ScoreOrder::LowerIsCloser => output.distances[pos],
+                ScoreOrder::HigherIsCloser => -output.distances[pos],
+            },
+        };
+
+        self.heap.clear();
+        self.seen.clear();
+        self.merged.clear();
+        for (i, output) in outputs.iter().enumerate() {
+            if !output.ids.is_empty() && !output.distances.is_empty() {
+                self.heap.push(Reverse((key(output, 0), i, 0)));
+            }
+        }
+        while self.merged.len() < k {
+            let Some(Reverse((_, i, pos))) = self.heap.pop() else {
+                break;
+            };
+            let output = &outputs[i];
+            let id = output.ids[pos];
+            if self.seen.insert(id, ()).is_none() {
+                self.merged.push(ScoredPoint {
+                    id,
+                    distance: output.distances[pos],
+                });
+            }
+            if pos + 1 < output.ids.len().min(output.distances.len()) {
+                self.heap.push(Reverse((key(output, pos + 1), i, pos + 1)));
+            }
+        }
+        &self.merged
+    }
+}
+
+fn is_sorted(output: &KnnSearchOutput, order: ScoreOrder) -> bool {
+    output.distances.windows(2).all(|pair| match order {
+        ScoreOrder::LowerIsCloser => pair[0].total_cmp(&pair[1]).is_le(),
+        ScoreOrder::HigherIsCloser => pair[0].total_cmp(&pair[1]).is_ge(),
+    })
+}
+
+/// Merges the outputs of several vsag searches into the overall `k` closest results.
+///
+/// An ID found by several outputs (e.g. replicated shards) is kept once, with its closest
+/// distance.
+pub fn merge_topk(outputs: &[KnnSearchOutput], k: usize) -> KnnSearchOutput {
+    merge_topk_by(outputs, k, ScoreOrder::LowerIsCloser)
+}
+
+/// Same as [`merge_topk`], for scores ordered by `order`.
+///
+/// Outputs sorted by `order` are merged with a [`TopkMerger`], others are concatenated and
+/// sorted.
+pub fn merge_topk_by(outputs: &[KnnSearchOutput], k: usize, order: ScoreOrder) -> KnnSearchOutput {
+    if outputs.iter().all(|output| is_sorted(output, order)) {
+        return TopkMerger::default()
+            .merge(outputs, k, order)
+            .iter()
+            .copied()
+            .collect();
+    }
+
+    let mut points: Vec<ScoredPoint> = outputs
+        .iter()
+        .flat_map(|output| output.scored_points())
+        .collect();
+    match order {
+        ScoreOrder::LowerIsCloser => points.sort_unstable(),
+        ScoreOrder::HigherIsCloser => {
+            points.sort_unstable_by(|a, b| b.distance.total_cmp(&a.distance).then(a.id.cmp(&b.id)))
+        }
+    }
+
+    let mut seen = HashSet::with_capacity(k);
+    points
+        .into_iter()
+        .filter(|point| seen.insert(point.id))
+        .take(k)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_topk() {
+        let outputs = [
+            KnnSearchOutput {
+                ids: vec![1, 2, 3],
+                distances: vec![0.1, 0.4, 0.9],
+            },
+            KnnSearchOutput {
+                ids: vec![4, 2, 5],
+                distances: vec![0.2, 0.3, 0.5],
+            },
+        ];
+
+        let merged = merge_topk(&outputs, 3);
+        assert_eq!(merged.ids, vec![1, 4, 2]);
+        assert_eq!(merged.distances, vec![0.1, 0.2, 0.3]);
+
+        let merged = merge_topk_by(&outputs, 3, ScoreOrder::HigherIsCloser);
+        assert_eq!(merged.ids, vec![3, 5, 2]);
+        assert_eq!(merged.distances, vec![0.9, 0.5, 0.4]);
+
+        assert!(merge_topk(&outputs, 0).ids.is_empty());
+        assert_eq!(merge_topk(&outputs, 10).ids.len(), 5);
+
+        // unsorted outputs are merged the same way.
+        let reversed: Vec<KnnSearchOutput> = outputs
+            .iter()
+            .map(|output| {
+                let mut points: Vec<ScoredPoint> = output.scored_points().collect();
+                points.reverse();
+                points.into_iter().collect()
+            })
+            .collect();
+        assert_eq!(merge_topk(&reversed, 3).ids, vec![1, 4, 2]);
+
+        let mut merger = TopkMerger::default();
+        let merged = merger.merge(&outputs, 4, ScoreOrder::LowerIsCloser);
+        let ids: Vec<i64> = merged.iter().map(|point| point.id).collect();
+        assert_eq!(ids, vec![1, 4, 2, 5]);
+    }
+
+    #[test]
+    fn test_merge_ties() {
+        let outputs = [
+            KnnSearchOutput {
+                ids: vec![7, 3],
+                distances: vec![0.5, 0.5],
+            },
+            KnnSearchOutput {
+                ids: vec![5, 1],
+                distances: vec![0.5, 0.5],
+            },
+        ];
+
+        let mut merger = TopkMerger::default();
+        for order in [ScoreOrder::LowerIsCloser, ScoreOrder::HigherIsCloser] {
+            let merged = merger.merge(&outputs, 3, order);
+            let ids: Vec<i64> = merged.iter().map(|point| point.id).collect();
+            assert_eq!(ids, vec![5, 1, 7]);
+        }
+    }
+}
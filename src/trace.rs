@@ -0,0 +1,158 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Instrumentation of calls into vsag: spans with the `tracing` feature, and metrics recorded
+//! through the [`metrics`](https://docs.rs/metrics) facade with the `metrics` feature.
+//!
+//! Without either feature, [`traced!`] evaluates the operation directly and compiles to nothing
+//! else.
+//!
+//! Metrics, all labeled with `operation` (`build`, `knn_search`, `dump` or `load`):
+//! - `vsag_operations_total`: counter of operations.
+//! - `vsag_operation_duration_seconds`: histogram of the duration of operations.
+//! - `vsag_operation_results`: histogram of the number of results of searches.
+//! - `vsag_operation_errors_total`: counter of failed operations, also labeled with
+//!   `error_type`.
+//! - `vsag_indexes`: gauge of the live indexes, unlabeled.
+//! - `vsag_ingest_queue_depth`: gauge of the points buffered by
+//!   [`IngestBuffer`](crate::ingest::IngestBuffer)s, unlabeled.
+
+/// Runs `$op` in a debug span named `$name` with the given fields, and records the duration of
+/// the operation in `duration_us`, then either the number of results computed by the optional
+/// `$num_results` in `num_results`, or the error in `error`.
+///
+/// The same values are recorded as metrics labeled with `$name`.
+macro_rules! traced {
+    ($name:literal, $num_results:expr, $op:expr $(, $($fields:tt)+)?) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            target: "vsag",
+            $name,
+            $($($fields)+,)?
+            duration_us = tracing::field::Empty,
+            num_results = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        #[cfg(not(feature = "tracing"))]
+        let span = ();
+        #[cfg(any(feature = "tracing", feature = "metrics"))]
+        let result = $crate::trace::observe($name, span, $num_results, || $op);
+        #[cfg(not(any(feature = "tracing", feature = "metrics")))]
+        let result = {
+            let _ = span;
+            $op
+        };
+        result
+    }};
+}
+
+pub(crate) use traced;
+
+#[cfg(feature = "tracing")]
+pub(crate) type Span = tracing::Span;
+#[cfg(all(feature = "metrics", not(feature = "tracing")))]
+pub(crate) type Span = ();
+
+#[cfg(any(feature = "tracing", feature = "metrics"))]
+pub(crate) fn observe<T>(
+    name: &'static str,
+    span: Span,
+    num_results: Option<fn(&T) -> usize>,
+    op: impl FnOnce() -> crate::error::Result<T>,
+) -> crate::error::Result<T> {
+    #[cfg(feature = "tracing")]
+    let _entered = span.enter();
+    let start = std::time::Instant::now();
+    let result = op();
+    let elapsed = start.elapsed();
+    let num_results = result.as_ref().ok().and_then(|v| num_results.map(|f| f(v)));
+
+    #[cfg(feature = "tracing")]
+    {
+        span.record("duration_us", elapsed.as_micros() as u64);
+        match &result {
+            Ok(_) => span.record("num_results", num_results),
+            Err(err) => span.record("error", tracing::field::debug(err)),
+        };
+    }
+    #[cfg(not(feature = "tracing"))]
+    let () = span;
+
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!("vsag_operations_total", "operation" => name).increment(1);
+        metrics::histogram!("vsag_operation_duration_seconds", "operation" => name)
+            .record(elapsed.as_secs_f64());
+        if let Some(num_results) = num_results {
+            metrics::histogram!("vsag_operation_results", "operation" => name)
+                .record(num_results as f64);
+        }
+        if let Err(err) = &result {
+            metrics::counter!(
+                "vsag_operation_errors_total",
+                "operation" => name,
+                "error_type" => format!("{:?}", err.error_type),
+            )
+            .increment(1);
+        }
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (name, elapsed);
+
+    result
+}
+
+/// Tracks a live index in the `vsag_indexes` gauge, `delta` being 1 on creation and -1 on drop.
+pub(crate) fn track_index(delta: f64) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("vsag_indexes").increment(delta);
+    #[cfg(not(feature = "metrics"))]
+    let _ = delta;
+}
+
+/// Tracks points buffered by an [`IngestBuffer`](crate::ingest::IngestBuffer) in the
+/// `vsag_ingest_queue_depth` gauge, `delta` being positive when buffering and negative when
+/// flushing.
+pub(crate) fn track_queued(delta: f64) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("vsag_ingest_queue_depth").increment(delta);
+    #[cfg(not(feature = "metrics"))]
+    let _ = delta;
+}
+
+/// Returns the `ef_search` of search parameters in JSON format, whatever the index type.
+#[cfg(feature = "tracing")]
+pub(crate) fn ef_search(search_params: &str) -> Option<u64> {
+    let rest = &search_params[search_params.find("\"ef_search\"")? + "\"ef_search\"".len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ef_search() {
+        assert_eq!(ef_search(r#"{"hnsw": {"ef_search" : 100}}"#), Some(100));
+        assert_eq!(
+            ef_search(r#"{"diskann": {"ef_search": 20, "io_limit": 200}}"#),
+            Some(20)
+        );
+        assert_eq!(ef_search(r#"{"hnsw": {}}"#), None);
+    }
+}
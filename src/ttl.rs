@@ -0,0 +1,149 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Background removal of expired points of a [`Collection`], see
+//! [`Collection::upsert_with_ttl`].
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::collection::Collection;
+use crate::error::{Error, ErrorType, Result};
+
+/// Periodically deletes the expired points of a collection in a background thread, until
+/// dropped.
+///
+/// Deletions are left uncommitted like any other, so they only leave the index at the next
+/// [`Collection::commit`]; searches skip expired points in the meantime anyway.
+#[derive(Debug)]
+pub struct Sweeper {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Sweeper {
+    /// Starts sweeping `collection` every `interval`.
+    pub fn spawn(collection: Arc<Mutex<Collection>>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    let mut collection = match collection.lock() {
+                        Ok(collection) => collection,
+                        Err(poisoned) => poisoned.into_inner(),
+                    };
+                    collection.sweep_expired();
+                }
+                _ => return,
+            }
+        });
+        Sweeper {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// Stops sweeping, like dropping it, but fails if the sweeping thread panicked.
+    pub fn close(mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        // dropping the sender wakes the thread up.
+        self.stop.take();
+        match self.handle.take().map(JoinHandle::join) {
+            Some(Err(_)) => Err(Error::new(
+                ErrorType::InternalError,
+                "expired points sweeper panicked",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::collection::CollectionConfig;
+    use crate::IndexOptions;
+
+    #[test]
+    fn test_ttl() {
+        let search_params = r#"{"hnsw": {"ef_search": 100}}"#;
+        let mut collection = Collection::new(CollectionConfig {
+            index_type: "hnsw".to_string(),
+            params: r#"{
+                "dtype": "float32",
+                "metric_type": "l2",
+                "dim": 2,
+                "hnsw": {
+                    "max_degree": 16,
+                    "ef_construction": 100
+                }
+            }"#
+            .to_string(),
+            dim: 2,
+            options: IndexOptions::default(),
+        });
+        let hour = Duration::from_secs(3600);
+        collection.upsert(1, vec![0.0, 0.0], vec![]).unwrap();
+        collection
+            .upsert_with_ttl(2, vec![0.1, 0.0], vec![], hour)
+            .unwrap();
+        collection
+            .upsert_with_ttl(3, vec![0.2, 0.0], vec![], hour)
+            .unwrap();
+        collection.commit().unwrap();
+
+        let dir = tempdir::TempDir::new("test_ttl").unwrap();
+        collection.save(dir.path()).unwrap();
+        let reopened = Collection::open(dir.path()).unwrap();
+        assert!(reopened.get(3).unwrap().expires_at.is_some());
+        assert!(reopened.get(1).unwrap().expires_at.is_none());
+
+        let ids = |collection: &Collection| -> Vec<i64> {
+            let hits = collection.search(&[0.0, 0.0], 3, search_params).unwrap();
+            hits.iter().map(|hit| hit.id).collect()
+        };
+        assert_eq!(ids(&collection), vec![1, 2, 3]);
+        // a zero TTL has expired by the time it's checked.
+        collection
+            .upsert_with_ttl(2, vec![0.1, 0.0], vec![], Duration::ZERO)
+            .unwrap();
+        // filtered out before being swept.
+        assert_eq!(ids(&collection), vec![1, 3]);
+        assert_eq!(collection.len(), 3);
+
+        let collection = Arc::new(Mutex::new(collection));
+        let sweeper = Sweeper::spawn(collection.clone(), Duration::from_millis(10));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while collection.lock().unwrap().len() > 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        sweeper.close().unwrap();
+        let collection = collection.lock().unwrap();
+        assert_eq!(collection.len(), 2);
+        assert!(collection.get(2).is_none());
+    }
+}